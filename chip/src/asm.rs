@@ -0,0 +1,655 @@
+mod encode;
+mod lexer;
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+use std::rc::Rc;
+
+use encode::{Arg, Expr, Operand, Term};
+use lexer::{Spanned, Token};
+
+use crate::DEFAULT_ROM_START;
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+const MAX_CONSTANT_DEPTH: usize = 32;
+const ADDRESS_SPACE: i64 = 0x10000;
+
+// Column where the problem is and what it is, the line is added by the caller
+type Fault = (usize, AsmErrorKind);
+
+#[derive(Debug)]
+pub enum AsmErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    InvalidNumber(String),
+    UnexpectedToken(String),
+    ExpectedOperand,
+    UnknownMnemonic(String),
+    InvalidOperands(String),
+    UndefinedSymbol(String),
+    DuplicateSymbol(String),
+    RecursiveConstant(String),
+    OutOfRange { value: i64, max: i64 },
+    OrgBackwards { org: i64, address: i64 },
+    ProgramTooLarge,
+    IncludeTooDeep,
+    UnexpectedEnd,
+    DivisionByZero,
+    ExpectedRegister(String),
+    UnmatchedBlock(String),
+    RecursiveMacro(String),
+    Io(std::io::Error),
+}
+
+impl Display for AsmErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedCharacter(c) => write!(f, "Unexpected character '{c}'"),
+            Self::UnterminatedString => write!(f, "Unterminated string"),
+            Self::InvalidNumber(text) => write!(f, "Invalid number {text}"),
+            Self::UnexpectedToken(token) => write!(f, "Unexpected '{token}'"),
+            Self::ExpectedOperand => write!(f, "Expected an operand"),
+            Self::UnknownMnemonic(name) => write!(f, "Unknown mnemonic {name}"),
+            Self::InvalidOperands(name) => write!(f, "Invalid operands for {name}"),
+            Self::UndefinedSymbol(name) => write!(f, "Undefined symbol {name}"),
+            Self::DuplicateSymbol(name) => write!(f, "Symbol {name} is already defined"),
+            Self::RecursiveConstant(name) => write!(f, "Constant {name} depends on itself"),
+            Self::OutOfRange { value, max } => {
+                write!(f, "Value {value:#X} out of range, the maximum is {max:#X}")
+            }
+            Self::OrgBackwards { org, address } => {
+                write!(
+                    f,
+                    "Org {org:#05X} is behind the current address {address:#05X}"
+                )
+            }
+            Self::ProgramTooLarge => write!(f, "Program does not fit in memory"),
+            Self::IncludeTooDeep => write!(f, "Includes nested too deep"),
+            Self::UnexpectedEnd => write!(f, "Unexpected end of source"),
+            Self::DivisionByZero => write!(f, "Division by zero"),
+            Self::ExpectedRegister(token) => write!(f, "Expected a register, found '{token}'"),
+            Self::UnmatchedBlock(message) => write!(f, "{message}"),
+            Self::RecursiveMacro(name) => write!(f, "Macro {name} expands forever"),
+            Self::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.kind
+        )
+    }
+}
+
+impl std::error::Error for AsmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            AsmErrorKind::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// File and line a statement comes from
+#[derive(Clone)]
+struct Place {
+    file: Rc<str>,
+    line: usize,
+}
+
+impl Place {
+    fn error(&self, (column, kind): Fault) -> AsmError {
+        AsmError {
+            file: self.file.to_string(),
+            line: self.line,
+            column,
+            kind,
+        }
+    }
+}
+
+enum Symbol {
+    Label(i64),
+    Constant(Expr, Place),
+}
+
+enum Kind {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<Operand>,
+    },
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+struct Statement {
+    place: Place,
+    column: usize,
+    address: i64,
+    kind: Kind,
+}
+
+// NOTE: The first pass collects the symbols and the address of every
+// statement, the second one evaluates the operands and encodes them
+struct Assembler {
+    symbols: HashMap<String, Symbol>,
+    statements: Vec<Statement>,
+    address: i64,
+    depth: usize,
+}
+
+// NOTE: Includes are relative to the working directory
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler::new();
+    assembler.source(source, &Rc::from("<source>"))?;
+    assembler.emit()
+}
+
+// NOTE: Includes are relative to the file that includes them
+pub fn assemble_file(path: impl AsRef<Path>) -> Result<Vec<u8>, AsmError> {
+    let path = path.as_ref();
+    let file: Rc<str> = Rc::from(path.to_string_lossy().as_ref());
+
+    let source = std::fs::read_to_string(path).map_err(|error| AsmError {
+        file: file.to_string(),
+        line: 0,
+        column: 0,
+        kind: AsmErrorKind::Io(error),
+    })?;
+
+    let mut assembler = Assembler::new();
+    assembler.source(&source, &file)?;
+    assembler.emit()
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Self {
+            symbols: HashMap::new(),
+            statements: Vec::new(),
+            address: i64::from(DEFAULT_ROM_START),
+            depth: 0,
+        }
+    }
+
+    fn source(&mut self, source: &str, file: &Rc<str>) -> Result<(), AsmError> {
+        for (index, text) in source.lines().enumerate() {
+            let place = Place {
+                file: Rc::clone(file),
+                line: index + 1,
+            };
+            let tokens = lexer::tokenize(text).map_err(|fault| place.error(fault))?;
+
+            self.line(&tokens, &place)?;
+        }
+
+        Ok(())
+    }
+
+    fn line(&mut self, tokens: &[Spanned], place: &Place) -> Result<(), AsmError> {
+        let fail = |fault| place.error(fault);
+        let mut tokens = tokens;
+
+        if let [Spanned {
+            token: Token::Ident(name),
+            column,
+        }, Spanned {
+            token: Token::Colon,
+            ..
+        }, rest @ ..] = tokens
+        {
+            self.define(name, Symbol::Label(self.address), *column)
+                .map_err(fail)?;
+            tokens = rest;
+        }
+
+        let [first, rest @ ..] = tokens else {
+            return Ok(());
+        };
+        let Token::Ident(word) = &first.token else {
+            return Err(fail(unexpected(first)));
+        };
+
+        if let [Spanned {
+            token: Token::Ident(equ),
+            ..
+        }, value @ ..] = rest
+        {
+            if equ.eq_ignore_ascii_case("EQU") {
+                let expr = expr(value, first).map_err(fail)?;
+                let symbol = Symbol::Constant(expr, place.clone());
+                return self.define(word, symbol, first.column).map_err(fail);
+            }
+        }
+
+        let kind = match word.to_ascii_uppercase().as_str() {
+            "ORG" => return self.org(rest, place, first),
+            "INCLUDE" => return self.include(rest, place, first),
+            "DB" => Kind::Bytes(exprs(rest, first).map_err(fail)?),
+            "DW" => Kind::Words(exprs(rest, first).map_err(fail)?),
+            _ => Kind::Instruction {
+                mnemonic: word.clone(),
+                operands: split(rest)
+                    .map_err(fail)?
+                    .into_iter()
+                    .map(encode::parse_operand)
+                    .collect::<Result<_, _>>()
+                    .map_err(fail)?,
+            },
+        };
+
+        let size = match &kind {
+            Kind::Instruction { operands, .. } => i64::from(encode::size(operands)),
+            Kind::Bytes(values) => values.len() as i64,
+            Kind::Words(values) => 2 * values.len() as i64,
+        };
+
+        if self.address + size > ADDRESS_SPACE {
+            return Err(fail((first.column, AsmErrorKind::ProgramTooLarge)));
+        }
+
+        self.statements.push(Statement {
+            place: place.clone(),
+            column: first.column,
+            address: self.address,
+            kind,
+        });
+        self.address += size;
+
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, symbol: Symbol, column: usize) -> Result<(), Fault> {
+        if self.symbols.contains_key(name) {
+            return Err((column, AsmErrorKind::DuplicateSymbol(name.to_owned())));
+        }
+
+        self.symbols.insert(name.to_owned(), symbol);
+
+        Ok(())
+    }
+
+    // NOTE: Before any code the org sets where the rom is loaded, after it
+    // the gap is filled with zeros
+    fn org(&mut self, rest: &[Spanned], place: &Place, first: &Spanned) -> Result<(), AsmError> {
+        let expr = expr(rest, first).map_err(|fault| place.error(fault))?;
+        let org = self.eval(&expr, place, 0)?;
+
+        let fault = if !(0..ADDRESS_SPACE).contains(&org) {
+            AsmErrorKind::OutOfRange {
+                value: org,
+                max: ADDRESS_SPACE - 1,
+            }
+        } else if !self.statements.is_empty() && org < self.address {
+            AsmErrorKind::OrgBackwards {
+                org,
+                address: self.address,
+            }
+        } else {
+            self.address = org;
+            return Ok(());
+        };
+
+        Err(place.error((expr.column, fault)))
+    }
+
+    fn include(
+        &mut self,
+        rest: &[Spanned],
+        place: &Place,
+        first: &Spanned,
+    ) -> Result<(), AsmError> {
+        let [Spanned {
+            token: Token::Str(path),
+            column,
+        }] = rest
+        else {
+            return Err(place.error(match rest {
+                [] => (first.column, AsmErrorKind::ExpectedOperand),
+                [spanned, ..] => unexpected(spanned),
+            }));
+        };
+
+        if self.depth == MAX_INCLUDE_DEPTH {
+            return Err(place.error((*column, AsmErrorKind::IncludeTooDeep)));
+        }
+
+        let path = Path::new(place.file.as_ref())
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(path);
+        let source = std::fs::read_to_string(&path)
+            .map_err(|error| place.error((*column, AsmErrorKind::Io(error))))?;
+
+        self.depth += 1;
+        let result = self.source(&source, &Rc::from(path.to_string_lossy().as_ref()));
+        self.depth -= 1;
+
+        result
+    }
+
+    // NOTE: Constants are evaluated where they are used, so they may refer to
+    // labels defined after them
+    fn eval(&self, expr: &Expr, place: &Place, depth: usize) -> Result<i64, AsmError> {
+        let mut total: i64 = 0;
+
+        for (negative, term, column) in &expr.terms {
+            let value = match term {
+                Term::Number(value) => *value,
+                Term::Symbol(name) => match self.symbols.get(name) {
+                    Some(Symbol::Label(address)) => *address,
+                    Some(Symbol::Constant(_, _)) if depth == MAX_CONSTANT_DEPTH => {
+                        let kind = AsmErrorKind::RecursiveConstant(name.clone());
+                        return Err(place.error((*column, kind)));
+                    }
+                    Some(Symbol::Constant(expr, defined)) => self.eval(expr, defined, depth + 1)?,
+                    None => {
+                        let kind = AsmErrorKind::UndefinedSymbol(name.clone());
+                        return Err(place.error((*column, kind)));
+                    }
+                },
+            };
+
+            total = if *negative {
+                total.saturating_sub(value)
+            } else {
+                total.saturating_add(value)
+            };
+        }
+
+        Ok(total)
+    }
+
+    fn arg(&self, operand: &Operand, place: &Place) -> Result<Arg, AsmError> {
+        let arg = match operand {
+            Operand::Register(x) => Arg::Register(*x),
+            Operand::Range(x, y) => Arg::Range(*x, *y),
+            Operand::I => Arg::I,
+            Operand::IndirectI => Arg::IndirectI,
+            Operand::Dt => Arg::Dt,
+            Operand::St => Arg::St,
+            Operand::K => Arg::K,
+            Operand::F => Arg::F,
+            Operand::Hf => Arg::Hf,
+            Operand::B => Arg::B,
+            Operand::R => Arg::R,
+            Operand::Long(expr) => Arg::Long(self.eval(expr, place, 0)?, expr.column),
+            Operand::Value(expr) => Arg::Value(self.eval(expr, place, 0)?, expr.column),
+        };
+
+        Ok(arg)
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    fn emit(&self) -> Result<Vec<u8>, AsmError> {
+        let origin = self
+            .statements
+            .first()
+            .map_or(0, |statement| statement.address);
+        let mut rom = Vec::new();
+
+        for statement in &self.statements {
+            let place = &statement.place;
+            let fail = |fault| place.error(fault);
+
+            // NOTE: Addresses only grow, so the gap left by an org is padded
+            rom.resize((statement.address - origin) as usize, 0);
+
+            match &statement.kind {
+                Kind::Instruction { mnemonic, operands } => {
+                    let args = operands
+                        .iter()
+                        .map(|operand| self.arg(operand, place))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let bytes = encode::encode(mnemonic, &args, statement.column).map_err(fail)?;
+                    rom.extend(bytes);
+                }
+                Kind::Bytes(values) => {
+                    for expr in values {
+                        let value = self.eval(expr, place, 0)?;
+                        rom.push(encode::byte(value, expr.column).map_err(fail)?);
+                    }
+                }
+                Kind::Words(values) => {
+                    for expr in values {
+                        let value = self.eval(expr, place, 0)?;
+                        rom.extend(
+                            encode::word(value, expr.column)
+                                .map_err(fail)?
+                                .to_be_bytes(),
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(rom)
+    }
+}
+
+// NOTE: Operands are separated by commas, none of them can be empty
+fn split(tokens: &[Spanned]) -> Result<Vec<&[Spanned]>, Fault> {
+    let mut operands = Vec::new();
+    let mut start = 0;
+
+    for (i, spanned) in tokens.iter().enumerate() {
+        if spanned.token == Token::Comma {
+            if i == start {
+                return Err((spanned.column, AsmErrorKind::ExpectedOperand));
+            }
+            operands.push(&tokens[start..i]);
+            start = i + 1;
+        }
+    }
+
+    match tokens.last() {
+        Some(last) if start == tokens.len() => Err((last.column, AsmErrorKind::ExpectedOperand)),
+        Some(_) => {
+            operands.push(&tokens[start..]);
+            Ok(operands)
+        }
+        None => Ok(operands),
+    }
+}
+
+fn expr(tokens: &[Spanned], first: &Spanned) -> Result<Expr, Fault> {
+    if tokens.is_empty() {
+        return Err((first.column, AsmErrorKind::ExpectedOperand));
+    }
+
+    encode::parse_expr(tokens)
+}
+
+fn exprs(tokens: &[Spanned], first: &Spanned) -> Result<Vec<Expr>, Fault> {
+    if tokens.is_empty() {
+        return Err((first.column, AsmErrorKind::ExpectedOperand));
+    }
+
+    split(tokens)?.into_iter().map(encode::parse_expr).collect()
+}
+
+fn unexpected(spanned: &Spanned) -> Fault {
+    (
+        spanned.column,
+        AsmErrorKind::UnexpectedToken(spanned.token.to_string()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::{disassemble, disassemble_recursive};
+    use crate::Opcode;
+
+    fn assemble_ok(source: &str) -> Vec<u8> {
+        match assemble(source) {
+            Ok(rom) => rom,
+            Err(error) => panic!("{error}"),
+        }
+    }
+
+    fn listing(lines: &[crate::disasm::Line]) -> String {
+        lines
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            start:  CLS             ; clear the screen
+                    ld va, 0x42
+                    DRW V1, V2, 5
+                    JP start
+        ";
+
+        assert_eq!(
+            assemble_ok(source),
+            [0x00, 0xE0, 0x6A, 0x42, 0xD1, 0x25, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_forward_labels_and_constants() {
+        let source = "
+            SPEED equ 3
+            TARGET EQU end + 2
+                CALL end
+                LD V0, SPEED
+                LD I, TARGET
+            end: RET
+        ";
+
+        assert_eq!(
+            assemble_ok(source),
+            [0x22, 0x06, 0x60, 0x03, 0xA2, 0x08, 0x00, 0xEE]
+        );
+    }
+
+    #[test]
+    fn test_data_and_org() {
+        let source = "
+            ORG 0x300
+                DB 1, 0b10, #3
+                DW 0x1234
+            ORG 0x308
+                DB -1
+        ";
+
+        assert_eq!(assemble_ok(source), [1, 2, 3, 0x12, 0x34, 0, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join(format!("chippy-asm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("temporary directory");
+        std::fs::write(dir.join("sprites.asm"), "sprite: DB 0xF0\n").expect("included file");
+        std::fs::write(
+            dir.join("main.asm"),
+            "LD I, sprite\nINCLUDE \"sprites.asm\"\n",
+        )
+        .expect("main file");
+
+        let rom = assemble_file(dir.join("main.asm"));
+        std::fs::remove_dir_all(&dir).expect("temporary directory removed");
+
+        assert_eq!(rom.ok(), Some(vec![0xA2, 0x02, 0xF0]));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| assemble(source).expect_err("invalid source");
+
+        let e = error("CLS\n  LD V0, 0x100");
+        assert_eq!((e.line, e.column), (2, 10));
+        assert!(matches!(
+            e.kind,
+            AsmErrorKind::OutOfRange { value: 0x100, .. }
+        ));
+
+        let e = error("  JP nowhere");
+        assert_eq!((e.line, e.column), (1, 6));
+        assert!(matches!(e.kind, AsmErrorKind::UndefinedSymbol(_)));
+
+        let e = error("a: CLS\na: CLS");
+        assert_eq!((e.line, e.column), (2, 1));
+        assert!(matches!(e.kind, AsmErrorKind::DuplicateSymbol(_)));
+
+        let e = error("  MOV V0, V1");
+        assert!(matches!(e.kind, AsmErrorKind::UnknownMnemonic(_)));
+
+        let e = error("  SKP 3");
+        assert!(matches!(e.kind, AsmErrorKind::InvalidOperands(_)));
+
+        let e = error("  DB 1,,2");
+        assert_eq!(e.column, 8);
+
+        let e = error("A EQU B\nB EQU A\nLD V0, A");
+        assert!(matches!(e.kind, AsmErrorKind::RecursiveConstant(_)));
+
+        let e = error("CLS\nORG 0x100");
+        assert!(matches!(e.kind, AsmErrorKind::OrgBackwards { .. }));
+
+        assert_eq!(
+            e.to_string(),
+            "<source>:2:5: Org 0x100 is behind the current address 0x202"
+        );
+    }
+
+    #[test]
+    fn test_every_opcode_round_trip() {
+        for value in 0..=u16::MAX {
+            let Ok(opcode) = Opcode::decode(value) else {
+                continue;
+            };
+            if opcode == Opcode::LdILong {
+                continue;
+            }
+
+            assert_eq!(
+                assemble(&opcode.to_string()).ok(),
+                Some(value.to_be_bytes().to_vec()),
+                "{opcode}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_listing_round_trip() {
+        let rom = [
+            0x22, 0x0C, // CALL 0x20C
+            0xA2, 0x14, // LD I, 0x214
+            0xD0, 0x13, // DRW V0, V1, 3
+            0xF0, 0x00, 0x02, 0x14, // LD I, LONG 0x214
+            0x12, 0x0A, // JP 0x20A
+            0x3A, 0x00, // SE VA, 0x00
+            0xB2, 0x00, // JP V0, 0x200
+            0x00, 0xEE, // RET
+            0x50, 0x01, // data
+            0x3C, 0x42, 0xFF, // sprite
+            0x7E, // data
+        ];
+
+        for lines in [
+            disassemble(&rom, DEFAULT_ROM_START),
+            disassemble_recursive(&rom, DEFAULT_ROM_START),
+        ] {
+            assert_eq!(assemble_ok(&listing(&lines)), rom);
+        }
+    }
+}
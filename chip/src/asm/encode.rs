@@ -0,0 +1,350 @@
+use super::lexer::{Spanned, Token};
+use super::{AsmErrorKind, Fault};
+use crate::Opcode;
+
+#[derive(Clone, Debug)]
+pub enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+// Sum of terms, each one with its sign and column
+#[derive(Clone, Debug)]
+pub struct Expr {
+    pub terms: Vec<(bool, Term, usize)>,
+    pub column: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum Operand {
+    Register(u8),
+    Range(u8, u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Expr),
+    Value(Expr),
+}
+
+// Operand with its expression already evaluated
+#[derive(Clone, Copy, Debug)]
+pub enum Arg {
+    Register(u8),
+    Range(u8, u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(i64, usize),
+    Value(i64, usize),
+}
+
+pub fn parse_expr(tokens: &[Spanned]) -> Result<Expr, Fault> {
+    let Some(first) = tokens.first() else {
+        return Err((0, AsmErrorKind::ExpectedOperand));
+    };
+
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut expect_term = true;
+
+    for spanned in tokens {
+        match (&spanned.token, expect_term) {
+            (Token::Minus, true) => negative = !negative,
+            (Token::Plus, true) => (),
+            (Token::Number(value), true) => {
+                terms.push((negative, Term::Number(*value), spanned.column));
+                expect_term = false;
+            }
+            (Token::Ident(name), true) => {
+                terms.push((negative, Term::Symbol(name.clone()), spanned.column));
+                expect_term = false;
+            }
+            (Token::Plus | Token::Minus, false) => {
+                negative = spanned.token == Token::Minus;
+                expect_term = true;
+            }
+            _ => return Err(unexpected(spanned)),
+        }
+    }
+
+    if expect_term {
+        let last = tokens.last().unwrap_or(first);
+        return Err((last.column, AsmErrorKind::ExpectedOperand));
+    }
+
+    Ok(Expr {
+        terms,
+        column: first.column,
+    })
+}
+
+pub fn parse_operand(tokens: &[Spanned]) -> Result<Operand, Fault> {
+    let idents: Vec<Option<String>> = tokens
+        .iter()
+        .map(|spanned| match &spanned.token {
+            Token::Ident(name) => Some(name.to_ascii_uppercase()),
+            _ => None,
+        })
+        .collect();
+
+    if let ([_, minus, _], [Some(x), None, Some(y)]) = (tokens, idents.as_slice()) {
+        if let (Token::Minus, Some(x), Some(y)) = (&minus.token, register(x), register(y)) {
+            return Ok(Operand::Range(x, y));
+        }
+    }
+
+    let operand = match (tokens, idents.as_slice()) {
+        ([_], [Some(name)]) => match name.as_str() {
+            "I" => Operand::I,
+            "DT" => Operand::Dt,
+            "ST" => Operand::St,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "HF" => Operand::Hf,
+            "B" => Operand::B,
+            "R" => Operand::R,
+            name => match register(name) {
+                Some(x) => Operand::Register(x),
+                None => Operand::Value(parse_expr(tokens)?),
+            },
+        },
+        (
+            [Spanned {
+                token: Token::LBracket,
+                ..
+            }, _, Spanned {
+                token: Token::RBracket,
+                ..
+            }],
+            [None, Some(name), None],
+        ) if name == "I" => Operand::IndirectI,
+        ([_, rest @ ..], [Some(name), ..]) if name == "LONG" => Operand::Long(parse_expr(rest)?),
+        _ => Operand::Value(parse_expr(tokens)?),
+    };
+
+    Ok(operand)
+}
+
+fn register(name: &str) -> Option<u8> {
+    let digit = name.strip_prefix('V')?;
+    if digit.len() != 1 {
+        return None;
+    }
+
+    u8::from_str_radix(digit, 16).ok()
+}
+
+fn unexpected(spanned: &Spanned) -> Fault {
+    (
+        spanned.column,
+        AsmErrorKind::UnexpectedToken(spanned.token.to_string()),
+    )
+}
+
+fn in_range(value: i64, column: usize, min: i64, max: i64) -> Result<i64, Fault> {
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err((column, AsmErrorKind::OutOfRange { value, max }))
+    }
+}
+
+#[allow(clippy::cast_sign_loss)]
+fn addr(value: i64, column: usize) -> Result<u16, Fault> {
+    Ok(in_range(value, column, 0, 0xFFF)? as u16)
+}
+
+// NOTE: Negative bytes are taken as two's complement
+#[allow(clippy::cast_sign_loss)]
+pub fn byte(value: i64, column: usize) -> Result<u8, Fault> {
+    Ok(in_range(value, column, -0x80, 0xFF)? as u8)
+}
+
+#[allow(clippy::cast_sign_loss)]
+fn nibble(value: i64, column: usize) -> Result<u8, Fault> {
+    Ok(in_range(value, column, 0, 0xF)? as u8)
+}
+
+#[allow(clippy::cast_sign_loss)]
+pub fn word(value: i64, column: usize) -> Result<u16, Fault> {
+    Ok(in_range(value, column, 0, 0xFFFF)? as u16)
+}
+
+// NOTE: Size in bytes, only the long load takes two words
+pub fn size(operands: &[Operand]) -> u16 {
+    if operands
+        .iter()
+        .any(|operand| matches!(operand, Operand::Long(_)))
+    {
+        4
+    } else {
+        2
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+pub fn encode(mnemonic: &str, args: &[Arg], column: usize) -> Result<Vec<u8>, Fault> {
+    let name = mnemonic.to_ascii_uppercase();
+
+    let opcode = match (name.as_str(), args) {
+        ("CLS", []) => Opcode::Cls,
+        ("RET", []) => Opcode::Ret,
+        ("SCD", [Arg::Value(n, c)]) => Opcode::ScrollDown(nibble(*n, *c)?),
+        ("SCU", [Arg::Value(n, c)]) => Opcode::ScrollUp(nibble(*n, *c)?),
+        ("SCR", []) => Opcode::ScrollRight,
+        ("SCL", []) => Opcode::ScrollLeft,
+        ("EXIT", []) => Opcode::Exit,
+        ("LOW", []) => Opcode::Low,
+        ("HIGH", []) => Opcode::High,
+        ("SYS", [Arg::Value(a, c)]) => Opcode::Sys(addr(*a, *c)?),
+        ("JP", [Arg::Value(a, c)]) => Opcode::Jp(addr(*a, *c)?),
+        ("JP", [Arg::Register(0), Arg::Value(a, c)]) => Opcode::JpV0(addr(*a, *c)?),
+        ("CALL", [Arg::Value(a, c)]) => Opcode::Call(addr(*a, *c)?),
+        ("SE", [Arg::Register(x), Arg::Value(b, c)]) => Opcode::SeByte {
+            x: *x,
+            byte: byte(*b, *c)?,
+        },
+        ("SE", [Arg::Register(x), Arg::Register(y)]) => Opcode::SeReg { x: *x, y: *y },
+        ("SNE", [Arg::Register(x), Arg::Value(b, c)]) => Opcode::SneByte {
+            x: *x,
+            byte: byte(*b, *c)?,
+        },
+        ("SNE", [Arg::Register(x), Arg::Register(y)]) => Opcode::SneReg { x: *x, y: *y },
+        ("LD", [Arg::IndirectI, Arg::Range(x, y)]) => Opcode::SaveRange { x: *x, y: *y },
+        ("LD", [Arg::Range(x, y), Arg::IndirectI]) => Opcode::LoadRange { x: *x, y: *y },
+        ("LD", [Arg::Register(x), Arg::Value(b, c)]) => Opcode::LdByte {
+            x: *x,
+            byte: byte(*b, *c)?,
+        },
+        ("LD", [Arg::Register(x), Arg::Register(y)]) => Opcode::LdReg { x: *x, y: *y },
+        ("LD", [Arg::I, Arg::Value(a, c)]) => Opcode::LdI(addr(*a, *c)?),
+        ("LD", [Arg::I, Arg::Long(a, c)]) => {
+            let mut bytes = Opcode::LdILong.encode().to_be_bytes().to_vec();
+            bytes.extend(word(*a, *c)?.to_be_bytes());
+            return Ok(bytes);
+        }
+        ("LD", [Arg::Register(x), Arg::Dt]) => Opcode::LdVxDt(*x),
+        ("LD", [Arg::Register(x), Arg::K]) => Opcode::LdVxK(*x),
+        ("LD", [Arg::Dt, Arg::Register(x)]) => Opcode::LdDtVx(*x),
+        ("LD", [Arg::St, Arg::Register(x)]) => Opcode::LdStVx(*x),
+        ("LD", [Arg::F, Arg::Register(x)]) => Opcode::LdF(*x),
+        ("LD", [Arg::Hf, Arg::Register(x)]) => Opcode::LdHf(*x),
+        ("LD", [Arg::B, Arg::Register(x)]) => Opcode::LdB(*x),
+        ("LD", [Arg::IndirectI, Arg::Register(x)]) => Opcode::LdIVx(*x),
+        ("LD", [Arg::Register(x), Arg::IndirectI]) => Opcode::LdVxI(*x),
+        ("LD", [Arg::R, Arg::Register(x)]) => Opcode::LdRVx(*x),
+        ("LD", [Arg::Register(x), Arg::R]) => Opcode::LdVxR(*x),
+        ("ADD", [Arg::Register(x), Arg::Value(b, c)]) => Opcode::AddByte {
+            x: *x,
+            byte: byte(*b, *c)?,
+        },
+        ("ADD", [Arg::Register(x), Arg::Register(y)]) => Opcode::AddReg { x: *x, y: *y },
+        ("ADD", [Arg::I, Arg::Register(x)]) => Opcode::AddIVx(*x),
+        ("OR", [Arg::Register(x), Arg::Register(y)]) => Opcode::Or { x: *x, y: *y },
+        ("AND", [Arg::Register(x), Arg::Register(y)]) => Opcode::And { x: *x, y: *y },
+        ("XOR", [Arg::Register(x), Arg::Register(y)]) => Opcode::Xor { x: *x, y: *y },
+        ("SUB", [Arg::Register(x), Arg::Register(y)]) => Opcode::Sub { x: *x, y: *y },
+        ("SUBN", [Arg::Register(x), Arg::Register(y)]) => Opcode::Subn { x: *x, y: *y },
+        // NOTE: Without Vy the register is shifted in place with any quirks
+        ("SHR", [Arg::Register(x)]) => Opcode::Shr { x: *x, y: *x },
+        ("SHR", [Arg::Register(x), Arg::Register(y)]) => Opcode::Shr { x: *x, y: *y },
+        ("SHL", [Arg::Register(x)]) => Opcode::Shl { x: *x, y: *x },
+        ("SHL", [Arg::Register(x), Arg::Register(y)]) => Opcode::Shl { x: *x, y: *y },
+        ("RND", [Arg::Register(x), Arg::Value(b, c)]) => Opcode::Rnd {
+            x: *x,
+            byte: byte(*b, *c)?,
+        },
+        ("DRW", [Arg::Register(x), Arg::Register(y), Arg::Value(n, c)]) => Opcode::Drw {
+            x: *x,
+            y: *y,
+            n: nibble(*n, *c)?,
+        },
+        ("SKP", [Arg::Register(x)]) => Opcode::Skp(*x),
+        ("SKNP", [Arg::Register(x)]) => Opcode::Sknp(*x),
+        ("PLANE", [Arg::Value(n, c)]) => Opcode::Plane(nibble(*n, *c)?),
+        ("AUDIO", []) => Opcode::Audio,
+        ("PITCH", [Arg::Register(x)]) => Opcode::Pitch(*x),
+        (
+            "CLS" | "RET" | "SCD" | "SCU" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "SYS" | "JP"
+            | "CALL" | "SE" | "SNE" | "LD" | "ADD" | "OR" | "AND" | "XOR" | "SUB" | "SUBN" | "SHR"
+            | "SHL" | "RND" | "DRW" | "SKP" | "SKNP" | "PLANE" | "AUDIO" | "PITCH",
+            _,
+        ) => return Err((column, AsmErrorKind::InvalidOperands(name))),
+        _ => return Err((column, AsmErrorKind::UnknownMnemonic(mnemonic.to_owned()))),
+    };
+
+    Ok(opcode.encode().to_be_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::lexer::tokenize;
+
+    fn operand(text: &str) -> Operand {
+        parse_operand(&tokenize(text).expect("valid operand")).expect("valid operand")
+    }
+
+    #[test]
+    fn test_parse_operand() {
+        assert!(matches!(operand("vA"), Operand::Register(0xA)));
+        assert!(matches!(operand("V1 - V3"), Operand::Range(1, 3)));
+        assert!(matches!(operand("[i]"), Operand::IndirectI));
+        assert!(matches!(operand("HF"), Operand::Hf));
+        assert!(matches!(operand("LONG 0x1234"), Operand::Long(_)));
+        assert!(matches!(operand("VG"), Operand::Value(_)));
+
+        let Operand::Value(expr) = operand("start + 2 - 1") else {
+            panic!("expected a value");
+        };
+        assert_eq!(expr.terms.len(), 3);
+        assert!(expr.terms[2].0);
+    }
+
+    #[test]
+    fn test_encode() {
+        let encode_one = |name, args: &[Arg]| encode(name, args, 1).ok();
+
+        assert_eq!(encode_one("cls", &[]), Some(vec![0x00, 0xE0]));
+        assert_eq!(
+            encode_one("ADD", &[Arg::Register(1), Arg::Value(-1, 1)]),
+            Some(vec![0x71, 0xFF])
+        );
+        assert_eq!(
+            encode_one("LD", &[Arg::I, Arg::Long(0x1234, 1)]),
+            Some(vec![0xF0, 0x00, 0x12, 0x34])
+        );
+        assert_eq!(
+            encode_one("SHR", &[Arg::Register(3)]),
+            Some(vec![0x83, 0x36])
+        );
+    }
+
+    #[test]
+    fn test_encode_errors() {
+        assert!(matches!(
+            encode("JP", &[Arg::Value(0x1000, 4)], 1),
+            Err((4, AsmErrorKind::OutOfRange { value: 0x1000, .. }))
+        ));
+        assert!(matches!(
+            encode("LD", &[Arg::K, Arg::Register(1)], 1),
+            Err((1, AsmErrorKind::InvalidOperands(_)))
+        ));
+        assert!(matches!(
+            encode("MOV", &[], 1),
+            Err((1, AsmErrorKind::UnknownMnemonic(_)))
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt::Display;
+
+use super::AsmErrorKind;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Token {
+    Ident(String),
+    Number(i64),
+    Str(String),
+    Comma,
+    Colon,
+    LBracket,
+    RBracket,
+    Plus,
+    Minus,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ident(name) => write!(f, "{name}"),
+            Self::Number(value) => write!(f, "{value}"),
+            Self::Str(text) => write!(f, "\"{text}\""),
+            Self::Comma => write!(f, ","),
+            Self::Colon => write!(f, ":"),
+            Self::LBracket => write!(f, "["),
+            Self::RBracket => write!(f, "]"),
+            Self::Plus => write!(f, "+"),
+            Self::Minus => write!(f, "-"),
+        }
+    }
+}
+
+// A token and the column it starts at, counting from 1
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Spanned {
+    pub token: Token,
+    pub column: usize,
+}
+
+// NOTE: Everything after a semicolon is a comment
+pub fn tokenize(line: &str) -> Result<Vec<Spanned>, (usize, AsmErrorKind)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+
+        let token = match c {
+            ';' => break,
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|c| *c == '"')
+                    .ok_or((column, AsmErrorKind::UnterminatedString))?;
+                let text = chars[i + 1..i + 1 + end].iter().collect();
+                i += end + 2;
+                tokens.push(Spanned {
+                    token: Token::Str(text),
+                    column,
+                });
+                continue;
+            }
+            c if c.is_ascii_digit() || c == '#' || c == '$' => {
+                let text = take_word(&chars, &mut i);
+                let number =
+                    parse_number(&text).ok_or((column, AsmErrorKind::InvalidNumber(text)))?;
+                tokens.push(Spanned {
+                    token: Token::Number(number),
+                    column,
+                });
+                continue;
+            }
+            c if is_ident_char(c) => {
+                let text = take_word(&chars, &mut i);
+                tokens.push(Spanned {
+                    token: Token::Ident(text),
+                    column,
+                });
+                continue;
+            }
+            c => return Err((column, AsmErrorKind::UnexpectedCharacter(c))),
+        };
+
+        tokens.push(Spanned { token, column });
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+const fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn take_word(chars: &[char], i: &mut usize) -> String {
+    let start = *i;
+    *i += 1;
+    while *i < chars.len() && is_ident_char(chars[*i]) {
+        *i += 1;
+    }
+
+    chars[start..*i].iter().collect()
+}
+
+// NOTE: Hex can be written as 0x1F, #1F or $1F and binary as 0b101
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+
+    let hex = ["0x", "#", "$"]
+        .iter()
+        .find_map(|prefix| lower.strip_prefix(prefix));
+
+    match (hex, lower.strip_prefix("0b")) {
+        (Some(hex), _) => i64::from_str_radix(hex, 16).ok(),
+        (None, Some(binary)) => i64::from_str_radix(binary, 2).ok(),
+        (None, None) => lower.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(line: &str) -> Vec<Token> {
+        tokenize(line)
+            .expect("valid line")
+            .into_iter()
+            .map(|spanned| spanned.token)
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokens("loop: LD [I], V0 - V3 ; comment"),
+            [
+                Token::Ident(String::from("loop")),
+                Token::Colon,
+                Token::Ident(String::from("LD")),
+                Token::LBracket,
+                Token::Ident(String::from("I")),
+                Token::RBracket,
+                Token::Comma,
+                Token::Ident(String::from("V0")),
+                Token::Minus,
+                Token::Ident(String::from("V3")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(
+            tokens("12 0x1F #1f $1F 0b101"),
+            [12, 0x1F, 0x1F, 0x1F, 0b101].map(Token::Number)
+        );
+    }
+
+    #[test]
+    fn test_columns() {
+        let tokens = tokenize("  JP  end").expect("valid line");
+
+        assert_eq!(tokens[0].column, 3);
+        assert_eq!(tokens[1].column, 7);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            tokenize("DB 0x1G"),
+            Err((4, AsmErrorKind::InvalidNumber(_)))
+        ));
+        assert!(matches!(
+            tokenize("LD V0, @"),
+            Err((8, AsmErrorKind::UnexpectedCharacter('@')))
+        ));
+        assert!(matches!(
+            tokenize("INCLUDE \"file"),
+            Err((9, AsmErrorKind::UnterminatedString))
+        ));
+    }
+}
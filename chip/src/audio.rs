@@ -0,0 +1,72 @@
+pub const PATTERN_SIZE: usize = 16;
+pub const PATTERN_BITS: usize = PATTERN_SIZE * 8;
+
+const DEFAULT_PITCH: u8 = 64;
+
+pub type Pattern = [u8; PATTERN_SIZE];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AudioState {
+    // NOTE: None until the rom loads a pattern with F002
+    pub pattern: Option<Pattern>,
+    pub pitch: u8,
+    pub playing: bool,
+}
+
+impl Default for AudioState {
+    fn default() -> Self {
+        Self {
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+            playing: false,
+        }
+    }
+}
+
+impl AudioState {
+    // NOTE: Rate in bits per second at which the pattern is played
+    #[must_use]
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * ((f32::from(self.pitch) - 64.0) / 48.0).exp2()
+    }
+
+    // NOTE: Returns the value of the bit of the pattern at the given position
+    #[must_use]
+    pub fn sample(&self, position: usize) -> bool {
+        self.pattern.is_some_and(|pattern| {
+            let bit = position % PATTERN_BITS;
+            pattern[bit / 8] & (0x80 >> (bit % 8)) != 0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playback_rate() {
+        let mut audio = AudioState::default();
+
+        assert!((audio.playback_rate() - 4000.0).abs() < f32::EPSILON);
+
+        audio.pitch = 64 + 48;
+
+        assert!((audio.playback_rate() - 8000.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut pattern = [0; PATTERN_SIZE];
+        pattern[0] = 0b1000_0001;
+        let audio = AudioState {
+            pattern: Some(pattern),
+            ..Default::default()
+        };
+
+        assert!(audio.sample(0));
+        assert!(!audio.sample(1));
+        assert!(audio.sample(7));
+        assert!(audio.sample(PATTERN_BITS));
+    }
+}
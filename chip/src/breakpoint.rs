@@ -0,0 +1,426 @@
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::{Opcode, Registers};
+
+// NOTE: Opcode patterns are written like in the docs, with these letters in
+// place of the digits that can take any value
+const WILDCARDS: [char; 4] = ['x', 'y', 'n', 'k'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    const OPERATORS: [(&'static str, Self); 6] = [
+        ("==", Self::Equal),
+        ("!=", Self::NotEqual),
+        ("<=", Self::LessOrEqual),
+        (">=", Self::GreaterOrEqual),
+        ("<", Self::Less),
+        (">", Self::Greater),
+    ];
+
+    #[must_use]
+    pub const fn holds(self, left: u8, right: u8) -> bool {
+        match self {
+            Self::Equal => left == right,
+            Self::NotEqual => left != right,
+            Self::Less => left < right,
+            Self::LessOrEqual => left <= right,
+            Self::Greater => left > right,
+            Self::GreaterOrEqual => left >= right,
+        }
+    }
+
+    const fn operator(self) -> &'static str {
+        match self {
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::Less => "<",
+            Self::LessOrEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+// Condition that stops the chip before running the instruction at PC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    // PC reaches the address
+    Address(u16),
+    // The next opcode masked matches the value, Dxyn is 0xF000 0xD000
+    Opcode {
+        mask: u16,
+        value: u16,
+    },
+    // NOTE: Only when the condition becomes true, not while it stays true
+    Register {
+        register: u8,
+        comparison: Comparison,
+        value: u8,
+    },
+}
+
+impl Breakpoint {
+    // NOTE: Checked after every instruction, with the registers from before
+    // and after it and the opcode that runs next
+    pub(crate) fn is_hit(self, before: &Registers, after: &Registers, next: Option<u16>) -> bool {
+        match self {
+            Self::Address(address) => after.pc == address,
+            Self::Opcode { mask, value } => next.is_some_and(|opcode| opcode & mask == value),
+            Self::Register {
+                register,
+                comparison,
+                value,
+            } => {
+                let register = usize::from(register);
+                comparison.holds(after.v[register], value)
+                    && !comparison.holds(before.v[register], value)
+            }
+        }
+    }
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Address(address) => write!(f, "{address:#05X}"),
+            Self::Opcode { mask, value } => {
+                // NOTE: Wildcards are shown with the first letter for all
+                for shift in [12, 8, 4, 0] {
+                    if mask & (0xF << shift) == 0 {
+                        write!(f, "{}", WILDCARDS[0])?;
+                    } else {
+                        write!(f, "{:X}", value >> shift & 0xF)?;
+                    }
+                }
+                Ok(())
+            }
+            Self::Register {
+                register,
+                comparison,
+                value,
+            } => write!(f, "V{register:X} {} {value:#04X}", comparison.operator()),
+        }
+    }
+}
+
+// NOTE: Breakpoints are written as an address like 0x2A0, an opcode pattern
+// like Dxyn or 00E0, or a register condition like V3 == 0x10
+impl FromStr for Breakpoint {
+    type Err = ParseBreakpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseBreakpointError { text: s.to_owned() };
+        let text = s.trim();
+
+        if let Some(register) = text.strip_prefix(['v', 'V']) {
+            let (operator, comparison) = Comparison::OPERATORS
+                .into_iter()
+                .find(|(operator, _)| register.contains(operator))
+                .ok_or_else(error)?;
+            let (register, value) = register.split_once(operator).ok_or_else(error)?;
+
+            let register = u8::from_str_radix(register.trim(), 16)
+                .ok()
+                .filter(|register| *register < 16)
+                .ok_or_else(error)?;
+            let value = parse_number(value.trim())
+                .and_then(|value| u8::try_from(value).ok())
+                .ok_or_else(error)?;
+
+            return Ok(Self::Register {
+                register,
+                comparison,
+                value,
+            });
+        }
+
+        if text.starts_with("0x") {
+            let address = parse_number(text)
+                .and_then(|address| u16::try_from(address).ok())
+                .ok_or_else(error)?;
+
+            return Ok(Self::Address(address));
+        }
+
+        if text.chars().count() != 4 {
+            return Err(error());
+        }
+
+        let mut mask = 0;
+        let mut value = 0;
+        for c in text.chars() {
+            mask <<= 4;
+            value <<= 4;
+            if !WILDCARDS.contains(&c.to_ascii_lowercase()) {
+                mask |= 0xF;
+                value |= c.to_digit(16).ok_or_else(error)? as u16;
+            }
+        }
+
+        Ok(Self::Opcode { mask, value })
+    }
+}
+
+impl From<Opcode> for Breakpoint {
+    // NOTE: Matches exactly that instruction
+    fn from(opcode: Opcode) -> Self {
+        Self::Opcode {
+            mask: 0xFFFF,
+            value: opcode.encode(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read => write!(f, "read"),
+            Self::Write => write!(f, "write"),
+        }
+    }
+}
+
+// Memory range that stops the chip after an instruction reads or writes it,
+// the instructions themselves are not data reads
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Watchpoint {
+    // NOTE: The first watched address in the accessed range, if any
+    pub(crate) fn hit(&self, access: Access, start: u16, len: u16) -> Option<u16> {
+        let watched = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+        };
+        if !watched || len == 0 {
+            return None;
+        }
+
+        let end = start.saturating_add(len - 1);
+        let first = start.max(*self.range.start());
+
+        (first <= end && first <= *self.range.end()).then_some(first)
+    }
+}
+
+impl Display for Watchpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#05X}", self.range.start())?;
+        if self.range.start() != self.range.end() {
+            write!(f, "-{:#05X}", self.range.end())?;
+        }
+
+        match (self.read, self.write) {
+            (true, false) => write!(f, ":r"),
+            (false, true) => write!(f, ":w"),
+            _ => Ok(()),
+        }
+    }
+}
+
+// NOTE: Watchpoints are written as an address or a range like 0x300-0x30F,
+// with :r or :w at the end to watch only reads or writes
+impl FromStr for Watchpoint {
+    type Err = ParseBreakpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseBreakpointError { text: s.to_owned() };
+
+        let (range, read, write) = match s.trim().rsplit_once(':') {
+            Some((range, "r")) => (range, true, false),
+            Some((range, "w")) => (range, false, true),
+            Some((range, "rw")) => (range, true, true),
+            Some(_) => return Err(error()),
+            None => (s.trim(), true, true),
+        };
+
+        let address = |text: &str| {
+            parse_number(text.trim())
+                .and_then(|address| u16::try_from(address).ok())
+                .ok_or_else(error)
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (address(start)?, address(end)?),
+            None => (address(range)?, address(range)?),
+        };
+        if start > end {
+            return Err(error());
+        }
+
+        Ok(Self {
+            range: start..=end,
+            read,
+            write,
+        })
+    }
+}
+
+// Why run_cycle stopped early
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BreakReason {
+    Breakpoint(Breakpoint),
+    Watchpoint {
+        watchpoint: Watchpoint,
+        access: Access,
+        address: u16,
+    },
+}
+
+impl Display for BreakReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Breakpoint(breakpoint) => write!(f, "Breakpoint {breakpoint}"),
+            Self::Watchpoint {
+                access, address, ..
+            } => write!(f, "Watchpoint {access} at {address:#05X}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseBreakpointError {
+    pub text: String,
+}
+
+impl Display for ParseBreakpointError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid breakpoint '{}'", self.text)
+    }
+}
+
+impl std::error::Error for ParseBreakpointError {}
+
+fn parse_number(text: &str) -> Option<i64> {
+    text.strip_prefix("0x").map_or_else(
+        || text.parse().ok(),
+        |hex| i64::from_str_radix(hex, 16).ok(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registers(v3: u8, pc: u16) -> Registers {
+        let mut v = [0; 16];
+        v[3] = v3;
+
+        Registers {
+            v,
+            i: 0,
+            pc,
+            delay_timer: 0,
+            sound_timer: 0,
+            sp: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_breakpoint() {
+        assert_eq!("0x2A0".parse().ok(), Some(Breakpoint::Address(0x2A0)));
+        assert_eq!(
+            "Dxyn".parse().ok(),
+            Some(Breakpoint::Opcode {
+                mask: 0xF000,
+                value: 0xD000
+            })
+        );
+        assert_eq!(
+            "Fx33".parse().ok(),
+            Some(Breakpoint::Opcode {
+                mask: 0xF0FF,
+                value: 0xF033
+            })
+        );
+        assert_eq!(
+            "V3 == 0x10".parse().ok(),
+            Some(Breakpoint::Register {
+                register: 3,
+                comparison: Comparison::Equal,
+                value: 0x10
+            })
+        );
+        assert_eq!(
+            "vf>=2".parse().ok(),
+            Some(Breakpoint::Register {
+                register: 0xF,
+                comparison: Comparison::GreaterOrEqual,
+                value: 2
+            })
+        );
+
+        for invalid in [
+            "",
+            "0x10000",
+            "Dxy",
+            "Gxyn",
+            "V3 = 1",
+            "VG == 1",
+            "V1 == 256",
+        ] {
+            assert!(invalid.parse::<Breakpoint>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_display_breakpoint() {
+        for text in ["0x2A0", "Dxxx", "00E0", "V3 == 0x10"] {
+            let breakpoint: Breakpoint = text.parse().expect("Valid breakpoint");
+            assert_eq!(breakpoint.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_breakpoint_hit() {
+        let before = registers(0x0F, 0x200);
+        let after = registers(0x10, 0x202);
+
+        assert!(Breakpoint::Address(0x202).is_hit(&before, &after, None));
+        assert!(!Breakpoint::Address(0x200).is_hit(&before, &after, None));
+
+        let pattern: Breakpoint = "Dxyn".parse().expect("Valid breakpoint");
+        assert!(pattern.is_hit(&before, &after, Some(0xD125)));
+        assert!(!pattern.is_hit(&before, &after, Some(0x00E0)));
+
+        let condition: Breakpoint = "V3 == 0x10".parse().expect("Valid breakpoint");
+        assert!(condition.is_hit(&before, &after, None));
+        assert!(!condition.is_hit(&after, &after, None));
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let watchpoint: Watchpoint = "0x300-0x30F:w".parse().expect("Valid watchpoint");
+        assert_eq!(watchpoint.range, 0x300..=0x30F);
+        assert_eq!(watchpoint.to_string(), "0x300-0x30F:w");
+
+        assert_eq!(watchpoint.hit(Access::Write, 0x2FE, 3), Some(0x300));
+        assert_eq!(watchpoint.hit(Access::Write, 0x30F, 1), Some(0x30F));
+        assert_eq!(watchpoint.hit(Access::Write, 0x310, 4), None);
+        assert_eq!(watchpoint.hit(Access::Write, 0x2F0, 0x10), None);
+        assert_eq!(watchpoint.hit(Access::Read, 0x300, 1), None);
+
+        assert!("0x30F-0x300".parse::<Watchpoint>().is_err());
+        assert!("0x300:x".parse::<Watchpoint>().is_err());
+    }
+}
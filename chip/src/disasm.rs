@@ -0,0 +1,490 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+use std::ops::Range;
+
+use crate::Opcode;
+
+// NOTE: Unknown bytes are grouped in lines of at most this many
+const DATA_BYTES_PER_LINE: usize = 8;
+
+// A single line of the listing, with the label pointing to it if any
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub label: Option<String>,
+    pub mnemonic: String,
+    pub comment: Option<String>,
+}
+
+// NOTE: Written as assembly source, the address and bytes go in a comment so
+// the listing can be assembled again
+impl Display for Line {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(label) = &self.label {
+            writeln!(f, "{label}:")?;
+        }
+
+        let bytes = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let comment = self.comment.as_deref().unwrap_or_default();
+
+        let line = format!(
+            "    {:<24}; {:04X}  {bytes:<11}  {comment}",
+            self.mnemonic, self.address
+        );
+
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Item {
+    Instruction(Opcode),
+    LongLoad(u16),
+    Word(u16),
+    Bytes,
+    Sprite,
+}
+
+impl Item {
+    fn read(bytes: &[u8]) -> (Self, usize) {
+        let word = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+
+        if bytes.len() < 2 {
+            return (Self::Bytes, 1);
+        }
+
+        match Opcode::decode(word(0)) {
+            Ok(Opcode::LdILong) if bytes.len() >= 4 => (Self::LongLoad(word(2)), 4),
+            // NOTE: Without the address the long load can not be executed
+            Ok(Opcode::LdILong) | Err(_) => (Self::Word(word(0)), 2),
+            Ok(opcode) => (Self::Instruction(opcode), 2),
+        }
+    }
+
+    // NOTE: Addresses worth a label, where control may go or where I may point
+    const fn target(self) -> Option<u16> {
+        match self {
+            Self::Instruction(Opcode::LdI(addr)) | Self::LongLoad(addr) => Some(addr),
+            Self::Instruction(opcode) => opcode.target(),
+            _ => None,
+        }
+    }
+
+    fn mnemonic(self, bytes: &[u8], labels: &HashMap<u16, String>) -> String {
+        let label = self.target().and_then(|addr| labels.get(&addr));
+
+        match (self, label) {
+            (Self::Instruction(Opcode::Jp(_)), Some(label)) => format!("JP {label}"),
+            (Self::Instruction(Opcode::Call(_)), Some(label)) => format!("CALL {label}"),
+            (Self::Instruction(Opcode::JpV0(_)), Some(label)) => format!("JP V0, {label}"),
+            (Self::Instruction(Opcode::LdI(_)), Some(label)) => format!("LD I, {label}"),
+            (Self::LongLoad(_), Some(label)) => format!("LD I, LONG {label}"),
+            (Self::Instruction(opcode), _) => opcode.to_string(),
+            (Self::LongLoad(addr), None) => format!("LD I, LONG {addr:#06X}"),
+            (Self::Word(word), _) => format!("DW {word:#06X}"),
+            (Self::Bytes | Self::Sprite, _) => {
+                let bytes: Vec<String> = bytes.iter().map(|byte| format!("{byte:#04X}")).collect();
+                format!("DB {}", bytes.join(", "))
+            }
+        }
+    }
+
+    fn comment(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            Self::Instruction(Opcode::JpV0(_)) => Some(String::from("indirect jump")),
+            Self::Sprite => Some(bytes.iter().map(|byte| sprite_row(*byte)).collect()),
+            _ => None,
+        }
+    }
+}
+
+fn sprite_row(byte: u8) -> String {
+    (0..8)
+        .map(|bit| if byte & (0x80 >> bit) == 0 { '.' } else { '#' })
+        .collect()
+}
+
+// NOTE: Linear sweep, every word is read as an instruction, the ones that are
+// not valid are shown as data
+#[must_use]
+pub fn disassemble(rom: &[u8], start: u16) -> Vec<Line> {
+    let mut items = Vec::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let (item, size) = Item::read(&rom[offset..]);
+        items.push((
+            start.wrapping_add(offset as u16),
+            offset..offset + size,
+            item,
+        ));
+        offset += size;
+    }
+
+    into_lines(rom, items)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Byte {
+    Unknown,
+    Code,
+    // NOTE: First byte of a sprite row and the row width in bytes
+    SpriteRow(usize),
+    SpriteTail,
+}
+
+// A sprite drawn by Dxyn with I pointing to a known address
+struct Sprite {
+    address: u16,
+    rows: usize,
+    width: usize,
+}
+
+// Recursive descent, starting from the first instruction only the paths the
+// program may take are read as code, everything else is data
+#[must_use]
+pub fn disassemble_recursive(rom: &[u8], start: u16) -> Vec<Line> {
+    let offset_of = |address: u16| offset_of(rom, start, address);
+    let (code, sprites) = trace(rom, start);
+
+    let mut bytes = vec![Byte::Unknown; rom.len()];
+    for (offset, (_, size)) in &code {
+        bytes[*offset..offset + size].fill(Byte::Code);
+    }
+    for sprite in sprites {
+        mark_sprite(&mut bytes, &sprite, offset_of);
+    }
+
+    // NOTE: Data lines are split at the targets so they can be labeled
+    let targets: HashSet<usize> = code
+        .values()
+        .filter_map(|(item, _)| item.target())
+        .filter_map(offset_of)
+        .collect();
+
+    let mut items = Vec::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let (item, size) = match (code.get(&offset), bytes[offset]) {
+            (Some((item, size)), _) => (*item, *size),
+            (None, Byte::SpriteRow(width)) => {
+                let row = (1..width)
+                    .take_while(|i| bytes.get(offset + i) == Some(&Byte::SpriteTail))
+                    .count();
+                (Item::Sprite, row + 1)
+            }
+            (None, Byte::SpriteTail) => (Item::Sprite, 1),
+            (None, _) => {
+                let run = (1..DATA_BYTES_PER_LINE)
+                    .take_while(|i| {
+                        let next = offset + i;
+                        next < rom.len() && bytes[next] == Byte::Unknown && !targets.contains(&next)
+                    })
+                    .count();
+                (Item::Bytes, run + 1)
+            }
+        };
+
+        items.push((
+            start.wrapping_add(offset as u16),
+            offset..offset + size,
+            item,
+        ));
+        offset += size;
+    }
+
+    into_lines(rom, items)
+}
+
+// NOTE: Follows every path from the start, returns the instructions found by
+// offset and the sprites drawn by them
+fn trace(rom: &[u8], start: u16) -> (BTreeMap<usize, (Item, usize)>, Vec<Sprite>) {
+    let offset_of = |address: u16| offset_of(rom, start, address);
+
+    let mut code: BTreeMap<usize, (Item, usize)> = BTreeMap::new();
+    let mut sprites = Vec::new();
+
+    // NOTE: Every pending path knows where I points to, if it is known
+    let mut pending: Vec<(u16, Option<u16>)> = vec![(start, None)];
+
+    while let Some((mut address, mut i)) = pending.pop() {
+        while let Some(offset) = offset_of(address) {
+            if code.contains_key(&offset) {
+                break;
+            }
+
+            let (item, size) = Item::read(&rom[offset..]);
+            let opcode = match item {
+                Item::Instruction(opcode) => opcode,
+                Item::LongLoad(addr) => {
+                    code.insert(offset, (item, size));
+                    i = Some(addr);
+                    address = address.wrapping_add(size as u16);
+                    continue;
+                }
+                // NOTE: The program would crash here, the path ends
+                _ => break,
+            };
+
+            code.insert(offset, (item, size));
+            let next = address.wrapping_add(size as u16);
+
+            match opcode {
+                Opcode::Jp(target) => {
+                    pending.push((target, i));
+                    break;
+                }
+                Opcode::Call(target) => {
+                    pending.push((target, i));
+                    i = None;
+                }
+                Opcode::Ret | Opcode::Exit | Opcode::JpV0(_) => break,
+                Opcode::SeByte { .. }
+                | Opcode::SneByte { .. }
+                | Opcode::SeReg { .. }
+                | Opcode::SneReg { .. }
+                | Opcode::Skp(_)
+                | Opcode::Sknp(_) => {
+                    // NOTE: The skipped instruction may be a long load
+                    if let Some(offset) = offset_of(next) {
+                        let (_, skipped) = Item::read(&rom[offset..]);
+                        pending.push((next.wrapping_add(skipped as u16), i));
+                    }
+                }
+                Opcode::LdI(addr) => i = Some(addr),
+                Opcode::Drw { n, .. } => {
+                    if let Some(address) = i {
+                        sprites.push(match n {
+                            0 => Sprite {
+                                address,
+                                rows: 16,
+                                width: 2,
+                            },
+                            n => Sprite {
+                                address,
+                                rows: usize::from(n),
+                                width: 1,
+                            },
+                        });
+                    }
+                }
+                Opcode::AddIVx(_)
+                | Opcode::LdF(_)
+                | Opcode::LdHf(_)
+                | Opcode::LdIVx(_)
+                | Opcode::LdVxI(_) => i = None,
+                _ => (),
+            }
+
+            address = next;
+        }
+    }
+
+    (code, sprites)
+}
+
+// NOTE: Bytes already read as code are never taken as sprite data
+fn mark_sprite(bytes: &mut [Byte], sprite: &Sprite, offset_of: impl Fn(u16) -> Option<usize>) {
+    for row in 0..sprite.rows {
+        let address = sprite.address.wrapping_add((row * sprite.width) as u16);
+
+        for column in 0..sprite.width {
+            let Some(offset) = offset_of(address.wrapping_add(column as u16)) else {
+                continue;
+            };
+            if bytes[offset] == Byte::Code {
+                continue;
+            }
+
+            bytes[offset] = match column {
+                0 => Byte::SpriteRow(sprite.width),
+                _ => Byte::SpriteTail,
+            };
+        }
+    }
+}
+
+// NOTE: Targets in the middle of a line or outside the rom are left as plain
+// addresses
+fn into_lines(rom: &[u8], items: Vec<(u16, Range<usize>, Item)>) -> Vec<Line> {
+    let addresses: HashSet<u16> = items.iter().map(|(address, _, _)| *address).collect();
+
+    let labels: HashMap<u16, String> = items
+        .iter()
+        .filter_map(|(_, _, item)| item.target())
+        .filter(|target| addresses.contains(target))
+        .map(|target| (target, label(target)))
+        .collect();
+
+    items
+        .into_iter()
+        .map(|(address, range, item)| {
+            let bytes = &rom[range];
+
+            Line {
+                address,
+                bytes: bytes.to_vec(),
+                label: labels.get(&address).cloned(),
+                mnemonic: item.mnemonic(bytes, &labels),
+                comment: item.comment(bytes),
+            }
+        })
+        .collect()
+}
+
+fn offset_of(rom: &[u8], start: u16, address: u16) -> Option<usize> {
+    let offset = usize::from(address.wrapping_sub(start));
+    (offset < rom.len()).then_some(offset)
+}
+
+fn label(address: u16) -> String {
+    format!("L{address:03X}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_ROM_START;
+
+    fn mnemonics(lines: Vec<Line>) -> Vec<String> {
+        lines.into_iter().map(|line| line.mnemonic).collect()
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let lines = disassemble(&[0x00, 0xE0, 0x6A, 0x42, 0xD1, 0x25], DEFAULT_ROM_START);
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1].address, 0x202);
+        assert_eq!(lines[1].bytes, vec![0x6A, 0x42]);
+        assert_eq!(lines[1].mnemonic, "LD VA, 0x42");
+        assert_eq!(lines[2].mnemonic, "DRW V1, V2, 5");
+    }
+
+    #[test]
+    fn test_labels() {
+        let lines = disassemble(&[0x22, 0x04, 0x12, 0x02, 0x00, 0xEE], DEFAULT_ROM_START);
+
+        assert_eq!(lines[0].mnemonic, "CALL L204");
+        assert_eq!(lines[1].mnemonic, "JP L202");
+        assert_eq!(lines[1].label.as_deref(), Some("L202"));
+        assert_eq!(lines[2].label.as_deref(), Some("L204"));
+        assert_eq!(lines[0].label, None);
+    }
+
+    #[test]
+    fn test_target_outside_rom() {
+        let lines = disassemble(&[0x13, 0x00, 0x12, 0x01], DEFAULT_ROM_START);
+
+        assert_eq!(mnemonics(lines), ["JP 0x300", "JP 0x201"]);
+    }
+
+    #[test]
+    fn test_data() {
+        let lines = disassemble(
+            &[0xF0, 0x00, 0x12, 0x34, 0x50, 0x01, 0xAB],
+            DEFAULT_ROM_START,
+        );
+        assert_eq!(
+            mnemonics(lines),
+            ["LD I, LONG 0x1234", "DW 0x5001", "DB 0xAB"]
+        );
+
+        let lines = disassemble(&[0xF0, 0x00], DEFAULT_ROM_START);
+        assert_eq!(mnemonics(lines), ["DW 0xF000"]);
+    }
+
+    #[test]
+    fn test_line_display() {
+        let lines = disassemble(&[0x12, 0x00, 0xF0, 0x00, 0x12, 0x34], DEFAULT_ROM_START);
+
+        assert_eq!(
+            lines[0].to_string(),
+            "L200:\n    JP L200                 ; 0200  12 00"
+        );
+        assert_eq!(
+            lines[1].to_string(),
+            "    LD I, LONG 0x1234       ; 0202  F0 00 12 34"
+        );
+    }
+
+    #[test]
+    fn test_recursive_sprite() {
+        let rom = [
+            0xA2, 0x06, // LD I, 0x206
+            0xD0, 0x12, // DRW V0, V1, 2
+            0x12, 0x04, // JP 0x204
+            0xF0, 0x81, // sprite
+        ];
+        let lines = disassemble_recursive(&rom, DEFAULT_ROM_START);
+
+        assert_eq!(
+            mnemonics(lines.clone()),
+            [
+                "LD I, L206",
+                "DRW V0, V1, 2",
+                "JP L204",
+                "DB 0xF0",
+                "DB 0x81"
+            ]
+        );
+        assert_eq!(lines[3].label.as_deref(), Some("L206"));
+        assert_eq!(lines[3].comment.as_deref(), Some("####...."));
+        assert_eq!(lines[4].comment.as_deref(), Some("#......#"));
+    }
+
+    #[test]
+    fn test_recursive_large_sprite() {
+        let mut rom = vec![0xA2, 0x06, 0xD0, 0x10, 0x00, 0xFD];
+        rom.extend([0xFF; 32]);
+        let lines = disassemble_recursive(&rom, DEFAULT_ROM_START);
+
+        assert_eq!(lines.len(), 3 + 16);
+        assert_eq!(lines[3].mnemonic, "DB 0xFF, 0xFF");
+        assert_eq!(lines[3].comment.as_deref(), Some("################"));
+    }
+
+    #[test]
+    fn test_recursive_flow() {
+        let rom = [
+            0x22, 0x08, // CALL 0x208
+            0x3A, 0x00, // SE VA, 0x00
+            0xF0, 0x00, 0x12, 0x34, // LD I, LONG 0x1234
+            0x00, 0xEE, // RET
+            0xB2, 0x00, // JP V0, 0x200
+            0x01, 0x02, 0x03, // data
+        ];
+        let lines = disassemble_recursive(&rom, DEFAULT_ROM_START);
+
+        assert_eq!(
+            mnemonics(lines),
+            [
+                "CALL L208",
+                "SE VA, 0x00",
+                "LD I, LONG 0x1234",
+                "RET",
+                "DB 0xB2, 0x00, 0x01, 0x02, 0x03",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recursive_indirect_jump() {
+        let rom = [0xB2, 0x04, 0x00, 0xE0, 0x00, 0xE0];
+        let lines = disassemble_recursive(&rom, DEFAULT_ROM_START);
+
+        assert_eq!(
+            mnemonics(lines.clone()),
+            ["JP V0, L204", "DB 0x00, 0xE0", "DB 0x00, 0xE0"]
+        );
+        assert_eq!(lines[0].comment.as_deref(), Some("indirect jump"));
+        assert_eq!(lines[2].label.as_deref(), Some("L204"));
+    }
+}
@@ -1,16 +1,93 @@
+use crate::state::{Snapshot, StateReader, StateWriter};
+use crate::ChipError;
+
 pub const DISPLAY_WIDTH: usize = 64;
 pub const DISPLAY_HEIGHT: usize = 32;
-pub type Screen = [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+
+pub const PLANES_COUNT: usize = 2;
+
+const HORIZONTAL_SCROLL: isize = 4;
+const DEFAULT_PLANES: u8 = 0b01;
+
+// NOTE: Each pixel holds one bit per plane
+type Pixels = [[u8; HIRES_DISPLAY_WIDTH]; HIRES_DISPLAY_HEIGHT];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resolution {
+    Low,
+    High,
+}
+
+impl Resolution {
+    #[must_use]
+    pub const fn width(self) -> usize {
+        match self {
+            Self::Low => DISPLAY_WIDTH,
+            Self::High => HIRES_DISPLAY_WIDTH,
+        }
+    }
+
+    #[must_use]
+    pub const fn height(self) -> usize {
+        match self {
+            Self::Low => DISPLAY_HEIGHT,
+            Self::High => HIRES_DISPLAY_HEIGHT,
+        }
+    }
+}
+
+// NOTE: The buffer is always allocated for hi-res, only the top left
+// corner of the size of the active resolution is used
+pub struct Screen {
+    pixels: Pixels,
+    resolution: Resolution,
+}
+
+impl Screen {
+    #[must_use]
+    pub const fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.resolution.width()
+    }
+
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.resolution.height()
+    }
+
+    // NOTE: Returns a 2-bit value, bit 0 is the first plane and bit 1 the second
+    #[must_use]
+    pub const fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y][x]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.pixels[..self.height()]
+            .iter()
+            .map(|row| &row[..self.width()])
+    }
+}
 
 pub struct Display {
     screen: Screen,
+    selected_planes: u8,
     must_draw: bool,
 }
 
 impl Default for Display {
     fn default() -> Self {
         Self {
-            screen: [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+            screen: Screen {
+                pixels: [[0; HIRES_DISPLAY_WIDTH]; HIRES_DISPLAY_HEIGHT],
+                resolution: Resolution::Low,
+            },
+            selected_planes: DEFAULT_PLANES,
             must_draw: false,
         }
     }
@@ -21,7 +98,7 @@ impl Display {
         &self.screen
     }
 
-    pub fn must_draw(&mut self) -> bool {
+    pub const fn must_draw(&mut self) -> bool {
         if self.must_draw {
             self.must_draw = false;
             return true;
@@ -30,42 +107,208 @@ impl Display {
         false
     }
 
+    pub const fn select_planes(&mut self, planes: u8) {
+        self.selected_planes = planes & 0b11;
+    }
+
+    pub const fn selected_planes_count(&self) -> usize {
+        self.selected_planes.count_ones() as usize
+    }
+
+    // NOTE: Only clears the selected planes
     pub fn clear(&mut self) {
-        self.screen = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        for pixel in self.screen.pixels.iter_mut().flatten() {
+            *pixel &= !self.selected_planes;
+        }
+        self.must_draw = true;
+    }
+
+    // NOTE: Changing the resolution clears every plane
+    pub const fn set_resolution(&mut self, resolution: Resolution) {
+        self.screen.resolution = resolution;
+        self.screen.pixels = [[0; HIRES_DISPLAY_WIDTH]; HIRES_DISPLAY_HEIGHT];
         self.must_draw = true;
     }
 
     // NOTE: Returns true on colision
-    pub fn draw_sprite(&mut self, sprite: &[u8], x: u8, y: u8) -> bool {
-        assert!(sprite.len() <= 15, "Unsupported sprite");
+    // The starting position always wraps, pixels outside the screen are
+    // wrapped or clipped depending on `wrap`
+    // With more than one plane selected the sprite holds the data of each
+    // plane one after the other
+    pub fn draw_sprite(&mut self, sprite: &[u8], x: u8, y: u8, wrap: bool) -> bool {
+        assert!(
+            sprite.len() <= 15 * self.selected_planes_count(),
+            "Unsupported sprite"
+        );
+
+        self.draw(sprite, 1, x, y, wrap)
+    }
 
-        let x = x as usize;
-        let y = y as usize;
+    // NOTE: Draws a 16x16 sprite, two bytes per row
+    pub fn draw_large_sprite(&mut self, sprite: &[u8], x: u8, y: u8, wrap: bool) -> bool {
+        assert!(
+            sprite.len() <= 32 * self.selected_planes_count(),
+            "Unsupported sprite"
+        );
+
+        self.draw(sprite, 2, x, y, wrap)
+    }
+
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.scroll(0, rows as isize);
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.scroll(0, -(rows as isize));
+    }
+
+    pub fn scroll_right(&mut self) {
+        self.scroll(HORIZONTAL_SCROLL, 0);
+    }
+
+    pub fn scroll_left(&mut self) {
+        self.scroll(-HORIZONTAL_SCROLL, 0);
+    }
+
+    // NOTE: Moves the selected planes, pixels that enter the screen are off
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let source = self.screen.pixels;
+        let width = self.screen.width();
+        let height = self.screen.height();
+
+        for (y, row) in self.screen.pixels[..height].iter_mut().enumerate() {
+            for (x, pixel) in row[..width].iter_mut().enumerate() {
+                let from_x = x.checked_add_signed(-dx).filter(|x| *x < width);
+                let from_y = y.checked_add_signed(-dy).filter(|y| *y < height);
+
+                let moved = match (from_x, from_y) {
+                    (Some(from_x), Some(from_y)) => source[from_y][from_x],
+                    _ => 0,
+                };
+
+                *pixel = (*pixel & !self.selected_planes) | (moved & self.selected_planes);
+            }
+        }
+
+        self.must_draw = true;
+    }
+
+    fn draw(&mut self, sprite: &[u8], bytes_per_row: usize, x: u8, y: u8, wrap: bool) -> bool {
+        let planes_count = self.selected_planes_count();
+        if planes_count == 0 || sprite.is_empty() {
+            return false;
+        }
+
+        let selected_planes = self.selected_planes;
+        let planes = (0..PLANES_COUNT)
+            .map(|plane| 1 << plane)
+            .filter(|plane| selected_planes & plane != 0);
+        let plane_data = sprite.chunks(sprite.len() / planes_count);
+
+        let mut colision = false;
+
+        for (plane, data) in planes.zip(plane_data) {
+            colision |= self.draw_plane(data, plane, bytes_per_row, x, y, wrap);
+        }
+
+        self.must_draw = true;
+
+        colision
+    }
+
+    fn draw_plane(
+        &mut self,
+        sprite: &[u8],
+        plane: u8,
+        bytes_per_row: usize,
+        x: u8,
+        y: u8,
+        wrap: bool,
+    ) -> bool {
+        let width = self.screen.width();
+        let height = self.screen.height();
+
+        let x = x as usize % width;
+        let y = y as usize % height;
 
         let mut colision = false;
 
-        for (height, byte) in sprite.iter().enumerate() {
-            let row = self.screen.get_mut(y + height);
-            if let Some(row) = row {
+        for (height_offset, row_bytes) in sprite.chunks(bytes_per_row).enumerate() {
+            let Some(row) = Self::wrap_or_clip(y + height_offset, height, wrap) else {
+                continue;
+            };
+
+            for (byte_offset, byte) in row_bytes.iter().enumerate() {
                 for bit in 0..8 {
-                    let old_pixel = row.get_mut(x + bit);
-                    if let Some(old_pixel) = old_pixel {
-                        let new_pixel = byte & (1 << (7 - bit)) != 0;
+                    let offset = byte_offset * 8 + bit;
+                    let Some(column) = Self::wrap_or_clip(x + offset, width, wrap) else {
+                        continue;
+                    };
+
+                    if byte & (1 << (7 - bit)) == 0 {
+                        continue;
+                    }
 
-                        if !colision && *old_pixel && new_pixel {
-                            colision = true;
-                        }
+                    let pixel = &mut self.screen.pixels[row][column];
 
-                        *old_pixel = *old_pixel != new_pixel;
+                    if *pixel & plane != 0 {
+                        colision = true;
                     }
+
+                    *pixel ^= plane;
                 }
             }
         }
 
-        self.must_draw = true;
-
         colision
     }
+
+    const fn wrap_or_clip(position: usize, size: usize, wrap: bool) -> Option<usize> {
+        if wrap {
+            Some(position % size)
+        } else if position < size {
+            Some(position)
+        } else {
+            None
+        }
+    }
+}
+
+impl Snapshot for Display {
+    fn save(&self, writer: &mut StateWriter) {
+        for row in &self.screen.pixels {
+            writer.write_bytes(row);
+        }
+        writer.write_bool(self.screen.resolution == Resolution::High);
+        writer.write_u8(self.selected_planes);
+        writer.write_bool(self.must_draw);
+    }
+
+    fn load(reader: &mut StateReader) -> Result<Self, ChipError> {
+        let mut pixels = [[0; HIRES_DISPLAY_WIDTH]; HIRES_DISPLAY_HEIGHT];
+        for row in &mut pixels {
+            *row = reader.read_array()?;
+        }
+
+        let resolution = if reader.read_bool()? {
+            Resolution::High
+        } else {
+            Resolution::Low
+        };
+
+        let selected_planes = reader.read_u8()?;
+
+        let max_value = (1 << PLANES_COUNT) - 1;
+        if selected_planes > max_value || pixels.iter().flatten().any(|p| *p > max_value) {
+            return Err(ChipError::InvalidState);
+        }
+
+        Ok(Self {
+            screen: Screen { pixels, resolution },
+            selected_planes,
+            must_draw: reader.read_bool()?,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -75,33 +318,37 @@ mod tests {
     #[test]
     fn test_display_empty() {
         let mut display = Display {
-            screen: [[true; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+            screen: Screen {
+                pixels: [[0b11; HIRES_DISPLAY_WIDTH]; HIRES_DISPLAY_HEIGHT],
+                resolution: Resolution::Low,
+            },
+            selected_planes: 0b11,
             must_draw: true,
         };
 
         display.clear();
 
-        assert_eq!(display.screen, [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT]);
+        assert!(display.screen.rows().flatten().all(|pixel| *pixel == 0));
     }
 
     const SPRITE: [u8; 3] = [0b0011_1111, 0b1111_1111, 0b1100_0010];
-    const SPRITE_BOOL: [[bool; 8]; 3] = [
-        [false, false, true, true, true, true, true, true],
-        [true, true, true, true, true, true, true, true],
-        [true, true, false, false, false, false, true, false],
+    const SPRITE_PIXELS: [[u8; 8]; 3] = [
+        [0, 0, 1, 1, 1, 1, 1, 1],
+        [1, 1, 1, 1, 1, 1, 1, 1],
+        [1, 1, 0, 0, 0, 0, 1, 0],
     ];
 
     #[test]
     fn test_sprite_no_colision() {
         let mut display = Display::default();
 
-        let colision = display.draw_sprite(&SPRITE, 0, 0);
+        let colision = display.draw_sprite(&SPRITE, 0, 0, false);
 
         assert!(!colision);
 
-        for (height, sprite_result) in SPRITE_BOOL.into_iter().enumerate() {
+        for (height, sprite_result) in SPRITE_PIXELS.into_iter().enumerate() {
             for (bit, result) in sprite_result.into_iter().enumerate() {
-                assert_eq!(display.screen[height][bit], result, "{bit}, {height}");
+                assert_eq!(display.screen.pixel(bit, height), result, "{bit}, {height}");
             }
         }
     }
@@ -110,14 +357,169 @@ mod tests {
     fn test_sprite_colision() {
         let mut display = Display::default();
 
-        display.draw_sprite(&SPRITE, 0, 0);
-        let colision = display.draw_sprite(&SPRITE, 7, 0);
+        display.draw_sprite(&SPRITE, 0, 0, false);
+        let colision = display.draw_sprite(&SPRITE, 7, 0, false);
 
         assert!(colision);
 
-        let results = [true, false, true];
+        let results = [1, 0, 1];
         for (height, result) in results.into_iter().enumerate() {
-            assert_eq!(display.screen[height][7], result);
+            assert_eq!(display.screen.pixel(7, height), result);
+        }
+    }
+
+    #[test]
+    fn test_sprite_clip() {
+        let mut display = Display::default();
+
+        display.draw_sprite(
+            &SPRITE,
+            DISPLAY_WIDTH as u8 - 4,
+            DISPLAY_HEIGHT as u8 - 1,
+            false,
+        );
+
+        assert_eq!(
+            display.screen.pixel(DISPLAY_WIDTH - 1, DISPLAY_HEIGHT - 1),
+            1
+        );
+        assert_eq!(display.screen.pixel(0, 0), 0);
+        assert_eq!(display.screen.pixel(0, DISPLAY_HEIGHT - 1), 0);
+    }
+
+    #[test]
+    fn test_sprite_wrap() {
+        let mut display = Display::default();
+
+        display.draw_sprite(
+            &SPRITE,
+            DISPLAY_WIDTH as u8 - 4,
+            DISPLAY_HEIGHT as u8 - 1,
+            true,
+        );
+
+        for (bit, result) in SPRITE_PIXELS[0].into_iter().enumerate() {
+            let x = (DISPLAY_WIDTH - 4 + bit) % DISPLAY_WIDTH;
+            assert_eq!(display.screen.pixel(x, DISPLAY_HEIGHT - 1), result, "{bit}");
         }
+        for (bit, result) in SPRITE_PIXELS[1].into_iter().enumerate() {
+            let x = (DISPLAY_WIDTH - 4 + bit) % DISPLAY_WIDTH;
+            assert_eq!(display.screen.pixel(x, 0), result, "{bit}");
+        }
+    }
+
+    #[test]
+    fn test_sprite_start_wraps() {
+        let mut display = Display::default();
+
+        display.draw_sprite(&SPRITE, DISPLAY_WIDTH as u8, DISPLAY_HEIGHT as u8, false);
+
+        assert_eq!(display.screen.pixels[0][0..8], SPRITE_PIXELS[0]);
+    }
+
+    #[test]
+    fn test_hires_sprite() {
+        let mut display = Display::default();
+        display.set_resolution(Resolution::High);
+
+        display.draw_sprite(&SPRITE, DISPLAY_WIDTH as u8, DISPLAY_HEIGHT as u8, false);
+
+        assert_eq!(display.screen.width(), HIRES_DISPLAY_WIDTH);
+        assert_eq!(
+            display.screen.pixels[DISPLAY_HEIGHT][DISPLAY_WIDTH..DISPLAY_WIDTH + 8],
+            SPRITE_PIXELS[0]
+        );
+    }
+
+    #[test]
+    fn test_large_sprite() {
+        let mut display = Display::default();
+        let sprite = [0xFF; 32];
+
+        let colision = display.draw_large_sprite(&sprite, 0, 0, false);
+
+        assert!(!colision);
+        assert!(display
+            .screen
+            .rows()
+            .take(16)
+            .all(|row| row[..16].iter().all(|pixel| *pixel == 1)));
+        assert_eq!(display.screen.pixel(16, 0), 0);
+        assert_eq!(display.screen.pixel(0, 16), 0);
+    }
+
+    #[test]
+    fn test_scroll_down() {
+        let mut display = Display::default();
+        display.draw_sprite(&SPRITE, 0, 0, false);
+
+        display.scroll_down(2);
+
+        assert!(display.screen.pixels[0..2]
+            .iter()
+            .flatten()
+            .all(|p| *p == 0));
+        assert_eq!(display.screen.pixels[2][0..8], SPRITE_PIXELS[0]);
+    }
+
+    #[test]
+    fn test_scroll_horizontal() {
+        let mut display = Display::default();
+        display.draw_sprite(&[0xFF], 0, 0, false);
+
+        display.scroll_right();
+
+        assert_eq!(
+            display.screen.pixels[0][0..12],
+            [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1]
+        );
+
+        display.scroll_left();
+        display.scroll_left();
+
+        assert_eq!(display.screen.pixels[0][0..8], [1, 1, 1, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_scroll_up() {
+        let mut display = Display::default();
+        display.draw_sprite(&SPRITE, 0, 2, false);
+
+        display.scroll_up(2);
+
+        assert_eq!(display.screen.pixels[0][0..8], SPRITE_PIXELS[0]);
+        assert!(display.screen.pixels[3..5]
+            .iter()
+            .flatten()
+            .all(|p| *p == 0));
+    }
+
+    #[test]
+    fn test_two_planes() {
+        let mut display = Display::default();
+        display.select_planes(0b11);
+
+        let colision = display.draw_sprite(&[0b1100_0000, 0b1010_0000], 0, 0, false);
+
+        assert!(!colision);
+        assert_eq!(display.screen.pixels[0][0..3], [0b11, 0b01, 0b10]);
+
+        display.select_planes(0b10);
+        display.clear();
+
+        assert_eq!(display.screen.pixels[0][0..3], [0b01, 0b01, 0b00]);
+    }
+
+    #[test]
+    fn test_plane_colision() {
+        let mut display = Display::default();
+
+        display.select_planes(0b10);
+        display.draw_sprite(&[0xFF], 0, 0, false);
+        display.select_planes(0b01);
+        let colision = display.draw_sprite(&[0xFF], 0, 0, false);
+
+        assert!(!colision);
+        assert_eq!(display.screen.pixel(0, 0), 0b11);
     }
 }
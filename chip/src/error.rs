@@ -0,0 +1,49 @@
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum ChipError {
+    UnknownOpcode { pc: u16, opcode: u16 },
+    StackOverflow,
+    StackUnderflow,
+    MemoryOutOfBounds { addr: usize },
+    RomTooLarge,
+    InvalidState,
+    UnsupportedStateVersion { version: u8 },
+    Io(std::io::Error),
+}
+
+impl Display for ChipError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownOpcode { pc, opcode } => {
+                write!(f, "Unknown opcode {opcode:04X} at {pc:#05X}")
+            }
+            Self::StackOverflow => write!(f, "Stack overflow"),
+            Self::StackUnderflow => write!(f, "Stack underflow"),
+            Self::MemoryOutOfBounds { addr } => {
+                write!(f, "Memory access out of bounds at {addr:#05X}")
+            }
+            Self::RomTooLarge => write!(f, "Rom too big"),
+            Self::InvalidState => write!(f, "Invalid save state"),
+            Self::UnsupportedStateVersion { version } => {
+                write!(f, "Unsupported save state version {version}")
+            }
+            Self::Io(error) => write!(f, "Error reading rom: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ChipError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ChipError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
@@ -1,36 +1,19 @@
-use std::time::{Duration, Instant};
-
-const TIMER_TICK: Duration = Duration::from_micros(1_000_000 / 60);
+use crate::state::{Snapshot, StateReader, StateWriter};
+use crate::ChipError;
 
+// NOTE: Timers are decremented once per frame, at 60 Hz
+#[derive(Default)]
 pub struct Timer {
     register: u8,
-    start_tick_time: Instant,
-}
-
-impl Default for Timer {
-    fn default() -> Self {
-        Self {
-            register: Default::default(),
-            start_tick_time: Instant::now(),
-        }
-    }
 }
 
 impl Timer {
-    pub fn countdown(&mut self) -> bool {
-        if self.register > 0 && self.start_tick_time.elapsed() >= TIMER_TICK {
-            self.register -= 1;
-            self.start_tick_time = Instant::now();
-            return true;
-        }
-
-        false
+    pub const fn countdown(&mut self) {
+        self.register = self.register.saturating_sub(1);
     }
 
-    pub fn set_time(&mut self, time: u8) {
+    pub const fn set_time(&mut self, time: u8) {
         self.register = time;
-
-        self.start_tick_time = Instant::now();
     }
 
     pub const fn get_remaining(&self) -> u8 {
@@ -38,13 +21,28 @@ impl Timer {
     }
 }
 
+impl Snapshot for Timer {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.register);
+    }
+
+    fn load(reader: &mut StateReader) -> Result<Self, ChipError> {
+        Ok(Self {
+            register: reader.read_u8()?,
+        })
+    }
+}
+
+pub const KEYS_COUNT: usize = 16;
+
 #[derive(Default)]
 pub struct Keyboard {
-    keys: [bool; 16],
+    keys: [bool; KEYS_COUNT],
     waiting_input: bool,
     last_pressed: Option<u8>,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Key {
     Key0,
     Key1,
@@ -64,8 +62,36 @@ pub enum Key {
     KeyF,
 }
 
+impl Key {
+    // NOTE: Keys are named by the hex digit they send
+    #[must_use]
+    pub const fn from_digit(digit: u8) -> Option<Self> {
+        let key = match digit {
+            0x0 => Self::Key0,
+            0x1 => Self::Key1,
+            0x2 => Self::Key2,
+            0x3 => Self::Key3,
+            0x4 => Self::Key4,
+            0x5 => Self::Key5,
+            0x6 => Self::Key6,
+            0x7 => Self::Key7,
+            0x8 => Self::Key8,
+            0x9 => Self::Key9,
+            0xA => Self::KeyA,
+            0xB => Self::KeyB,
+            0xC => Self::KeyC,
+            0xD => Self::KeyD,
+            0xE => Self::KeyE,
+            0xF => Self::KeyF,
+            _ => return None,
+        };
+
+        Some(key)
+    }
+}
+
 impl Keyboard {
-    pub fn press_key(&mut self, key: Key) {
+    pub const fn press_key(&mut self, key: Key) {
         let key = key as usize;
         self.keys[key] = true;
 
@@ -74,15 +100,16 @@ impl Keyboard {
         }
     }
 
-    pub fn unpress_key(&mut self, key: Key) {
+    pub const fn unpress_key(&mut self, key: Key) {
         self.keys[key as usize] = false;
     }
 
     pub const fn is_key_pressed(&self, key: u8) -> bool {
-        self.keys[key as usize]
+        // NOTE: Only the lowest nibble selects the key
+        self.keys[(key & 0xF) as usize]
     }
 
-    pub fn get_key(&mut self) -> Option<u8> {
+    pub const fn get_key(&mut self) -> Option<u8> {
         if self.waiting_input && self.last_pressed.is_some() {
             self.waiting_input = false;
 
@@ -97,6 +124,38 @@ impl Keyboard {
     pub const fn is_waiting(&self) -> bool {
         self.waiting_input
     }
+
+    pub const fn keys(&self) -> [bool; KEYS_COUNT] {
+        self.keys
+    }
+}
+
+impl Snapshot for Keyboard {
+    fn save(&self, writer: &mut StateWriter) {
+        for key in self.keys {
+            writer.write_bool(key);
+        }
+        writer.write_bool(self.waiting_input);
+        writer.write_bool(self.last_pressed.is_some());
+        writer.write_u8(self.last_pressed.unwrap_or_default());
+    }
+
+    fn load(reader: &mut StateReader) -> Result<Self, ChipError> {
+        let mut keys = [false; KEYS_COUNT];
+        for key in &mut keys {
+            *key = reader.read_bool()?;
+        }
+
+        let waiting_input = reader.read_bool()?;
+        let has_last_pressed = reader.read_bool()?;
+        let last_pressed = reader.read_u8()?;
+
+        Ok(Self {
+            keys,
+            waiting_input,
+            last_pressed: has_last_pressed.then_some(last_pressed),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -105,6 +164,26 @@ mod tests {
 
     const KEY: u8 = 0xF;
 
+    #[test]
+    fn test_timer_countdown() {
+        let mut timer = Timer::default();
+        timer.set_time(2);
+
+        timer.countdown();
+        assert_eq!(timer.get_remaining(), 1);
+
+        timer.countdown();
+        timer.countdown();
+        assert_eq!(timer.get_remaining(), 0);
+    }
+
+    #[test]
+    fn test_key_from_digit() {
+        assert_eq!(Key::from_digit(0x0), Some(Key::Key0));
+        assert_eq!(Key::from_digit(KEY).map(|key| key as u8), Some(KEY));
+        assert_eq!(Key::from_digit(0x10), None);
+    }
+
     #[test]
     fn test_get_key_not_waiting() {
         let mut keyboard = Keyboard::default();
@@ -1,73 +1,255 @@
+pub mod asm;
+mod audio;
+mod breakpoint;
+pub mod disasm;
 mod display;
+mod error;
 mod hardware;
+mod hash;
 mod memory;
+mod movie;
+pub mod octo;
+mod opcode;
+mod quirks;
+mod screenshot;
 mod stack;
+mod state;
+mod trace;
 
+use audio::Pattern;
 use display::Display;
 use hardware::{Keyboard, Timer};
 use memory::Memory;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use stack::Stack;
+use state::{Snapshot, StateReader, StateWriter, STATE_MAGIC, STATE_VERSION};
 
-use rand::thread_rng;
-
-pub use display::{Screen, DISPLAY_HEIGHT, DISPLAY_WIDTH};
-pub use hardware::Key;
+pub use audio::{AudioState, PATTERN_BITS, PATTERN_SIZE};
+pub use breakpoint::{
+    Access, BreakReason, Breakpoint, Comparison, ParseBreakpointError, Watchpoint,
+};
+pub use display::{
+    Resolution, Screen, DISPLAY_HEIGHT, DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH,
+    PLANES_COUNT,
+};
+pub use error::ChipError;
+pub use hardware::{Key, KEYS_COUNT};
+pub use hash::sha256_hex;
+pub use movie::{Movie, MovieError, MovieInput};
+pub use opcode::{DecodeError, Opcode};
+pub use quirks::Quirks;
+pub use rand::RngCore;
+pub use screenshot::{Palette, DEFAULT_PALETTE, PALETTE_SIZE};
+pub use trace::{ParseTraceError, TraceEntry, TraceHook};
 
 const REGISTERS_COUNT: usize = 16;
-const DEFAULT_ROM_START: u16 = 0x200;
+pub const RPL_FLAGS_COUNT: usize = 16;
+pub const DEFAULT_ROM_START: u16 = 0x200;
 
 pub struct Chip {
     v_registers: [u8; REGISTERS_COUNT],
+    rpl_flags: [u8; RPL_FLAGS_COUNT],
     memory: Memory,
     delay_timer: Timer,
     sound_timer: Timer,
     stack: Stack,
     keyboard: Keyboard,
     display: Display,
+    quirks: Quirks,
+    exited: bool,
+    audio_pattern: Option<Pattern>,
+    pitch: u8,
+    rng: Box<dyn RngCore + Send>,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    cycles: u64,
+    trace_hook: Option<TraceHook>,
 }
 
 pub struct Actions {
     pub draw: bool,
-    pub beep: bool,
+    pub exit: bool,
+    pub break_reason: Option<BreakReason>,
+}
+
+// Copy of the registers, for tools that inspect the chip from outside
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Registers {
+    pub v: [u8; REGISTERS_COUNT],
+    pub i: u16,
+    pub pc: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    // NOTE: Number of return addresses in the stack
+    pub sp: u8,
 }
 
 impl Default for Chip {
     fn default() -> Self {
-        Self::new(DEFAULT_ROM_START)
+        Self::new(DEFAULT_ROM_START, Quirks::default())
     }
 }
 
 impl Chip {
     #[must_use]
-    pub fn new(rom_start: u16) -> Self {
+    pub fn new(rom_start: u16, quirks: Quirks) -> Self {
+        Self::with_rng(rom_start, quirks, StdRng::from_entropy())
+    }
+
+    // NOTE: Chips created with the same seed produce the same random numbers
+    #[must_use]
+    pub fn with_seed(rom_start: u16, quirks: Quirks, seed: u64) -> Self {
+        Self::with_rng(rom_start, quirks, StdRng::seed_from_u64(seed))
+    }
+
+    #[must_use]
+    pub fn with_rng(rom_start: u16, quirks: Quirks, rng: impl RngCore + Send + 'static) -> Self {
         Self {
             v_registers: [0; REGISTERS_COUNT],
+            rpl_flags: [0; RPL_FLAGS_COUNT],
             memory: Memory::new(rom_start),
             delay_timer: Timer::default(),
             sound_timer: Timer::default(),
             stack: Stack::default(),
             keyboard: Keyboard::default(),
             display: Display::default(),
+            quirks,
+            exited: false,
+            audio_pattern: None,
+            pitch: AudioState::default().pitch,
+            rng: Box::new(rng),
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            cycles: 0,
+            trace_hook: None,
         }
     }
 
-    pub fn run_cycle(&mut self) -> Actions {
-        self.process_instruction();
+    pub fn run_cycle(&mut self) -> Result<Actions, ChipError> {
+        let before = self.registers();
+        self.memory.clear_accesses();
+
+        if let Some(hook) = &mut self.trace_hook {
+            if let Ok(opcode) = self.memory.get_current_instruction() {
+                hook(&TraceEntry {
+                    cycle: self.cycles,
+                    opcode,
+                    registers: before,
+                });
+            }
+        }
+
+        self.process_instruction()?;
+        self.cycles += 1;
 
-        self.delay_timer.countdown();
-        let beep = self.sound_timer.countdown();
         let draw = self.display.must_draw();
 
-        Actions { draw, beep }
+        Ok(Actions {
+            draw,
+            exit: self.exited,
+            break_reason: self.break_reason(&before),
+        })
+    }
+
+    // NOTE: Watchpoints are checked first, they were hit by the instruction
+    // that just ran while breakpoints are about the next one
+    fn break_reason(&self, before: &Registers) -> Option<BreakReason> {
+        for &(access, start, len) in self.memory.accesses() {
+            for watchpoint in &self.watchpoints {
+                if let Some(address) = watchpoint.hit(access, start, len) {
+                    return Some(BreakReason::Watchpoint {
+                        watchpoint: watchpoint.clone(),
+                        access,
+                        address,
+                    });
+                }
+            }
+        }
+
+        if self.breakpoints.is_empty() {
+            return None;
+        }
+
+        let after = self.registers();
+        let next = self.memory.get_current_instruction().ok();
+
+        self.breakpoints
+            .iter()
+            .find(|breakpoint| breakpoint.is_hit(before, &after, next))
+            .map(|breakpoint| BreakReason::Breakpoint(*breakpoint))
+    }
+
+    // NOTE: A breakpoint at the current PC is only hit after coming back to it
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    // NOTE: Returns false if there was no such breakpoint
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|other| other != breakpoint);
+
+        self.breakpoints.len() != count
+    }
+
+    #[must_use]
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|other| other != watchpoint);
+
+        self.watchpoints.len() != count
     }
 
-    pub fn load_rom(&mut self, path: String) -> Result<(), std::io::Error> {
+    #[must_use]
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // NOTE: Instructions run since the chip was created, loading states does
+    // not change it
+    #[must_use]
+    pub const fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn set_trace_hook(&mut self, hook: impl FnMut(&TraceEntry) + Send + 'static) {
+        self.trace_hook = Some(Box::new(hook));
+    }
+
+    // NOTE: Lets the hook keep tracing in another chip
+    pub fn take_trace_hook(&mut self) -> Option<TraceHook> {
+        self.trace_hook.take()
+    }
+
+    // NOTE: Must be called at 60 Hz, once per frame, independently of the
+    // number of cycles run in it
+    pub const fn tick_timers(&mut self) {
+        self.delay_timer.countdown();
+        self.sound_timer.countdown();
+    }
+
+    pub fn load_rom(&mut self, path: String) -> Result<(), ChipError> {
         let rom = std::fs::read(path)?;
 
-        self.memory.load_rom(&rom);
+        self.load_rom_bytes(&rom)
+    }
 
-        Ok(())
+    // NOTE: For roms that are not read from a file, like compiled sources
+    pub fn load_rom_bytes(&mut self, rom: &[u8]) -> Result<(), ChipError> {
+        self.memory.load_rom(rom)
     }
 
     #[must_use]
@@ -75,11 +257,151 @@ impl Chip {
         self.display.screen()
     }
 
-    pub fn press_key(&mut self, key: Key) {
+    // NOTE: The random number generator is not part of the state
+    #[must_use]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::default();
+
+        writer.write_bytes(&STATE_MAGIC);
+        writer.write_u8(STATE_VERSION);
+
+        writer.write_bytes(&self.v_registers);
+        writer.write_bytes(&self.rpl_flags);
+        self.memory.save(&mut writer);
+        self.delay_timer.save(&mut writer);
+        self.sound_timer.save(&mut writer);
+        self.stack.save(&mut writer);
+        self.keyboard.save(&mut writer);
+        self.display.save(&mut writer);
+        writer.write_bool(self.exited);
+        writer.write_bool(self.audio_pattern.is_some());
+        writer.write_bytes(&self.audio_pattern.unwrap_or_default());
+        writer.write_u8(self.pitch);
+
+        writer.into_bytes()
+    }
+
+    // NOTE: The chip is left untouched if the state is invalid
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), ChipError> {
+        let mut reader = StateReader::new(state);
+
+        if reader.read_array()? != STATE_MAGIC {
+            return Err(ChipError::InvalidState);
+        }
+
+        let version = reader.read_u8()?;
+        if version != STATE_VERSION {
+            return Err(ChipError::UnsupportedStateVersion { version });
+        }
+
+        let v_registers = reader.read_array()?;
+        let rpl_flags = reader.read_array()?;
+        let memory = Memory::load(&mut reader)?;
+        let delay_timer = Timer::load(&mut reader)?;
+        let sound_timer = Timer::load(&mut reader)?;
+        let stack = Stack::load(&mut reader)?;
+        let keyboard = Keyboard::load(&mut reader)?;
+        let display = Display::load(&mut reader)?;
+        let exited = reader.read_bool()?;
+        let has_audio_pattern = reader.read_bool()?;
+        let audio_pattern = reader.read_array()?;
+        let pitch = reader.read_u8()?;
+
+        if !reader.is_empty() {
+            return Err(ChipError::InvalidState);
+        }
+
+        self.v_registers = v_registers;
+        self.rpl_flags = rpl_flags;
+        self.memory = memory;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.stack = stack;
+        self.keyboard = keyboard;
+        self.display = display;
+        self.exited = exited;
+        self.audio_pattern = has_audio_pattern.then_some(audio_pattern);
+        self.pitch = pitch;
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub const fn audio_state(&self) -> AudioState {
+        AudioState {
+            pattern: self.audio_pattern,
+            pitch: self.pitch,
+            playing: self.sound_timer.get_remaining() > 0,
+        }
+    }
+
+    #[must_use]
+    pub const fn rpl_flags(&self) -> &[u8; RPL_FLAGS_COUNT] {
+        &self.rpl_flags
+    }
+
+    pub const fn set_rpl_flags(&mut self, flags: [u8; RPL_FLAGS_COUNT]) {
+        self.rpl_flags = flags;
+    }
+
+    #[must_use]
+    pub const fn registers(&self) -> Registers {
+        Registers {
+            v: self.v_registers,
+            i: self.memory.i_register,
+            pc: self.memory.pc_register,
+            delay_timer: self.delay_timer.get_remaining(),
+            sound_timer: self.sound_timer.get_remaining(),
+            sp: self.stack.pointer() as u8,
+        }
+    }
+
+    // NOTE: The chip is left untouched if the stack pointer is too big
+    pub fn set_registers(&mut self, registers: &Registers) -> Result<(), ChipError> {
+        self.stack.set_pointer(usize::from(registers.sp))?;
+
+        self.v_registers = registers.v;
+        self.memory.i_register = registers.i;
+        self.memory.pc_register = registers.pc;
+        self.delay_timer.set_time(registers.delay_timer);
+        self.sound_timer.set_time(registers.sound_timer);
+
+        Ok(())
+    }
+
+    pub fn write_memory(&mut self, address: u16, bytes: &[u8]) -> Result<(), ChipError> {
+        self.memory.write(address, bytes)
+    }
+
+    // NOTE: Return addresses, the oldest first
+    #[must_use]
+    pub fn stack(&self) -> &[u16] {
+        self.stack.values()
+    }
+
+    #[must_use]
+    pub const fn pressed_keys(&self) -> [bool; KEYS_COUNT] {
+        self.keyboard.keys()
+    }
+
+    #[must_use]
+    pub const fn memory(&self) -> &[u8] {
+        self.memory.as_slice()
+    }
+
+    // NOTE: None if the bytes at PC are not a valid instruction
+    #[must_use]
+    pub fn current_opcode(&self) -> Option<Opcode> {
+        let raw = self.memory.get_current_instruction().ok()?;
+
+        Opcode::decode(raw).ok()
+    }
+
+    pub const fn press_key(&mut self, key: Key) {
         self.keyboard.press_key(key);
     }
 
-    pub fn unpress_key(&mut self, key: Key) {
+    pub const fn unpress_key(&mut self, key: Key) {
         self.keyboard.unpress_key(key);
     }
 
@@ -88,163 +410,712 @@ impl Chip {
         self.v_registers[0xF] = u8::from(condition);
     }
 
-    #[allow(clippy::too_many_lines)]
-    fn process_instruction(&mut self) {
-        let instruction = self.memory.get_current_instruction();
+    #[inline]
+    const fn v(&self, register: u8) -> u8 {
+        self.v_registers[register as usize]
+    }
 
-        let nibbles = instruction.get_nibbles();
+    #[inline]
+    const fn set_v(&mut self, register: u8, value: u8) {
+        self.v_registers[register as usize] = value;
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn process_instruction(&mut self) -> Result<(), ChipError> {
+        let raw = self.memory.get_current_instruction()?;
 
-        let v_x = self.v_registers[instruction.x];
-        let v_y = self.v_registers[instruction.y];
+        let opcode = Opcode::decode(raw).map_err(|error| ChipError::UnknownOpcode {
+            pc: self.memory.pc_register,
+            opcode: error.opcode,
+        })?;
 
         let mut jump = false;
 
-        match nibbles {
-            // 00E0 - CLS
-            (0x0, 0x0, 0xE, 0x0) => self.display.clear(),
-            // 00EE - RET
-            (0x0, 0x0, 0xE, 0xE) => {
-                self.memory.pc_register = self.stack.pop();
-            }
-            // 0nnn - SYS addr - Ignored
-            (0x0, _, _, _) => (),
-            // 1nnn - JP addr
-            (0x1, _, _, _) => {
-                self.memory.pc_register = instruction.nnn;
+        match opcode {
+            Opcode::Cls => self.display.clear(),
+            Opcode::Ret => {
+                self.memory.pc_register = self.stack.pop()?;
+            }
+            Opcode::ScrollDown(n) => self.display.scroll_down(n as usize),
+            Opcode::ScrollUp(n) => self.display.scroll_up(n as usize),
+            Opcode::ScrollRight => self.display.scroll_right(),
+            Opcode::ScrollLeft => self.display.scroll_left(),
+            Opcode::Exit => {
+                self.exited = true;
+                jump = true;
+            }
+            Opcode::Low => self.display.set_resolution(Resolution::Low),
+            Opcode::High => self.display.set_resolution(Resolution::High),
+            // NOTE: Machine code routines are ignored
+            Opcode::Sys(_) => (),
+            Opcode::Jp(addr) => {
+                self.memory.pc_register = addr;
                 jump = true;
             }
-            // 2nnn - CALL addr
-            (0x2, _, _, _) => {
-                self.stack.push(self.memory.pc_register);
-                self.memory.pc_register = instruction.nnn;
+            Opcode::Call(addr) => {
+                self.stack.push(self.memory.pc_register)?;
+                self.memory.pc_register = addr;
                 jump = true;
             }
-            // 3xkk - SE Vx, byte
-            (0x3, _, _, _) => {
-                if v_x == instruction.kk {
-                    self.memory.increase_pc();
+            Opcode::SeByte { x, byte } => {
+                if self.v(x) == byte {
+                    self.memory.skip_instruction();
+                }
+            }
+            Opcode::SneByte { x, byte } => {
+                if self.v(x) != byte {
+                    self.memory.skip_instruction();
+                }
+            }
+            Opcode::SeReg { x, y } => {
+                if self.v(x) == self.v(y) {
+                    self.memory.skip_instruction();
+                }
+            }
+            Opcode::SaveRange { x, y } => {
+                let bytes: Vec<u8> = register_range(x, y).map(|r| self.v(r)).collect();
+                self.memory.load_bytes_to_memory(&bytes)?;
+            }
+            Opcode::LoadRange { x, y } => {
+                let count = x.abs_diff(y) + 1;
+                let bytes = self.memory.get_bytes(count)?;
+                for (register, byte) in register_range(x, y).zip(bytes) {
+                    self.v_registers[register as usize] = *byte;
+                }
+            }
+            Opcode::LdByte { x, byte } => self.set_v(x, byte),
+            Opcode::AddByte { x, byte } => self.set_v(x, self.v(x).wrapping_add(byte)),
+            Opcode::LdReg { x, y } => self.set_v(x, self.v(y)),
+            Opcode::Or { x, y } => {
+                self.set_v(x, self.v(x) | self.v(y));
+                if self.quirks.logic_resets_vf {
+                    self.set_flag(false);
                 }
             }
-            // 4xkk - SNE Vx, byte
-            (0x4, _, _, _) => {
-                if v_x != instruction.kk {
-                    self.memory.increase_pc();
+            Opcode::And { x, y } => {
+                self.set_v(x, self.v(x) & self.v(y));
+                if self.quirks.logic_resets_vf {
+                    self.set_flag(false);
                 }
             }
-            // 5xy0 - SE Vx, Vy
-            (0x5, _, _, 0x0) => {
-                if v_x == v_y {
-                    self.memory.increase_pc();
+            Opcode::Xor { x, y } => {
+                self.set_v(x, self.v(x) ^ self.v(y));
+                if self.quirks.logic_resets_vf {
+                    self.set_flag(false);
                 }
             }
-            // 6xkk - LD Vx, byte
-            (0x6, _, _, _) => self.v_registers[instruction.x] = instruction.kk,
-            // 7xkk - ADD Vx, byte
-            (0x7, _, _, _) => self.v_registers[instruction.x] = v_x.wrapping_add(instruction.kk),
-            // 8xy0 - LD Vx, Vy
-            (0x8, _, _, 0x0) => self.v_registers[instruction.x] = v_y,
-
-            // 8xy1 - OR Vx, Vy
-            (0x8, _, _, 0x1) => self.v_registers[instruction.x] |= v_y,
-            // 8xy2 - AND Vx, Vy
-            (0x8, _, _, 0x2) => self.v_registers[instruction.x] &= v_y,
-            // 8xy3 - XOR Vx, Vy
-            (0x8, _, _, 0x3) => self.v_registers[instruction.x] ^= v_y,
-            // 8xy4 - ADD Vx, Vy
-            (0x8, _, _, 0x4) => {
-                self.set_flag(u16::from(v_x) + u16::from(v_y) > 255);
-                self.v_registers[instruction.x] = v_x.wrapping_add(v_y);
-            }
-            // 8xy5 - SUB Vx, Vy
-            (0x8, _, _, 0x5) => {
-                self.set_flag(v_x > v_y);
-                self.v_registers[instruction.x] = v_x.wrapping_sub(v_y);
-            }
-            // 8xy6 - SHR Vx {, Vy}
-            (0x8, _, _, 0x6) => {
-                self.set_flag(v_x & 0x01 == 1);
-                self.v_registers[instruction.x] = v_x >> 1;
-            }
-            // 8xy7 - SUBN Vx, Vy
-            (0x8, _, _, 0x7) => {
-                self.set_flag(v_y > v_x);
-                self.v_registers[instruction.x] = v_y.wrapping_sub(v_x);
-            }
-            // 8xyE - SHL Vx {, Vy}
-            (0x8, _, _, 0xE) => {
-                self.set_flag(v_x >> 7 == 1);
-                self.v_registers[instruction.x] = v_x << 1;
-            }
-            // 9xy0 - SNE Vx, Vy
-            (0x9, _, _, 0x0) => {
-                if v_x != v_y {
-                    self.memory.increase_pc();
+            // NOTE: The flag is written after the result, so it is what is left
+            // in VF when VF is the destination
+            Opcode::AddReg { x, y } => {
+                let (v_x, v_y) = (self.v(x), self.v(y));
+                let (sum, carry) = v_x.overflowing_add(v_y);
+                self.set_v(x, sum);
+                self.set_flag(carry);
+            }
+            Opcode::Sub { x, y } => {
+                let (v_x, v_y) = (self.v(x), self.v(y));
+                self.set_v(x, v_x.wrapping_sub(v_y));
+                self.set_flag(v_x >= v_y);
+            }
+            Opcode::Shr { x, y } => {
+                let value = if self.quirks.shift_uses_vy {
+                    self.v(y)
+                } else {
+                    self.v(x)
+                };
+                self.set_v(x, value >> 1);
+                self.set_flag(value & 0x01 == 1);
+            }
+            Opcode::Subn { x, y } => {
+                let (v_x, v_y) = (self.v(x), self.v(y));
+                self.set_v(x, v_y.wrapping_sub(v_x));
+                self.set_flag(v_y >= v_x);
+            }
+            Opcode::Shl { x, y } => {
+                let value = if self.quirks.shift_uses_vy {
+                    self.v(y)
+                } else {
+                    self.v(x)
+                };
+                self.set_v(x, value << 1);
+                self.set_flag(value >> 7 == 1);
+            }
+            Opcode::SneReg { x, y } => {
+                if self.v(x) != self.v(y) {
+                    self.memory.skip_instruction();
                 }
             }
-            // Annn - LD I, addr
-            (0xA, _, _, _) => self.memory.i_register = instruction.nnn,
-            // Bnnn - JP V0, addr
-            (0xB, _, _, _) => {
-                self.memory.pc_register = instruction.nnn + u16::from(self.v_registers[0x0]);
+            Opcode::LdI(addr) => self.memory.i_register = addr,
+            Opcode::JpV0(addr) => {
+                let register = if self.quirks.jump_uses_vx {
+                    (addr >> 8) as u8
+                } else {
+                    0x0
+                };
+                self.memory.pc_register = addr + u16::from(self.v(register));
                 jump = true;
             }
-            // Cxkk - RND Vx, byte
-            (0xC, _, _, _) => {
-                self.v_registers[instruction.x] = thread_rng().gen::<u8>() & instruction.kk;
+            Opcode::Rnd { x, byte } => {
+                let value = self.rng.gen::<u8>() & byte;
+                self.set_v(x, value);
             }
-            // Dxyn - DRW Vx, Vy, nibble
-            (0xD, _, _, _) => {
-                let sprite = self.memory.get_bytes(instruction.n);
-                let colision = self.display.draw_sprite(sprite, v_x, v_y);
+            Opcode::Drw { x, y, n } => {
+                let (v_x, v_y) = (self.v(x), self.v(y));
+                let planes = self.display.selected_planes_count();
+                let wrap = self.quirks.sprite_wraps;
+
+                // NOTE: Dxy0 draws a 16x16 sprite. Without a selected plane
+                // nothing is drawn and no sprite bytes are read
+                let colision = if planes == 0 {
+                    false
+                } else if n == 0 {
+                    let sprite = self.memory.get_bytes((32 * planes) as u8)?;
+                    self.display.draw_large_sprite(sprite, v_x, v_y, wrap)
+                } else {
+                    let sprite = self.memory.get_bytes((n as usize * planes) as u8)?;
+                    self.display.draw_sprite(sprite, v_x, v_y, wrap)
+                };
                 self.set_flag(colision);
             }
-            // Ex9E - SKP Vx
-            (0xE, _, 0x9, 0xE) => {
-                if self.keyboard.is_key_pressed(v_x) {
-                    self.memory.increase_pc();
+            Opcode::Skp(x) => {
+                if self.keyboard.is_key_pressed(self.v(x)) {
+                    self.memory.skip_instruction();
                 }
             }
-            // ExA1 - SKNP Vx
-            (0xE, _, 0xA, 0x1) => {
-                if !self.keyboard.is_key_pressed(v_x) {
-                    self.memory.increase_pc();
+            Opcode::Sknp(x) => {
+                if !self.keyboard.is_key_pressed(self.v(x)) {
+                    self.memory.skip_instruction();
                 }
             }
-            // Fx07 - LD Vx, DT
-            (0xF, _, 0x0, 0x7) => {
-                self.v_registers[instruction.x] = self.delay_timer.get_remaining();
+            Opcode::LdILong => self.memory.load_long_address()?,
+            Opcode::Plane(n) => self.display.select_planes(n),
+            Opcode::Audio => {
+                let bytes = self.memory.get_bytes(PATTERN_SIZE as u8)?;
+                let mut pattern = [0; PATTERN_SIZE];
+                pattern.copy_from_slice(bytes);
+                self.audio_pattern = Some(pattern);
             }
-            // Fx0A - LD Vx, K
-            (0xF, _, 0x0, 0xA) => {
+            Opcode::LdVxDt(x) => self.set_v(x, self.delay_timer.get_remaining()),
+            Opcode::LdVxK(x) => {
                 if let Some(key) = self.keyboard.get_key() {
-                    self.v_registers[instruction.x] = key;
+                    self.set_v(x, key);
+                }
+            }
+            Opcode::LdDtVx(x) => self.delay_timer.set_time(self.v(x)),
+            Opcode::LdStVx(x) => self.sound_timer.set_time(self.v(x)),
+            Opcode::AddIVx(x) => {
+                self.memory.i_register = self.memory.i_register.wrapping_add(u16::from(self.v(x)));
+            }
+            Opcode::LdF(x) => self.memory.load_default_sprite(self.v(x)),
+            Opcode::LdHf(x) => self.memory.load_hires_sprite(self.v(x)),
+            Opcode::LdB(x) => self.memory.load_decimal_to_memory(self.v(x))?,
+            Opcode::Pitch(x) => self.pitch = self.v(x),
+            Opcode::LdIVx(x) => {
+                let count = usize::from(x) + 1;
+                self.memory
+                    .load_bytes_to_memory(&self.v_registers[..count])?;
+                if self.quirks.load_store_increments_i {
+                    self.memory.i_register = self.memory.i_register.wrapping_add(count as u16);
+                }
+            }
+            Opcode::LdVxI(x) => {
+                let count = usize::from(x) + 1;
+                let bytes = self.memory.get_bytes(count as u8)?;
+                self.v_registers[..count].copy_from_slice(bytes);
+                if self.quirks.load_store_increments_i {
+                    self.memory.i_register = self.memory.i_register.wrapping_add(count as u16);
                 }
             }
-            // Fx15 - LD DT, Vx
-            (0xF, _, 0x1, 0x5) => self.delay_timer.set_time(v_x),
-            // Fx18 - LD ST, Vx
-            (0xF, _, 0x1, 0x8) => self.sound_timer.set_time(v_x),
-            // Fx1E - ADD I, Vx
-            (0xF, _, 0x1, 0xE) => self.memory.i_register += u16::from(v_x),
-            // Fx29 - LD F, Vx
-            (0xF, _, 0x2, 0x9) => self.memory.load_default_sprite(v_x),
-            // Fx33 - LD B, Vx
-            (0xF, _, 0x3, 0x3) => self.memory.load_decimal_to_memory(v_x),
-            // Fx55 - LD [I], Vx
-            (0xF, _, 0x5, 0x5) => {
-                let bytes = &self.v_registers[0..instruction.x];
-                self.memory.load_bytes_to_memory(bytes);
-            }
-            // Fx65 - LD Vx, [I]
-            (0xF, _, 0x6, 0x5) => {
-                let bytes = self.memory.get_bytes(1 + instruction.x as u8);
-                self.v_registers[0..=instruction.x].copy_from_slice(bytes);
-            }
-            _ => panic!("Unknown instruction"),
-        };
+            Opcode::LdRVx(x) => {
+                let count = usize::from(x) + 1;
+                self.rpl_flags[..count].copy_from_slice(&self.v_registers[..count]);
+            }
+            Opcode::LdVxR(x) => {
+                let count = usize::from(x) + 1;
+                self.v_registers[..count].copy_from_slice(&self.rpl_flags[..count]);
+            }
+        }
 
         if !self.keyboard.is_waiting() && !jump {
             self.memory.increase_pc();
         }
+
+        Ok(())
+    }
+}
+
+// NOTE: Registers from x to y, descending when x is bigger than y
+fn register_range(x: u8, y: u8) -> impl Iterator<Item = u8> {
+    let ascending = x <= y;
+    let (low, high) = if ascending { (x, y) } else { (y, x) };
+
+    (low..=high).map(move |i| if ascending { i } else { high + low - i })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chip_with_program(quirks: Quirks, program: &[u8]) -> Chip {
+        let mut chip = Chip::new(DEFAULT_ROM_START, quirks);
+        chip.memory.load_rom(program).expect("Valid program");
+        chip
+    }
+
+    fn run(chip: &mut Chip, cycles: usize) {
+        for _ in 0..cycles {
+            chip.run_cycle().expect("Valid instruction");
+        }
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let mut chip = chip_with_program(Quirks::default(), &[0xFF, 0xFF]);
+
+        let result = chip.run_cycle();
+
+        assert!(matches!(
+            result,
+            Err(ChipError::UnknownOpcode {
+                pc: DEFAULT_ROM_START,
+                opcode: 0xFFFF
+            })
+        ));
+    }
+
+    #[test]
+    fn test_hires_mode() {
+        // HIGH - LOW
+        let program = [0x00, 0xFF, 0x00, 0xFE];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 1);
+        assert_eq!(chip.screen().resolution(), Resolution::High);
+        run(&mut chip, 1);
+        assert_eq!(chip.screen().resolution(), Resolution::Low);
+    }
+
+    #[test]
+    fn test_rpl_flags() {
+        // LD V0, 0x12 - LD V1, 0x34 - LD R, V1 - LD V0, 0x00 - LD V1, R
+        let program = [0x60, 0x12, 0x61, 0x34, 0xF1, 0x75, 0x60, 0x00, 0xF1, 0x85];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 5);
+
+        assert_eq!(chip.rpl_flags()[0..2], [0x12, 0x34]);
+        assert_eq!(chip.v_registers[0..2], [0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut chip = chip_with_program(Quirks::default(), &[0x00, 0xFD]);
+
+        let actions = chip.run_cycle().expect("Valid instruction");
+
+        assert!(actions.exit);
+    }
+
+    #[test]
+    fn test_inspect() {
+        // CALL 0x204 - LD V3, 0x42 - LD I, 0x300 - LD DT, V3
+        let program = [0x22, 0x04, 0x00, 0x00, 0x63, 0x42, 0xA3, 0x00, 0xF3, 0x15];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 4);
+        chip.press_key(Key::KeyA);
+
+        let registers = chip.registers();
+        assert_eq!(registers.v[0x3], 0x42);
+        assert_eq!(registers.i, 0x300);
+        assert_eq!(registers.pc, 0x20A);
+        assert_eq!(registers.delay_timer, 0x42);
+        assert_eq!(registers.sp, 1);
+        assert_eq!(chip.stack(), [0x200]);
+        assert!(chip.pressed_keys()[0xA]);
+        assert_eq!(chip.memory()[0x204], 0x63);
+        assert_eq!(chip.current_opcode(), Some(Opcode::Sys(0)));
+    }
+
+    #[test]
+    fn test_set_registers() {
+        let mut chip = Chip::default();
+
+        let mut registers = chip.registers();
+        registers.v[0xA] = 0x12;
+        registers.i = 0x345;
+        registers.pc = 0x300;
+        registers.sp = 2;
+        chip.set_registers(&registers).expect("Valid registers");
+        chip.write_memory(0x300, &[0x00, 0xEE])
+            .expect("Address inside memory");
+
+        assert_eq!(chip.registers(), registers);
+        run(&mut chip, 1);
+        assert_eq!(chip.registers().sp, 1);
+
+        registers.sp = 17;
+        assert!(chip.set_registers(&registers).is_err());
+        assert!(chip.write_memory(0xFFFF, &[0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // LD V3, 0x10 - LD V0, 0x01 - DRW V0, V0, 1 - JP 0x200
+        let program = [0x63, 0x10, 0x60, 0x01, 0xD0, 0x01, 0x12, 0x00];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        chip.add_breakpoint(Breakpoint::Address(0x206));
+        chip.add_breakpoint("V3 == 0x10".parse().expect("Valid breakpoint"));
+        chip.add_breakpoint("Dxyn".parse().expect("Valid breakpoint"));
+
+        let reasons: Vec<_> = (0..6)
+            .map(|_| chip.run_cycle().expect("Valid instruction").break_reason)
+            .collect();
+
+        assert_eq!(
+            reasons,
+            [
+                Some(BreakReason::Breakpoint(Breakpoint::Register {
+                    register: 3,
+                    comparison: Comparison::Equal,
+                    value: 0x10
+                })),
+                Some(BreakReason::Breakpoint(Breakpoint::Opcode {
+                    mask: 0xF000,
+                    value: 0xD000
+                })),
+                Some(BreakReason::Breakpoint(Breakpoint::Address(0x206))),
+                None,
+                // NOTE: The register condition stays true, it is not hit again
+                None,
+                Some(BreakReason::Breakpoint(Breakpoint::Opcode {
+                    mask: 0xF000,
+                    value: 0xD000
+                })),
+            ]
+        );
+
+        assert!(chip.remove_breakpoint(&Breakpoint::Address(0x206)));
+        assert!(!chip.remove_breakpoint(&Breakpoint::Address(0x206)));
+        assert_eq!(chip.breakpoints().len(), 2);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        // LD I, 0x300 - LD V0, 0x7B - LD B, V0 - LD [I], V1 - DRW V0, V0, 2
+        let program = [0xA3, 0x00, 0x60, 0x7B, 0xF0, 0x33, 0xF1, 0x55, 0xD0, 0x02];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        chip.add_watchpoint("0x302:w".parse().expect("Valid watchpoint"));
+        chip.add_watchpoint("0x301-0x310:r".parse().expect("Valid watchpoint"));
+
+        let reasons: Vec<_> = (0..5)
+            .map(|_| chip.run_cycle().expect("Valid instruction").break_reason)
+            .map(|reason| match reason {
+                Some(BreakReason::Watchpoint {
+                    access, address, ..
+                }) => Some((access, address)),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            reasons,
+            [
+                None,
+                None,
+                Some((Access::Write, 0x302)),
+                None,
+                Some((Access::Read, 0x301)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_hook() {
+        use std::sync::{Arc, Mutex};
+
+        // LD V0, 0x12 - JP 0x200
+        let program = [0x60, 0x12, 0x12, 0x00];
+        let mut chip = chip_with_program(Quirks::default(), &program);
+
+        let entries = Arc::new(Mutex::new(Vec::new()));
+        let traced = Arc::clone(&entries);
+        chip.set_trace_hook(move |entry| traced.lock().expect("Not poisoned").push(*entry));
+        let traced = || entries.lock().expect("Not poisoned").clone();
+        run(&mut chip, 3);
+
+        let opcodes: Vec<(u64, u16, u16)> = traced()
+            .iter()
+            .map(|entry| (entry.cycle, entry.registers.pc, entry.opcode))
+            .collect();
+        assert_eq!(
+            opcodes,
+            [(0, 0x200, 0x6012), (1, 0x202, 0x1200), (2, 0x200, 0x6012)]
+        );
+        assert_eq!(traced()[0].registers.v[0], 0);
+        assert_eq!(traced()[1].registers.v[0], 0x12);
+        assert_eq!(chip.cycles(), 3);
+
+        assert!(chip.take_trace_hook().is_some());
+        run(&mut chip, 1);
+        assert_eq!(traced().len(), 3);
+    }
+
+    #[test]
+    fn test_long_load() {
+        // LD I, long 0x1234 - LD V0, 0x01
+        let program = [0xF0, 0x00, 0x12, 0x34, 0x60, 0x01];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 2);
+
+        assert_eq!(chip.memory.i_register, 0x1234);
+        assert_eq!(chip.v_registers[0x0], 0x01);
+    }
+
+    #[test]
+    fn test_draw_without_planes() {
+        // PLANE 0 - DRW V0, V0, 1
+        let mut chip = chip_with_program(Quirks::xo_chip(), &[0xF0, 0x01, 0xD0, 0x01]);
+        chip.v_registers[0xF] = 1;
+        run(&mut chip, 2);
+
+        assert_eq!(chip.v_registers[0xF], 0);
+        assert!(chip.memory.accesses().is_empty());
+    }
+
+    #[test]
+    fn test_skip_long_load() {
+        // SE V0, 0x00 - LD I, long 0x1234 - LD V1, 0x01
+        let program = [0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x61, 0x01];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 2);
+
+        assert_eq!(chip.memory.i_register, 0);
+        assert_eq!(chip.v_registers[0x1], 0x01);
+    }
+
+    #[test]
+    fn test_register_range_save_load() {
+        // LD V1, 0x11 - LD V2, 0x22 - LD I, 0x300 - LD [I], V2 - V1 - LD V1 - V2, [I]
+        let program = [0x61, 0x11, 0x62, 0x22, 0xA3, 0x00, 0x52, 0x12, 0x51, 0x23];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 5);
+
+        assert_eq!(chip.memory.get_bytes(2).ok(), Some(&[0x22, 0x11][..]));
+        assert_eq!(chip.v_registers[1..=2], [0x22, 0x11]);
+        assert_eq!(chip.memory.i_register, 0x300);
+    }
+
+    #[test]
+    fn test_audio_state() {
+        // LD I, 0x300 - AUDIO - LD V0, 0x70 - PITCH V0 - LD ST, V0
+        let program = [0xA3, 0x00, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A, 0xF0, 0x18];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        assert_eq!(chip.audio_state(), AudioState::default());
+
+        run(&mut chip, 5);
+
+        let audio = chip.audio_state();
+        assert_eq!(audio.pattern, Some([0; PATTERN_SIZE]));
+        assert_eq!(audio.pitch, 0x70);
+        assert!(audio.playing);
+    }
+
+    #[test]
+    fn test_tick_timers() {
+        // LD V0, 0x02 - LD DT, V0 - LD ST, V0 - LD V1, DT
+        let program = [0x60, 0x02, 0xF0, 0x15, 0xF0, 0x18, 0xF1, 0x07];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 3);
+
+        chip.tick_timers();
+        assert!(chip.audio_state().playing);
+
+        chip.tick_timers();
+        assert!(!chip.audio_state().playing);
+
+        run(&mut chip, 1);
+        assert_eq!(chip.v_registers[0x1], 0);
+    }
+
+    #[test]
+    fn test_seeded_random() {
+        // RND V0, 0xFF - RND V1, 0xFF - RND V2, 0xFF
+        let program = [0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF];
+
+        let mut first = Chip::with_seed(DEFAULT_ROM_START, Quirks::default(), 42);
+        let mut second = Chip::with_seed(DEFAULT_ROM_START, Quirks::default(), 42);
+        for chip in [&mut first, &mut second] {
+            chip.memory.load_rom(&program).expect("Valid program");
+            run(chip, 3);
+        }
+
+        assert_eq!(first.v_registers[0..3], second.v_registers[0..3]);
+    }
+
+    #[test]
+    fn test_save_load_state() {
+        // LD V0, 0x12 - LD I, 0x300 - LD [I], V0 - HIGH - CALL 0x20A - JP 0x20A
+        let program = [
+            0x60, 0x12, 0xA3, 0x00, 0xF0, 0x55, 0x00, 0xFF, 0x22, 0x0A, 0x12, 0x0A,
+        ];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 5);
+        let state = chip.save_state();
+
+        let mut loaded = Chip::default();
+        loaded.load_state(&state).expect("Valid state");
+
+        assert_eq!(loaded.v_registers, chip.v_registers);
+        assert_eq!(loaded.memory.pc_register, 0x20A);
+        assert_eq!(loaded.memory.i_register, 0x300);
+        assert_eq!(loaded.memory.get_bytes(1).ok(), Some(&[0x12][..]));
+        assert_eq!(loaded.screen().resolution(), Resolution::High);
+        assert_eq!(loaded.save_state()[..], state[..]);
+        assert_eq!(loaded.stack.pop().ok(), Some(0x208));
+    }
+
+    #[test]
+    fn test_load_invalid_state() {
+        let mut chip = chip_with_program(Quirks::default(), &[0x60, 0x12]);
+        run(&mut chip, 1);
+        let mut state = chip.save_state();
+
+        assert!(matches!(
+            chip.load_state(&state[..state.len() - 1]),
+            Err(ChipError::InvalidState)
+        ));
+
+        state[STATE_MAGIC.len()] = STATE_VERSION + 1;
+        assert!(matches!(
+            chip.load_state(&state),
+            Err(ChipError::UnsupportedStateVersion { .. })
+        ));
+
+        assert!(matches!(
+            chip.load_state(b"NOPE"),
+            Err(ChipError::InvalidState)
+        ));
+        assert_eq!(chip.v_registers[0x0], 0x12);
+    }
+
+    // NOTE: Runs 8xyn with Vx = a and V1 = b, where x is VF or V0. Returns Vx
+    // and VF, which starts as 0xAA when it is not the destination
+    fn arithmetic(n: u8, a: u8, b: u8, into_vf: bool) -> (u8, u8) {
+        let x = if into_vf { 0xF } else { 0x0 };
+        // LD VF, 0xAA - LD Vx, a - LD V1, b - 8x1n
+        let program = [0x6F, 0xAA, 0x60 | x, a, 0x61, b, 0x80 | x, 0x10 | n];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 4);
+        (chip.v_registers[x as usize], chip.v_registers[0xF])
+    }
+
+    #[test]
+    fn test_add_flag() {
+        assert_eq!(arithmetic(0x4, 0x10, 0x02, false), (0x12, 0));
+        assert_eq!(arithmetic(0x4, 0xFF, 0x01, false), (0x00, 1));
+
+        // NOTE: The flag is written last, so it replaces the sum in VF
+        assert_eq!(arithmetic(0x4, 0x10, 0x02, true), (0, 0));
+        assert_eq!(arithmetic(0x4, 0xFF, 0x02, true), (1, 1));
+    }
+
+    #[test]
+    fn test_sub_flag() {
+        assert_eq!(arithmetic(0x5, 0x05, 0x03, false), (0x02, 1));
+        assert_eq!(arithmetic(0x5, 0x03, 0x05, false), (0xFE, 0));
+        assert_eq!(arithmetic(0x5, 0x05, 0x05, false), (0x00, 1));
+
+        assert_eq!(arithmetic(0x5, 0x10, 0x02, true), (1, 1));
+        assert_eq!(arithmetic(0x5, 0x02, 0x10, true), (0, 0));
+        assert_eq!(arithmetic(0x5, 0x07, 0x07, true), (1, 1));
+    }
+
+    #[test]
+    fn test_subn_flag() {
+        assert_eq!(arithmetic(0x7, 0x03, 0x05, false), (0x02, 1));
+        assert_eq!(arithmetic(0x7, 0x05, 0x03, false), (0xFE, 0));
+        assert_eq!(arithmetic(0x7, 0x07, 0x07, false), (0x00, 1));
+
+        assert_eq!(arithmetic(0x7, 0x20, 0x10, true), (0, 0));
+        assert_eq!(arithmetic(0x7, 0x10, 0x20, true), (1, 1));
+        assert_eq!(arithmetic(0x7, 0x07, 0x07, true), (1, 1));
+    }
+
+    #[test]
+    fn test_shift_quirk() {
+        // LD V1, 0x81 - SHR V0, V1
+        let program = [0x61, 0x81, 0x80, 0x16];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 2);
+        assert_eq!(chip.v_registers[0x0], 0);
+        assert_eq!(chip.v_registers[0xF], 0);
+
+        let mut chip = chip_with_program(Quirks::cosmac_vip(), &program);
+        run(&mut chip, 2);
+        assert_eq!(chip.v_registers[0x0], 0x40);
+        assert_eq!(chip.v_registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_jump_quirk() {
+        // LD V0, 0x02 - LD V3, 0x04 - JP V0, 0x300
+        let program = [0x60, 0x02, 0x63, 0x04, 0xB3, 0x00];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 3);
+        assert_eq!(chip.memory.pc_register, 0x302);
+
+        let mut chip = chip_with_program(Quirks::schip(), &program);
+        run(&mut chip, 3);
+        assert_eq!(chip.memory.pc_register, 0x304);
+    }
+
+    #[test]
+    fn test_load_store_quirk() {
+        // LD I, 0x300 - LD [I], V2
+        let program = [0xA3, 0x00, 0xF2, 0x55];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 2);
+        assert_eq!(chip.memory.i_register, 0x300);
+
+        let mut chip = chip_with_program(Quirks::cosmac_vip(), &program);
+        run(&mut chip, 2);
+        assert_eq!(chip.memory.i_register, 0x303);
+    }
+
+    #[test]
+    fn test_load_store_wraps_i() {
+        // LD I, long 0xFFFF - LD [I], V0
+        let program = [0xF0, 0x00, 0xFF, 0xFF, 0xF0, 0x55];
+
+        let mut chip = chip_with_program(Quirks::xo_chip(), &program);
+        run(&mut chip, 2);
+        assert_eq!(chip.memory.i_register, 0);
+    }
+
+    #[test]
+    fn test_logic_quirk() {
+        // LD VF, 0x01 - OR V0, V1
+        let program = [0x6F, 0x01, 0x80, 0x11];
+
+        let mut chip = chip_with_program(Quirks::default(), &program);
+        run(&mut chip, 2);
+        assert_eq!(chip.v_registers[0xF], 1);
+
+        let mut chip = chip_with_program(Quirks::cosmac_vip(), &program);
+        run(&mut chip, 2);
+        assert_eq!(chip.v_registers[0xF], 0);
     }
 }
@@ -1,4 +1,9 @@
-const MAX_MEMORY: usize = 4096;
+use crate::breakpoint::Access;
+use crate::state::{Snapshot, StateReader, StateWriter};
+use crate::ChipError;
+
+const MAX_MEMORY: usize = 0x10000;
+const LONG_LOAD_OPCODE: u16 = 0xF000;
 const DEFAULT_SPRITE_SIZE: usize = 5;
 
 const DEFAULT_SPRITES: [u8; DEFAULT_SPRITE_SIZE * 16] = [
@@ -20,130 +25,185 @@ const DEFAULT_SPRITES: [u8; DEFAULT_SPRITE_SIZE * 16] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+const HIRES_SPRITE_SIZE: usize = 10;
+const HIRES_SPRITES_START: usize = DEFAULT_SPRITES.len();
+
+const HIRES_SPRITES: [u8; HIRES_SPRITE_SIZE * 16] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
 pub struct Memory {
-    array: [u8; MAX_MEMORY],
+    array: Box<[u8]>,
     pub i_register: u16,
     pub pc_register: u16,
+    // NOTE: Data read or written since the last clear, for watchpoints
+    accesses: Vec<(Access, u16, u16)>,
 }
 
 impl Memory {
     pub fn new(start: u16) -> Self {
         let mut memory = Self {
-            array: [0; MAX_MEMORY],
+            array: vec![0; MAX_MEMORY].into_boxed_slice(),
             i_register: 0,
             pc_register: start,
+            accesses: Vec::new(),
         };
 
         memory.array[0..DEFAULT_SPRITES.len()].copy_from_slice(&DEFAULT_SPRITES);
+        memory.array[HIRES_SPRITES_START..HIRES_SPRITES_START + HIRES_SPRITES.len()]
+            .copy_from_slice(&HIRES_SPRITES);
 
         memory
     }
 
-    pub fn load_rom(&mut self, rom: &[u8]) {
-        assert!(
-            rom.len() <= MAX_MEMORY - self.pc_register as usize,
-            "Rom too big"
-        );
-
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), ChipError> {
         let i = self.pc_register as usize;
 
-        self.array[i..i + rom.len()].copy_from_slice(rom);
+        self.array
+            .get_mut(i..i + rom.len())
+            .ok_or(ChipError::RomTooLarge)?
+            .copy_from_slice(rom);
+
+        Ok(())
+    }
+
+    pub const fn as_slice(&self) -> &[u8] {
+        &self.array
+    }
+
+    // NOTE: Writes from outside the chip, they are not watched
+    pub fn write(&mut self, address: u16, bytes: &[u8]) -> Result<(), ChipError> {
+        let start = address as usize;
+
+        self.array
+            .get_mut(start..start + bytes.len())
+            .ok_or_else(|| ChipError::MemoryOutOfBounds {
+                addr: (start + bytes.len()).saturating_sub(1),
+            })?
+            .copy_from_slice(bytes);
+
+        Ok(())
     }
 
-    pub fn get_bytes(&self, count: u8) -> &[u8] {
-        let count = count as usize;
+    pub fn accesses(&self) -> &[(Access, u16, u16)] {
+        &self.accesses
+    }
+
+    pub fn clear_accesses(&mut self) {
+        self.accesses.clear();
+    }
+
+    pub fn get_bytes(&mut self, count: u8) -> Result<&[u8], ChipError> {
         let i = self.i_register as usize;
-        &self.array[i..i + count]
+        self.accesses
+            .push((Access::Read, self.i_register, u16::from(count)));
+
+        self.get_range(i, count as usize)
     }
 
-    pub fn get_current_instruction(&self) -> Instruction {
+    pub fn get_current_instruction(&self) -> Result<u16, ChipError> {
         let address = self.pc_register as usize;
-        let first = u16::from(self.array[address]);
-        let second = u16::from(self.array[address + 1]);
+        let bytes = self.get_range(address, 2)?;
 
-        let raw_instruction = first << 8 | second;
+        Ok(u16::from(bytes[0]) << 8 | u16::from(bytes[1]))
+    }
 
-        Instruction::from(raw_instruction)
+    pub const fn increase_pc(&mut self) {
+        self.pc_register = self.pc_register.wrapping_add(2);
     }
 
-    pub fn increase_pc(&mut self) {
-        self.pc_register += 2;
+    // NOTE: F000 NNNN is four bytes long, it must be skipped entirely
+    pub fn skip_instruction(&mut self) {
+        self.increase_pc();
+
+        if matches!(self.get_current_instruction(), Ok(LONG_LOAD_OPCODE)) {
+            self.increase_pc();
+        }
+    }
+
+    // NOTE: Loads the address following the current instruction into I
+    // and moves PC over it
+    pub fn load_long_address(&mut self) -> Result<(), ChipError> {
+        let address = self.pc_register as usize + 2;
+        let bytes = self.get_range(address, 2)?;
+
+        self.i_register = u16::from(bytes[0]) << 8 | u16::from(bytes[1]);
+        self.increase_pc();
+
+        Ok(())
     }
 
     pub fn load_default_sprite(&mut self, x: u8) {
-        assert!(x <= 0xF, "Invalid default sprite");
+        // NOTE: Only the lowest nibble selects the digit
+        self.i_register = u16::from(x & 0xF) * DEFAULT_SPRITE_SIZE as u16;
+    }
 
-        self.i_register = u16::from(x) * DEFAULT_SPRITE_SIZE as u16;
+    pub const fn load_hires_sprite(&mut self, x: u8) {
+        // NOTE: Only the lowest nibble selects the digit
+        self.i_register = (HIRES_SPRITES_START + (x & 0xF) as usize * HIRES_SPRITE_SIZE) as u16;
     }
 
-    pub fn load_decimal_to_memory(&mut self, num: u8) {
+    pub fn load_decimal_to_memory(&mut self, num: u8) -> Result<(), ChipError> {
         let hundreds = num / 100;
         let tens = num / 10 % 10;
         let ones = num % 10;
 
-        self.load_bytes_to_memory(&[hundreds, tens, ones]);
+        self.load_bytes_to_memory(&[hundreds, tens, ones])
     }
 
-    pub fn load_bytes_to_memory(&mut self, bytes: &[u8]) {
+    pub fn load_bytes_to_memory(&mut self, bytes: &[u8]) -> Result<(), ChipError> {
         let index = self.i_register as usize;
-        self.array[index..index + bytes.len()].copy_from_slice(bytes);
-    }
-}
+        self.accesses
+            .push((Access::Write, self.i_register, bytes.len() as u16));
 
-// s or start - A 4-bit value, the first 4 bits of the instruction
-// nnn or addr - A 12-bit value, the lowest 12 bits of the instruction
-// n or nibble - A 4-bit value, the lowest 4 bits of the instruction
-// x - A 4-bit value, the lower 4 bits of the high byte of the instruction
-// y - A 4-bit value, the upper 4 bits of the low byte of the instruction
-// kk or byte - An 8-bit value, the lowest 8 bits of the instruction
-#[derive(Clone, Copy)]
-pub enum BitVariables {
-    S,
-    Nnn,
-    N,
-    X,
-    Y,
-    Kk,
-}
+        self.array
+            .get_mut(index..index + bytes.len())
+            .ok_or_else(|| ChipError::MemoryOutOfBounds {
+                addr: (index + bytes.len()).saturating_sub(1),
+            })?
+            .copy_from_slice(bytes);
 
-// Returns the part of the u16 represented by the variable
-const fn get_variable(value: u16, variable: BitVariables) -> u16 {
-    match variable {
-        BitVariables::S => value >> 12,
-        BitVariables::Nnn => value & 0x0FFF,
-        BitVariables::N => value & 0x000F,
-        BitVariables::X => (value & 0x0F00) >> 8,
-        BitVariables::Y => (value & 0x00F0) >> 4,
-        BitVariables::Kk => value & 0x00FF,
+        Ok(())
     }
-}
 
-#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
-pub struct Instruction {
-    pub s: u8,
-    pub x: usize,
-    pub y: usize,
-    pub n: u8,
-    pub nnn: u16,
-    pub kk: u8,
+    fn get_range(&self, start: usize, count: usize) -> Result<&[u8], ChipError> {
+        self.array
+            .get(start..start + count)
+            .ok_or_else(|| ChipError::MemoryOutOfBounds {
+                addr: (start + count).saturating_sub(1),
+            })
+    }
 }
 
-impl From<u16> for Instruction {
-    fn from(value: u16) -> Self {
-        Self {
-            s: get_variable(value, BitVariables::S) as u8,
-            x: get_variable(value, BitVariables::X) as usize,
-            y: get_variable(value, BitVariables::Y) as usize,
-            n: get_variable(value, BitVariables::N) as u8,
-            nnn: get_variable(value, BitVariables::Nnn),
-            kk: get_variable(value, BitVariables::Kk) as u8,
-        }
+impl Snapshot for Memory {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.array);
+        writer.write_u16(self.i_register);
+        writer.write_u16(self.pc_register);
     }
-}
 
-impl Instruction {
-    pub const fn get_nibbles(&self) -> (u8, u8, u8, u8) {
-        (self.s, self.x as u8, self.y as u8, self.n)
+    fn load(reader: &mut StateReader) -> Result<Self, ChipError> {
+        Ok(Self {
+            array: reader.read_bytes(MAX_MEMORY)?.into(),
+            i_register: reader.read_u16()?,
+            pc_register: reader.read_u16()?,
+            accesses: Vec::new(),
+        })
     }
 }
 
@@ -152,30 +212,15 @@ mod tests {
     use super::*;
     const DEFAULT_START: u16 = 0x200;
 
-    #[test]
-    fn test_instruction() {
-        let instruction = Instruction::from(0x1234);
-
-        assert_eq!(instruction.s, 1);
-        assert_eq!(instruction.x, 2);
-        assert_eq!(instruction.y, 3);
-        assert_eq!(instruction.n, 4);
-        assert_eq!(instruction.kk, 0x34);
-        assert_eq!(instruction.nnn, 0x234);
-    }
-
-    #[test]
-    fn test_nibbles() {
-        let instruction = Instruction::from(0x1234);
-
-        assert_eq!((1, 2, 3, 4), instruction.get_nibbles());
-    }
-
     #[test]
     fn test_new_memory() {
         let memory = Memory::new(DEFAULT_START);
 
         assert_eq!(memory.array[0..DEFAULT_SPRITES.len()], DEFAULT_SPRITES);
+        assert_eq!(
+            memory.array[HIRES_SPRITES_START..HIRES_SPRITES_START + HIRES_SPRITES.len()],
+            HIRES_SPRITES
+        );
     }
 
     #[test]
@@ -191,12 +236,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_load_hires_sprite() {
+        let mut memory = Memory::new(DEFAULT_START);
+
+        memory.load_hires_sprite(0x9);
+
+        let i = memory.i_register as usize;
+        assert_eq!(
+            memory.array[i..i + HIRES_SPRITE_SIZE],
+            HIRES_SPRITES[90..100]
+        );
+    }
+
     #[test]
     fn test_load_decimal() {
         let mut memory = Memory::new(DEFAULT_START);
         memory.i_register = DEFAULT_START;
 
-        memory.load_decimal_to_memory(123);
+        memory.load_decimal_to_memory(123).expect("Valid address");
 
         let i = memory.i_register as usize;
         assert_eq!(memory.array[i..i + 3], [1, 2, 3]);
@@ -206,11 +264,20 @@ mod tests {
     fn test_get_bytes() {
         let mut memory = Memory::new(DEFAULT_START);
         memory.i_register = 0;
-        let bytes = memory.get_bytes(10);
+        let bytes = memory.get_bytes(10).expect("Valid address");
 
         assert_eq!(&DEFAULT_SPRITES[0..10], bytes);
     }
 
+    #[test]
+    fn test_get_no_bytes() {
+        let mut memory = Memory::new(DEFAULT_START);
+        memory.i_register = 0;
+
+        assert!(memory.get_bytes(0).expect("Valid address").is_empty());
+        memory.load_bytes_to_memory(&[]).expect("Valid address");
+    }
+
     #[test]
     fn test_load_bytes() {
         const DATA: [u8; 3] = [1, 2, 3];
@@ -218,7 +285,7 @@ mod tests {
         let mut memory = Memory::new(DEFAULT_START);
         memory.i_register = DEFAULT_START;
 
-        memory.load_bytes_to_memory(&DATA);
+        memory.load_bytes_to_memory(&DATA).expect("Valid address");
 
         let i = memory.i_register as usize;
         assert_eq!(memory.array[i..i + DATA.len()], DATA);
@@ -233,6 +300,33 @@ mod tests {
         assert_eq!(memory.pc_register, DEFAULT_START + 2);
     }
 
+    #[test]
+    fn test_skip_instruction() {
+        let mut memory = Memory::new(DEFAULT_START);
+
+        memory.skip_instruction();
+        assert_eq!(memory.pc_register, DEFAULT_START + 2);
+
+        let pc = memory.pc_register as usize;
+        memory.array[pc + 2] = 0xF0;
+        memory.array[pc + 3] = 0x00;
+
+        memory.skip_instruction();
+        assert_eq!(memory.pc_register, DEFAULT_START + 6);
+    }
+
+    #[test]
+    fn test_load_long_address() {
+        let mut memory = Memory::new(DEFAULT_START);
+        let pc = memory.pc_register as usize;
+        memory.array[pc..pc + 4].copy_from_slice(&[0xF0, 0x00, 0xAB, 0xCD]);
+
+        memory.load_long_address().expect("Valid address");
+
+        assert_eq!(memory.i_register, 0xABCD);
+        assert_eq!(memory.pc_register, DEFAULT_START + 2);
+    }
+
     #[test]
     fn test_get_instruction() {
         let mut memory = Memory::new(DEFAULT_START);
@@ -242,6 +336,36 @@ mod tests {
 
         let instruction = memory.get_current_instruction();
 
-        assert_eq!(instruction, Instruction::from(0x1234));
+        assert_eq!(instruction.ok(), Some(0x1234));
+    }
+
+    #[test]
+    fn test_load_rom_too_big() {
+        let mut memory = Memory::new(DEFAULT_START);
+        let rom = vec![0; MAX_MEMORY];
+
+        assert!(matches!(memory.load_rom(&rom), Err(ChipError::RomTooLarge)));
+    }
+
+    #[test]
+    fn test_get_bytes_out_of_bounds() {
+        let mut memory = Memory::new(DEFAULT_START);
+        memory.i_register = (MAX_MEMORY - 2) as u16;
+
+        assert!(matches!(
+            memory.get_bytes(3),
+            Err(ChipError::MemoryOutOfBounds { addr }) if addr == MAX_MEMORY
+        ));
+    }
+
+    #[test]
+    fn test_get_instruction_out_of_bounds() {
+        let mut memory = Memory::new(DEFAULT_START);
+        memory.pc_register = (MAX_MEMORY - 1) as u16;
+
+        assert!(matches!(
+            memory.get_current_instruction(),
+            Err(ChipError::MemoryOutOfBounds { .. })
+        ));
     }
 }
@@ -0,0 +1,340 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::{sha256_hex, Chip, ChipError, Key, Quirks, DEFAULT_ROM_START};
+
+const MAGIC: &str = "chippy-movie";
+const VERSION: u32 = 1;
+
+// A key pressed or released at the start of a frame
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MovieInput {
+    pub frame: u64,
+    pub key: Key,
+    pub pressed: bool,
+}
+
+// NOTE: Everything needed to play a session again: the rom, how the chip was
+// created, how many instructions run per frame and the keys of each frame.
+// The timers tick once per frame, so the frame number is the only clock
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Movie {
+    pub rom_hash: String,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub cycles_per_frame: u32,
+    // NOTE: Length of the session, the last inputs may be earlier
+    pub frames: u64,
+    inputs: Vec<MovieInput>,
+}
+
+impl Movie {
+    #[must_use]
+    pub fn new(rom: &[u8], seed: u64, quirks: Quirks, cycles_per_frame: u32) -> Self {
+        Self {
+            rom_hash: sha256_hex(rom),
+            seed,
+            quirks,
+            cycles_per_frame,
+            frames: 0,
+            inputs: Vec::new(),
+        }
+    }
+
+    // NOTE: Inputs of the same frame are kept in the order they came, the
+    // movie lasts at least until the frame of the last one
+    pub fn record(&mut self, frame: u64, key: Key, pressed: bool) {
+        let index = self.inputs.partition_point(|input| input.frame <= frame);
+        self.inputs.insert(
+            index,
+            MovieInput {
+                frame,
+                key,
+                pressed,
+            },
+        );
+        self.frames = self.frames.max(frame + 1);
+    }
+
+    #[must_use]
+    pub fn inputs(&self) -> &[MovieInput] {
+        &self.inputs
+    }
+
+    #[must_use]
+    pub fn inputs_at(&self, frame: u64) -> &[MovieInput] {
+        let start = self.inputs.partition_point(|input| input.frame < frame);
+        let end = self.inputs.partition_point(|input| input.frame <= frame);
+
+        &self.inputs[start..end]
+    }
+
+    // NOTE: Must be called at the start of every frame, before running it
+    pub fn apply(&self, chip: &mut Chip, frame: u64) {
+        for input in self.inputs_at(frame) {
+            if input.pressed {
+                chip.press_key(input.key);
+            } else {
+                chip.unpress_key(input.key);
+            }
+        }
+    }
+
+    // NOTE: A chip like the one that was recorded, only for the same rom
+    pub fn chip(&self, rom: &[u8]) -> Result<Chip, MovieError> {
+        let hash = sha256_hex(rom);
+        if hash != self.rom_hash {
+            return Err(MovieError::RomMismatch {
+                expected: self.rom_hash.clone(),
+                actual: hash,
+            });
+        }
+
+        let mut chip = Chip::with_seed(DEFAULT_ROM_START, self.quirks, self.seed);
+        chip.load_rom_bytes(rom).map_err(MovieError::Chip)?;
+
+        Ok(chip)
+    }
+}
+
+// NOTE: A text header with a field per line and then an input per line,
+// so movies can be read and edited by hand:
+// chippy-movie 1
+// rom 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+// seed 42
+// quirks schip
+// cycles-per-frame 10
+// frames 300
+// 120 press 5
+// 126 release 5
+impl Display for Movie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{MAGIC} {VERSION}")?;
+        writeln!(f, "rom {}", self.rom_hash)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "quirks {}", self.quirks)?;
+        writeln!(f, "cycles-per-frame {}", self.cycles_per_frame)?;
+        writeln!(f, "frames {}", self.frames)?;
+
+        for input in &self.inputs {
+            let action = if input.pressed { "press" } else { "release" };
+            writeln!(f, "{} {action} {:X}", input.frame, input.key as u8)?;
+        }
+
+        Ok(())
+    }
+}
+
+// NOTE: Blank lines and lines starting with # are skipped, the fields of
+// the header may come in any order
+impl FromStr for Movie {
+    type Err = MovieError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        match lines.next() {
+            Some((_, line)) if line == format!("{MAGIC} {VERSION}") => (),
+            Some((line, text)) => {
+                return Err(MovieError::Invalid {
+                    line,
+                    text: text.to_owned(),
+                })
+            }
+            None => return Err(MovieError::Missing("header")),
+        }
+
+        let (mut rom_hash, mut seed, mut quirks, mut cycles_per_frame, mut frames) =
+            (None, None, None, None, None);
+        let mut inputs = Vec::new();
+
+        for (line, text) in lines {
+            let invalid = || MovieError::Invalid {
+                line,
+                text: text.to_owned(),
+            };
+
+            match text.split_whitespace().collect::<Vec<_>>()[..] {
+                ["rom", hash] => rom_hash = Some(hash.to_owned()),
+                ["seed", value] => seed = Some(value.parse().map_err(|_| invalid())?),
+                ["quirks", value] => quirks = Some(value.parse().map_err(|_| invalid())?),
+                ["cycles-per-frame", value] => {
+                    cycles_per_frame = Some(value.parse().map_err(|_| invalid())?);
+                }
+                ["frames", value] => frames = Some(value.parse().map_err(|_| invalid())?),
+                [frame, action, key] => inputs.push(MovieInput {
+                    frame: frame.parse().map_err(|_| invalid())?,
+                    key: u8::from_str_radix(key, 16)
+                        .ok()
+                        .and_then(Key::from_digit)
+                        .ok_or_else(invalid)?,
+                    pressed: match action {
+                        "press" => true,
+                        "release" => false,
+                        _ => return Err(invalid()),
+                    },
+                }),
+                _ => return Err(invalid()),
+            }
+        }
+
+        // NOTE: Stable, inputs of the same frame keep their order
+        inputs.sort_by_key(|input| input.frame);
+
+        Ok(Self {
+            rom_hash: rom_hash.ok_or(MovieError::Missing("rom"))?,
+            seed: seed.ok_or(MovieError::Missing("seed"))?,
+            quirks: quirks.ok_or(MovieError::Missing("quirks"))?,
+            cycles_per_frame: cycles_per_frame.ok_or(MovieError::Missing("cycles-per-frame"))?,
+            frames: frames.ok_or(MovieError::Missing("frames"))?,
+            inputs,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum MovieError {
+    Invalid { line: usize, text: String },
+    Missing(&'static str),
+    RomMismatch { expected: String, actual: String },
+    Chip(ChipError),
+}
+
+impl Display for MovieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid { line, text } => write!(f, "Invalid movie line {line}: '{text}'"),
+            Self::Missing(field) => write!(f, "The movie has no {field}"),
+            Self::RomMismatch { expected, actual } => write!(
+                f,
+                "The movie was recorded with another rom, with hash {expected} instead of {actual}"
+            ),
+            Self::Chip(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Chip(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RND V0, 0xFF - LD V1, K - LD V2, DT - LD V3, 0x3C - LD DT, V3 - JP 0x202
+    const ROM: [u8; 12] = [
+        0xC0, 0xFF, 0xF1, 0x0A, 0xF2, 0x07, 0x63, 0x3C, 0xF3, 0x15, 0x12, 0x02,
+    ];
+
+    fn movie() -> Movie {
+        let mut movie = Movie::new(&ROM, 42, Quirks::schip(), 10);
+        movie.record(8, Key::Key5, false);
+        movie.record(3, Key::Key5, true);
+        movie.record(8, Key::KeyA, true);
+        movie.frames = 20;
+        movie
+    }
+
+    fn play(movie: &Movie) -> Chip {
+        let mut chip = movie.chip(&ROM).expect("Same rom");
+
+        for frame in 0..movie.frames {
+            movie.apply(&mut chip, frame);
+            for _ in 0..movie.cycles_per_frame {
+                chip.run_cycle().expect("Valid rom");
+            }
+            chip.tick_timers();
+        }
+
+        chip
+    }
+
+    #[test]
+    fn test_record() {
+        let movie = movie();
+
+        let frames: Vec<u64> = movie.inputs().iter().map(|input| input.frame).collect();
+        assert_eq!(frames, [3, 8, 8]);
+        assert_eq!(
+            movie.inputs_at(8),
+            [
+                MovieInput {
+                    frame: 8,
+                    key: Key::Key5,
+                    pressed: false,
+                },
+                MovieInput {
+                    frame: 8,
+                    key: Key::KeyA,
+                    pressed: true,
+                },
+            ]
+        );
+        assert!(movie.inputs_at(4).is_empty());
+    }
+
+    #[test]
+    fn test_parse_movie() {
+        let movie = movie();
+        let text = movie.to_string();
+
+        assert!(text.starts_with("chippy-movie 1\nrom "));
+        assert!(text.ends_with("frames 20\n3 press 5\n8 release 5\n8 press A\n"));
+        assert_eq!(text.parse::<Movie>().ok(), Some(movie));
+
+        let commented = text.replace("seed 42\n", "# Jumping over the first gap\n\nseed 42\n");
+        assert!(commented.parse::<Movie>().is_ok());
+
+        assert!(matches!(
+            "".parse::<Movie>(),
+            Err(MovieError::Missing("header"))
+        ));
+        assert!(matches!(
+            text.replace("seed 42\n", "").parse::<Movie>(),
+            Err(MovieError::Missing("seed"))
+        ));
+        assert!(matches!(
+            text.replace("press A", "press G").parse::<Movie>(),
+            Err(MovieError::Invalid { line: 9, .. })
+        ));
+        assert!(matches!(
+            text.replace("chippy-movie 1", "chippy-movie 2")
+                .parse::<Movie>(),
+            Err(MovieError::Invalid { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_play_movie() {
+        let movie = movie();
+
+        let first = play(&movie);
+        let second = play(&movie);
+        assert_eq!(first.registers(), second.registers());
+        assert_eq!(first.registers().v[1], 0xA);
+
+        let mut other = movie;
+        other.seed = 43;
+        assert_ne!(play(&other).registers().v[0], first.registers().v[0]);
+    }
+
+    #[test]
+    fn test_other_rom() {
+        let movie = movie();
+
+        assert!(matches!(
+            movie.chip(&ROM[..10]),
+            Err(MovieError::RomMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,1188 @@
+mod calc;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::asm::{AsmError, AsmErrorKind};
+use crate::{Opcode, DEFAULT_ROM_START};
+
+const MAX_MACRO_DEPTH: usize = 1_000;
+const ADDRESS_SPACE: usize = 0x10000;
+// NOTE: Register clobbered by the comparisons that are not native
+const COMPARE_TEMP: u8 = 0xF;
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+// NOTE: Tokens are separated by whitespace and comments start with #
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or_default();
+        let mut column = 0;
+
+        for word in code.split_whitespace() {
+            column += code[column..].find(word).unwrap_or_default();
+            tokens.push(Token {
+                text: word.to_owned(),
+                line: index + 1,
+                column: code[..column].chars().count() + 1,
+            });
+            column += word.len();
+        }
+    }
+
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = text
+        .strip_prefix('-')
+        .map_or((false, text), |digits| (true, digits));
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+// Address that is patched once the label it refers to is known
+struct Fixup {
+    offset: usize,
+    label: Token,
+    long: bool,
+}
+
+enum Block {
+    If { jump: usize },
+    Else { jump: usize },
+    Loop { start: u16, breaks: Vec<usize> },
+}
+
+struct Compiler {
+    file: String,
+    tokens: Vec<Token>,
+    position: usize,
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(Block, Token)>,
+    // NOTE: Where the tokens of each macro expansion in progress end, the
+    // innermost one last
+    expanding: Vec<usize>,
+}
+
+pub fn compile(source: &str) -> Result<Vec<u8>, AsmError> {
+    Compiler::new("<source>", source).compile()
+}
+
+pub fn compile_file(path: impl AsRef<Path>) -> Result<Vec<u8>, AsmError> {
+    let path = path.as_ref();
+    let file = path.to_string_lossy().into_owned();
+
+    let source = std::fs::read_to_string(path).map_err(|error| AsmError {
+        file: file.clone(),
+        line: 0,
+        column: 0,
+        kind: AsmErrorKind::Io(error),
+    })?;
+
+    Compiler::new(&file, &source).compile()
+}
+
+impl Compiler {
+    fn new(file: &str, source: &str) -> Self {
+        Self {
+            file: file.to_owned(),
+            tokens: tokenize(source),
+            position: 0,
+            rom: Vec::new(),
+            here: usize::from(DEFAULT_ROM_START),
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            expanding: Vec::new(),
+        }
+    }
+
+    fn error(&self, token: &Token, kind: AsmErrorKind) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: token.line,
+            column: token.column,
+            kind,
+        }
+    }
+
+    fn compile(mut self) -> Result<Vec<u8>, AsmError> {
+        // NOTE: Programs start at main, the jump is not needed when it is first
+        let main_first = matches!(self.tokens.as_slice(), [colon, main, ..] if colon.text == ":" && main.text == "main");
+        if !main_first {
+            let label = self.tokens.first().cloned().unwrap_or(Token {
+                text: String::new(),
+                line: 1,
+                column: 1,
+            });
+            self.jump_to(
+                Opcode::Jp(0),
+                &Token {
+                    text: String::from("main"),
+                    ..label
+                },
+            )?;
+        }
+
+        while self.position < self.tokens.len() {
+            self.statement()?;
+        }
+
+        if let Some((block, token)) = self.blocks.last() {
+            let missing = match block {
+                Block::If { .. } | Block::Else { .. } => "end",
+                Block::Loop { .. } => "again",
+            };
+            return Err(self.error(
+                token,
+                AsmErrorKind::UnmatchedBlock(format!("Missing {missing}")),
+            ));
+        }
+
+        for fixup in &self.fixups {
+            let address = *self.labels.get(&fixup.label.text).ok_or_else(|| {
+                self.error(
+                    &fixup.label,
+                    AsmErrorKind::UndefinedSymbol(fixup.label.text.clone()),
+                )
+            })?;
+
+            if fixup.long {
+                self.rom[fixup.offset + 2..fixup.offset + 4]
+                    .copy_from_slice(&address.to_be_bytes());
+            } else {
+                self.check_address(&fixup.label, i64::from(address))?;
+                self.rom[fixup.offset] |= (address >> 8) as u8;
+                self.rom[fixup.offset + 1] = address as u8;
+            }
+        }
+
+        Ok(self.rom)
+    }
+
+    fn next(&mut self) -> Result<Token, AsmError> {
+        let Some(token) = self.tokens.get(self.position).cloned() else {
+            let last = self.tokens.last().cloned().unwrap_or(Token {
+                text: String::new(),
+                line: 1,
+                column: 1,
+            });
+            return Err(self.error(&last, AsmErrorKind::UnexpectedEnd));
+        };
+        self.position += 1;
+
+        Ok(token)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens
+            .get(self.position)
+            .map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(self.unexpected(&token));
+        }
+
+        Ok(token)
+    }
+
+    fn unexpected(&self, token: &Token) -> AsmError {
+        self.error(token, AsmErrorKind::UnexpectedToken(token.text.clone()))
+    }
+
+    fn offset(&self) -> usize {
+        self.here - usize::from(DEFAULT_ROM_START)
+    }
+
+    fn emit_byte(&mut self, token: &Token, byte: u8) -> Result<(), AsmError> {
+        if self.here >= ADDRESS_SPACE {
+            return Err(self.error(token, AsmErrorKind::ProgramTooLarge));
+        }
+
+        let offset = self.offset();
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+
+        Ok(())
+    }
+
+    fn emit(&mut self, token: &Token, opcode: Opcode) -> Result<(), AsmError> {
+        for byte in opcode.encode().to_be_bytes() {
+            self.emit_byte(token, byte)?;
+        }
+
+        Ok(())
+    }
+
+    // NOTE: Emits an instruction with an address, patched later if the label
+    // is not defined yet
+    fn jump_to(&mut self, opcode: Opcode, target: &Token) -> Result<(), AsmError> {
+        let address = self.address_of(target, false);
+        let address = self.check_address(target, address)?;
+
+        let opcode = match opcode {
+            Opcode::Jp(_) => Opcode::Jp(address),
+            Opcode::Call(_) => Opcode::Call(address),
+            Opcode::JpV0(_) => Opcode::JpV0(address),
+            Opcode::LdI(_) => Opcode::LdI(address),
+            _ => Opcode::Sys(address),
+        };
+
+        self.emit(target, opcode)
+    }
+
+    fn address_of(&mut self, target: &Token, long: bool) -> i64 {
+        if let Some(address) = self.labels.get(&target.text) {
+            return i64::from(*address);
+        }
+        if let Ok(value) = self.value(target) {
+            return value;
+        }
+
+        self.fixups.push(Fixup {
+            offset: self.offset(),
+            label: target.clone(),
+            long,
+        });
+
+        0
+    }
+
+    fn check_address(&self, token: &Token, value: i64) -> Result<u16, AsmError> {
+        self.in_range(token, value, 0, 0xFFF)
+            .map(|value| value as u16)
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    fn in_range(&self, token: &Token, value: i64, min: i64, max: i64) -> Result<u64, AsmError> {
+        if (min..=max).contains(&value) {
+            Ok(value as u64 & 0xFFFF)
+        } else {
+            Err(self.error(token, AsmErrorKind::OutOfRange { value, max }))
+        }
+    }
+
+    fn value(&self, token: &Token) -> Result<i64, AsmError> {
+        parse_number(&token.text)
+            .or_else(|| self.constants.get(&token.text).copied())
+            .ok_or_else(|| self.error(token, AsmErrorKind::UndefinedSymbol(token.text.clone())))
+    }
+
+    // NOTE: Negative bytes are taken as two's complement
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        let value = self.value(&token)?;
+
+        Ok(self.in_range(&token, value, -0x80, 0xFF)? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        let value = self.value(&token)?;
+
+        Ok(self.in_range(&token, value, 0, 0xF)? as u8)
+    }
+
+    fn is_register(&self, text: &str) -> bool {
+        self.aliases.contains_key(text) || parse_register(text).is_some()
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+
+        self.aliases
+            .get(&token.text)
+            .copied()
+            .or_else(|| parse_register(&token.text))
+            .ok_or_else(|| self.error(&token, AsmErrorKind::ExpectedRegister(token.text.clone())))
+    }
+
+    fn define(&self, name: &Token) -> Result<(), AsmError> {
+        let taken = self.labels.contains_key(&name.text)
+            || self.constants.contains_key(&name.text)
+            || self.aliases.contains_key(&name.text)
+            || self.macros.contains_key(&name.text);
+
+        if taken {
+            return Err(self.error(name, AsmErrorKind::DuplicateSymbol(name.text.clone())));
+        }
+
+        Ok(())
+    }
+
+    // NOTE: Tokens between braces, the opening one must be next
+    fn braces(&mut self) -> Result<Vec<Token>, AsmError> {
+        let open = self.expect("{")?;
+        let mut depth = 1;
+        let mut tokens = Vec::new();
+
+        loop {
+            let token = self.next().map_err(|_| {
+                self.error(
+                    &open,
+                    AsmErrorKind::UnmatchedBlock(String::from("Missing }")),
+                )
+            })?;
+
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 1 => return Ok(tokens),
+                "}" => depth -= 1,
+                _ => (),
+            }
+
+            tokens.push(token);
+        }
+    }
+
+    fn calc(&mut self) -> Result<i64, AsmError> {
+        let tokens = self.braces()?;
+        let lookup = |name: &str| {
+            self.constants
+                .get(name)
+                .copied()
+                .or_else(|| self.labels.get(name).map(|address| i64::from(*address)))
+        };
+
+        calc::evaluate(&tokens, lookup).map_err(|(index, kind)| {
+            let token = tokens
+                .get(index)
+                .or_else(|| tokens.last())
+                .cloned()
+                .unwrap_or_else(|| self.tokens[self.position - 1].clone());
+            self.error(&token, kind)
+        })
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+
+        match token.text.as_str() {
+            text if text.starts_with(':') => self.directive(&token)?,
+            "return" | ";" => self.emit(&token, Opcode::Ret)?,
+            "clear" => self.emit(&token, Opcode::Cls)?,
+            "exit" => self.emit(&token, Opcode::Exit)?,
+            "lores" => self.emit(&token, Opcode::Low)?,
+            "hires" => self.emit(&token, Opcode::High)?,
+            "scroll-left" => self.emit(&token, Opcode::ScrollLeft)?,
+            "scroll-right" => self.emit(&token, Opcode::ScrollRight)?,
+            "audio" => self.emit(&token, Opcode::Audio)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(&token, Opcode::ScrollDown(n))?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(&token, Opcode::ScrollUp(n))?;
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(&token, Opcode::Plane(n))?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(&token, Opcode::LdB(x))?;
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(&token, Opcode::LdRVx(x))?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(&token, Opcode::LdVxR(x))?;
+            }
+            "save" | "load" => self.save_load(&token)?,
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(&token, Opcode::Drw { x, y, n })?;
+            }
+            "jump" => {
+                let target = self.next()?;
+                self.jump_to(Opcode::Jp(0), &target)?;
+            }
+            "jump0" => {
+                let target = self.next()?;
+                self.jump_to(Opcode::JpV0(0), &target)?;
+            }
+            "native" => {
+                let target = self.next()?;
+                self.jump_to(Opcode::Sys(0), &target)?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let opcode = match token.text.as_str() {
+                    "delay" => Opcode::LdDtVx(x),
+                    "buzzer" => Opcode::LdStVx(x),
+                    _ => Opcode::Pitch(x),
+                };
+                self.emit(&token, opcode)?;
+            }
+            "i" => self.i_operation(&token)?,
+            "if" | "else" | "end" | "loop" | "while" | "again" => self.block(token)?,
+            text if self.is_register(text) => {
+                self.position -= 1;
+                self.register_operation()?;
+            }
+            text if self.macros.contains_key(text) => self.expand_macro(&token)?,
+            _ => match self.value(&token) {
+                // NOTE: Bare numbers are data
+                Ok(value) => {
+                    let byte = self.in_range(&token, value, -0x80, 0xFF)? as u8;
+                    self.emit_byte(&token, byte)?;
+                }
+                // NOTE: Any other name is a call to a subroutine
+                Err(_) => self.jump_to(Opcode::Call(0), &token)?,
+            },
+        }
+
+        Ok(())
+    }
+
+    fn directive(&mut self, token: &Token) -> Result<(), AsmError> {
+        match token.text.as_str() {
+            ":" => {
+                let name = self.next()?;
+                self.define(&name)?;
+                // NOTE: Labels past 0xFFF are only reachable with i := long,
+                // the short instructions check the address when they use it
+                let address = self.in_range(&name, self.here as i64, 0, 0xFFFF)? as u16;
+                self.labels.insert(name.text, address);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                self.define(&name)?;
+                let register = self.register()?;
+                self.aliases.insert(name.text, register);
+            }
+            ":const" => {
+                let name = self.next()?;
+                self.define(&name)?;
+                let value_token = self.next()?;
+                let value = self.value(&value_token)?;
+                self.constants.insert(name.text, value);
+            }
+            ":calc" => {
+                let name = self.next()?;
+                self.define(&name)?;
+                let value = self.calc()?;
+                self.constants.insert(name.text, value);
+            }
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let byte = if self.peek() == Some("{") {
+                    let value = self.calc()?;
+                    self.in_range(token, value, -0x80, 0xFF)? as u8
+                } else {
+                    self.byte()?
+                };
+                self.emit_byte(token, byte)?;
+            }
+            ":org" => {
+                let address = self.next()?;
+                let value = self.value(&address)?;
+                let start = i64::from(DEFAULT_ROM_START);
+                self.here =
+                    self.in_range(&address, value, start, ADDRESS_SPACE as i64 - 1)? as usize;
+            }
+            _ => return Err(self.unexpected(token)),
+        }
+
+        Ok(())
+    }
+
+    fn block(&mut self, token: Token) -> Result<(), AsmError> {
+        match token.text.as_str() {
+            "if" => self.if_statement()?,
+            "else" => {
+                let Some((Block::If { jump }, _)) = self.blocks.pop() else {
+                    return Err(self.error(
+                        &token,
+                        AsmErrorKind::UnmatchedBlock(String::from("else without begin")),
+                    ));
+                };
+                let next_jump = self.offset();
+                self.emit(&token, Opcode::Jp(0))?;
+                self.patch_jump(&token, jump)?;
+                self.blocks.push((Block::Else { jump: next_jump }, token));
+            }
+            "end" => match self.blocks.pop() {
+                Some((Block::If { jump } | Block::Else { jump }, _)) => {
+                    self.patch_jump(&token, jump)?;
+                }
+                _ => {
+                    return Err(self.error(
+                        &token,
+                        AsmErrorKind::UnmatchedBlock(String::from("end without begin")),
+                    ))
+                }
+            },
+            "loop" => {
+                let start = self.here as u16;
+                self.blocks.push((
+                    Block::Loop {
+                        start,
+                        breaks: Vec::new(),
+                    },
+                    token,
+                ));
+            }
+            "while" => {
+                let jump = self.condition(true)?;
+                let Some((Block::Loop { breaks, .. }, _)) = self.blocks.last_mut() else {
+                    return Err(self.error(
+                        &token,
+                        AsmErrorKind::UnmatchedBlock(String::from("while outside a loop")),
+                    ));
+                };
+                breaks.push(jump);
+                self.emit(&token, Opcode::Jp(0))?;
+            }
+            "again" => {
+                let Some((Block::Loop { start, breaks }, _)) = self.blocks.pop() else {
+                    return Err(self.error(
+                        &token,
+                        AsmErrorKind::UnmatchedBlock(String::from("again without loop")),
+                    ));
+                };
+                let start = self.check_address(&token, i64::from(start))?;
+                self.emit(&token, Opcode::Jp(start))?;
+                for jump in breaks {
+                    self.patch_jump(&token, jump)?;
+                }
+            }
+            _ => return Err(self.unexpected(&token)),
+        }
+
+        Ok(())
+    }
+
+    // NOTE: Points the jump at offset to the current address, the token is
+    // the one that ends the block
+    fn patch_jump(&mut self, token: &Token, offset: usize) -> Result<(), AsmError> {
+        let address = self.check_address(token, self.here as i64)?.to_be_bytes();
+        self.rom[offset] = 0x10 | address[0];
+        self.rom[offset + 1] = address[1];
+
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.next()?;
+        self.define(&name)?;
+
+        let mut params = Vec::new();
+        while self.peek().is_some_and(|text| text != "{") {
+            params.push(self.next()?.text);
+        }
+        let body = self.braces()?;
+
+        self.macros.insert(name.text, Macro { params, body });
+
+        Ok(())
+    }
+
+    // NOTE: The body replaces the invocation, with the arguments in place of
+    // the parameters. The expansions the invocation came from are still in
+    // progress, so a macro that keeps invoking itself nests deeper each time
+    fn expand_macro(&mut self, name: &Token) -> Result<(), AsmError> {
+        // NOTE: The name was just read, the innermost expansions that ended
+        // before it are done
+        let invocation = self.position - 1;
+        while self.expanding.last().is_some_and(|&end| end <= invocation) {
+            self.expanding.pop();
+        }
+        if self.expanding.len() >= MAX_MACRO_DEPTH {
+            return Err(self.error(name, AsmErrorKind::RecursiveMacro(name.text.clone())));
+        }
+
+        let Some(definition) = self.macros.get(&name.text) else {
+            return Ok(());
+        };
+        let params = definition.params.clone();
+        let body = definition.body.clone();
+
+        let mut arguments = HashMap::new();
+        for param in params {
+            arguments.insert(param, self.next()?.text);
+        }
+
+        let expanded: Vec<Token> = body
+            .into_iter()
+            .map(|token| match arguments.get(&token.text) {
+                Some(argument) => Token {
+                    text: argument.clone(),
+                    ..token
+                },
+                None => token,
+            })
+            .collect();
+
+        for end in &mut self.expanding {
+            if *end > self.position {
+                *end += expanded.len();
+            }
+        }
+        self.expanding.push(self.position + expanded.len());
+        self.tokens.splice(self.position..self.position, expanded);
+
+        Ok(())
+    }
+
+    fn save_load(&mut self, token: &Token) -> Result<(), AsmError> {
+        let x = self.register()?;
+        let save = token.text == "save";
+
+        let opcode = if self.peek() == Some("-") {
+            self.next()?;
+            let y = self.register()?;
+            if save {
+                Opcode::SaveRange { x, y }
+            } else {
+                Opcode::LoadRange { x, y }
+            }
+        } else if save {
+            Opcode::LdIVx(x)
+        } else {
+            Opcode::LdVxI(x)
+        };
+
+        self.emit(token, opcode)
+    }
+
+    fn i_operation(&mut self, token: &Token) -> Result<(), AsmError> {
+        let operator = self.next()?;
+
+        match operator.text.as_str() {
+            ":=" => {
+                let value = self.next()?;
+                match value.text.as_str() {
+                    "hex" => {
+                        let x = self.register()?;
+                        self.emit(token, Opcode::LdF(x))
+                    }
+                    "bighex" => {
+                        let x = self.register()?;
+                        self.emit(token, Opcode::LdHf(x))
+                    }
+                    "long" => self.long_load(token),
+                    _ => self.jump_to(Opcode::LdI(0), &value),
+                }
+            }
+            "+=" => {
+                let x = self.register()?;
+                self.emit(token, Opcode::AddIVx(x))
+            }
+            _ => Err(self.unexpected(&operator)),
+        }
+    }
+
+    fn long_load(&mut self, token: &Token) -> Result<(), AsmError> {
+        let target = self.next()?;
+        let address = self.address_of(&target, true);
+        let address = self.in_range(&target, address, 0, 0xFFFF)? as u16;
+
+        self.emit(token, Opcode::LdILong)?;
+        for byte in address.to_be_bytes() {
+            self.emit_byte(token, byte)?;
+        }
+
+        Ok(())
+    }
+
+    fn register_operation(&mut self) -> Result<(), AsmError> {
+        let target = self.next()?;
+        let x = self.register_of(&target)?;
+        let operator = self.next()?;
+        let source = self.peek().map(str::to_owned).unwrap_or_default();
+
+        let opcode = match (operator.text.as_str(), source.as_str()) {
+            (":=", "random") => {
+                self.next()?;
+                Opcode::Rnd {
+                    x,
+                    byte: self.byte()?,
+                }
+            }
+            (":=", "delay") => {
+                self.next()?;
+                Opcode::LdVxDt(x)
+            }
+            (":=", "key") => {
+                self.next()?;
+                Opcode::LdVxK(x)
+            }
+            (op, source) if self.is_register(source) => {
+                let y = self.register()?;
+                match op {
+                    ":=" => Opcode::LdReg { x, y },
+                    "+=" => Opcode::AddReg { x, y },
+                    "-=" => Opcode::Sub { x, y },
+                    "=-" => Opcode::Subn { x, y },
+                    "|=" => Opcode::Or { x, y },
+                    "&=" => Opcode::And { x, y },
+                    "^=" => Opcode::Xor { x, y },
+                    ">>=" => Opcode::Shr { x, y },
+                    "<<=" => Opcode::Shl { x, y },
+                    _ => return Err(self.unexpected(&operator)),
+                }
+            }
+            (":=", _) => Opcode::LdByte {
+                x,
+                byte: self.byte()?,
+            },
+            ("+=", _) => Opcode::AddByte {
+                x,
+                byte: self.byte()?,
+            },
+            // NOTE: There is no instruction for it, the negated byte is added
+            ("-=", _) => Opcode::AddByte {
+                x,
+                byte: self.byte()?.wrapping_neg(),
+            },
+            _ => return Err(self.unexpected(&operator)),
+        };
+
+        self.emit(&target, opcode)
+    }
+
+    fn register_of(&self, token: &Token) -> Result<u8, AsmError> {
+        self.aliases
+            .get(&token.text)
+            .copied()
+            .or_else(|| parse_register(&token.text))
+            .ok_or_else(|| self.error(token, AsmErrorKind::ExpectedRegister(token.text.clone())))
+    }
+
+    fn if_statement(&mut self) -> Result<(), AsmError> {
+        let start = self.position;
+        // NOTE: The keyword after the condition decides how it is compiled
+        let keyword = self.tokens[start..]
+            .iter()
+            .take(5)
+            .find(|token| token.text == "then" || token.text == "begin")
+            .cloned();
+
+        let Some(keyword) = keyword else {
+            let token = self.next()?;
+            return Err(self.error(
+                &token,
+                AsmErrorKind::UnmatchedBlock(String::from("if without then or begin")),
+            ));
+        };
+
+        if keyword.text == "then" {
+            self.condition(false)?;
+            self.expect("then")?;
+        } else {
+            let jump = self.condition(true)?;
+            self.expect("begin")?;
+            self.emit(&keyword, Opcode::Jp(0))?;
+            self.blocks.push((Block::If { jump }, keyword));
+        }
+
+        Ok(())
+    }
+
+    // NOTE: Emits the instructions that skip the next one when the condition
+    // is false, or when it is true if negated, and returns the offset after
+    // them
+    fn condition(&mut self, negated: bool) -> Result<usize, AsmError> {
+        let left = self.next()?;
+        let x = self.register_of(&left)?;
+        let operator = self.next()?;
+
+        let comparison = match (operator.text.as_str(), negated) {
+            ("==", false) | ("!=", true) => "==",
+            ("!=", false) | ("==", true) => "!=",
+            ("key", false) | ("-key", true) => "key",
+            ("-key", false) | ("key", true) => "-key",
+            (">", false) | ("<=", true) => ">",
+            ("<", false) | (">=", true) => "<",
+            (">=", false) | ("<", true) => ">=",
+            ("<=", false) | (">", true) => "<=",
+            _ => return Err(self.unexpected(&operator)),
+        };
+
+        let opcode = match comparison {
+            "key" => Opcode::Sknp(x),
+            "-key" => Opcode::Skp(x),
+            "==" | "!=" if self.peek().is_some_and(|text| self.is_register(text)) => {
+                let y = self.register()?;
+                if comparison == "==" {
+                    Opcode::SneReg { x, y }
+                } else {
+                    Opcode::SeReg { x, y }
+                }
+            }
+            "==" => Opcode::SneByte {
+                x,
+                byte: self.byte()?,
+            },
+            "!=" => Opcode::SeByte {
+                x,
+                byte: self.byte()?,
+            },
+            _ => {
+                // NOTE: The difference is computed in VF, the borrow flag
+                // tells the order of the operands. This relies on 8xy5 and
+                // 8xy7 writing the flag after the result and setting it when
+                // the operands are equal
+                let temp = COMPARE_TEMP;
+                let load = if self.peek().is_some_and(|text| self.is_register(text)) {
+                    Opcode::LdReg {
+                        x: temp,
+                        y: self.register()?,
+                    }
+                } else {
+                    Opcode::LdByte {
+                        x: temp,
+                        byte: self.byte()?,
+                    }
+                };
+                self.emit(&left, load)?;
+
+                let (difference, skip) = match comparison {
+                    ">" => (
+                        Opcode::Sub { x: temp, y: x },
+                        Opcode::SeByte { x: temp, byte: 1 },
+                    ),
+                    "<" => (
+                        Opcode::Subn { x: temp, y: x },
+                        Opcode::SeByte { x: temp, byte: 1 },
+                    ),
+                    ">=" => (
+                        Opcode::Subn { x: temp, y: x },
+                        Opcode::SneByte { x: temp, byte: 1 },
+                    ),
+                    _ => (
+                        Opcode::Sub { x: temp, y: x },
+                        Opcode::SneByte { x: temp, byte: 1 },
+                    ),
+                };
+                self.emit(&left, difference)?;
+                skip
+            }
+        };
+
+        self.emit(&left, opcode)?;
+
+        Ok(self.offset())
+    }
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+
+    u8::from_str_radix(digit, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip, Quirks};
+    use std::fmt::Write;
+
+    fn compile_ok(source: &str) -> Vec<u8> {
+        match compile(source) {
+            Ok(rom) => rom,
+            Err(error) => panic!("{error}"),
+        }
+    }
+
+    fn words(rom: &[u8]) -> Vec<u16> {
+        rom.chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or_default()]))
+            .collect()
+    }
+
+    #[test]
+    fn test_main_first() {
+        assert_eq!(
+            words(&compile_ok(": main clear loop again")),
+            [0x00E0, 0x1202]
+        );
+    }
+
+    #[test]
+    fn test_jump_to_main() {
+        let rom = compile_ok(": draw sprite v0 v1 5 ; : main draw");
+
+        assert_eq!(words(&rom), [0x1206, 0xD015, 0x00EE, 0x2202]);
+    }
+
+    #[test]
+    fn test_register_operations() {
+        let source = "
+            : main
+                v0 := 5  v1 := v0  v2 += 1  v3 -= 1  v4 -= v5  v4 =- v5
+                v6 |= v7  v8 &= v9  va ^= vb  vc >>= vd  ve <<= vf
+                v1 := random 0xFF  v2 := delay  v3 := key
+                delay := v1  buzzer := v2  pitch := v3
+        ";
+
+        assert_eq!(
+            words(&compile_ok(source)),
+            [
+                0x6005, 0x8100, 0x7201, 0x73FF, 0x8455, 0x8457, 0x8671, 0x8892, 0x8AB3, 0x8CD6,
+                0x8EFE, 0xC1FF, 0xF207, 0xF30A, 0xF115, 0xF218, 0xF33A,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_i_and_memory() {
+        let source = "
+            : main
+                i := data  i += v1  i := hex v2  i := bighex v3  i := long data
+                save v4  load v5  save v1 - v3  load v2 - v4  bcd v6
+                saveflags v7  loadflags v8
+            : data 0xF0 0x90
+        ";
+
+        assert_eq!(
+            words(&compile_ok(source)),
+            [
+                0xA21A, 0xF11E, 0xF229, 0xF330, 0xF000, 0x021A, 0xF455, 0xF565, 0x5132, 0x5243,
+                0xF633, 0xF775, 0xF885, 0xF090,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_if_then() {
+        let source = "
+            : main
+                if v0 == 1 then v1 := 2
+                if v0 != v1 then v1 := 2
+                if v2 key then v1 := 2
+                if v2 -key then v1 := 2
+                if v3 > 4 then v1 := 2
+        ";
+
+        assert_eq!(
+            words(&compile_ok(source)),
+            [
+                0x4001, 0x6102, 0x5010, 0x6102, 0xE2A1, 0x6102, 0xE29E, 0x6102, 0x6F04, 0x8F35,
+                0x3F01, 0x6102,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_begin_else_end() {
+        let source = "
+            : main
+                if v0 == 1 begin
+                    v1 := 1
+                else
+                    v1 := 2
+                end
+        ";
+
+        assert_eq!(
+            words(&compile_ok(source)),
+            [0x3001, 0x1208, 0x6101, 0x120A, 0x6102]
+        );
+    }
+
+    // NOTE: Runs the comparison on a chip, true if the body ran
+    fn compares(operator: &str, a: u8, b: u8) -> bool {
+        let source = format!(": main v0 := {a} v1 := {b} if v0 {operator} v1 then v2 := 1 exit");
+        let mut chip = Chip::new(DEFAULT_ROM_START, Quirks::default());
+        chip.load_rom_bytes(&compile_ok(&source))
+            .expect("Valid rom");
+
+        while !chip.run_cycle().expect("Valid rom").exit {}
+        chip.registers().v[2] == 1
+    }
+
+    #[test]
+    fn test_comparisons_run() {
+        for (operator, less, equal, greater) in [
+            ("<", true, false, false),
+            ("<=", true, true, false),
+            (">", false, false, true),
+            (">=", false, true, true),
+        ] {
+            assert_eq!(compares(operator, 3, 5), less, "3 {operator} 5");
+            assert_eq!(compares(operator, 5, 5), equal, "5 {operator} 5");
+            assert_eq!(compares(operator, 5, 3), greater, "5 {operator} 3");
+        }
+    }
+
+    #[test]
+    fn test_loop_while() {
+        let source = "
+            : main
+                loop
+                    v0 += 1
+                    while v0 != 10
+                again
+        ";
+
+        assert_eq!(words(&compile_ok(source)), [0x7001, 0x400A, 0x1208, 0x1200]);
+    }
+
+    #[test]
+    fn test_alias_const_calc() {
+        let source = "
+            :alias x v3
+            :const SPEED 2
+            :calc DOUBLE { SPEED * 2 + 1 }
+            : main
+                x := SPEED
+                x += DOUBLE
+                :byte { DOUBLE << 1 }
+        ";
+
+        assert_eq!(words(&compile_ok(source)), [0x1202, 0x6302, 0x7305, 0x0A00]);
+    }
+
+    #[test]
+    fn test_macro() {
+        let source = "
+            :macro add-twice reg amount { reg += amount reg += amount }
+            : main
+                add-twice v1 3
+                add-twice v2 4
+        ";
+
+        assert_eq!(
+            words(&compile_ok(source)),
+            [0x1202, 0x7103, 0x7103, 0x7204, 0x7204]
+        );
+    }
+
+    #[test]
+    fn test_macro_depth() {
+        // NOTE: Each macro invokes the next one twice, so there are far more
+        // expansions than levels
+        let mut source = String::from(":macro level-0 { v0 += 1 }\n");
+        for level in 1..=14 {
+            let previous = level - 1;
+            let _ = writeln!(
+                source,
+                ":macro level-{level} {{ level-{previous} level-{previous} }}"
+            );
+        }
+        source.push_str(": main level-14");
+
+        let words = words(&compile_ok(&source));
+        assert_eq!(words.len(), 1 + (1 << 14));
+        assert!(words[1..].iter().all(|&word| word == 0x7001));
+    }
+
+    #[test]
+    fn test_org() {
+        let rom = compile_ok(": main jump end :org 0x208 : end exit");
+
+        assert_eq!(words(&rom), [0x1208, 0x0000, 0x0000, 0x0000, 0x00FD]);
+    }
+
+    #[test]
+    fn test_long_label() {
+        let rom = compile_ok(": main i := long data :org 0x1200 : data 0x12 0x34");
+
+        assert_eq!(words(&rom[..4]), [0xF000, 0x1200]);
+        assert_eq!(rom[0x1000..], [0x12, 0x34]);
+
+        let e =
+            compile(": main jump data :org 0x1200 : data 0x12").expect_err("data is out of reach");
+        assert!(matches!(
+            e.kind,
+            AsmErrorKind::OutOfRange { value: 0x1200, .. }
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| compile(source).expect_err("invalid source");
+
+        let e = error(": main\n  v0 := 300");
+        assert_eq!((e.line, e.column), (2, 9));
+        assert!(matches!(
+            e.kind,
+            AsmErrorKind::OutOfRange { value: 300, .. }
+        ));
+
+        let e = error(": main\n  jump nowhere");
+        assert_eq!((e.line, e.column), (2, 8));
+        assert!(matches!(e.kind, AsmErrorKind::UndefinedSymbol(_)));
+
+        let e = error(": main loop v0 += 1");
+        assert!(matches!(e.kind, AsmErrorKind::UnmatchedBlock(_)));
+
+        let e = error(": main end");
+        assert!(matches!(e.kind, AsmErrorKind::UnmatchedBlock(_)));
+
+        let e = error(": main : main");
+        assert!(matches!(e.kind, AsmErrorKind::DuplicateSymbol(_)));
+
+        let e = error(":macro forever { forever } : main forever");
+        assert!(matches!(e.kind, AsmErrorKind::RecursiveMacro(_)));
+
+        let e = error(": main :org 0x1000 loop\n  again");
+        assert_eq!(e.line, 2);
+        assert!(matches!(
+            e.kind,
+            AsmErrorKind::OutOfRange { value: 0x1000, .. }
+        ));
+
+        let e = error(": main :org 0xFFC if v0 == 1 begin v1 := 2\nend");
+        assert_eq!(e.line, 2);
+        assert!(matches!(
+            e.kind,
+            AsmErrorKind::OutOfRange { value: 0x1002, .. }
+        ));
+
+        let e = error("clear");
+        assert!(matches!(e.kind, AsmErrorKind::UndefinedSymbol(ref name) if name == "main"));
+
+        let e = error(": main i -= v0");
+        assert!(matches!(e.kind, AsmErrorKind::UnexpectedToken(_)));
+    }
+}
@@ -0,0 +1,152 @@
+use super::{parse_number, Token};
+use crate::asm::AsmErrorKind;
+
+// Index of the token where the problem is and what it is
+type Fault = (usize, AsmErrorKind);
+
+// NOTE: Binding power of the binary operators, higher binds tighter
+fn precedence(operator: &str) -> Option<u8> {
+    let power = match operator {
+        "|" => 1,
+        "^" => 2,
+        "&" => 3,
+        "<<" | ">>" => 4,
+        "+" | "-" => 5,
+        "*" | "/" | "%" => 6,
+        _ => return None,
+    };
+
+    Some(power)
+}
+
+// Integer expression inside the braces of :calc and :byte, the operands can
+// be numbers, constants or labels already defined
+pub fn evaluate(tokens: &[Token], lookup: impl Fn(&str) -> Option<i64>) -> Result<i64, Fault> {
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        lookup: &lookup,
+    };
+
+    let value = parser.expression(0)?;
+    if parser.position < tokens.len() {
+        let token = &tokens[parser.position];
+        return Err((
+            parser.position,
+            AsmErrorKind::UnexpectedToken(token.text.clone()),
+        ));
+    }
+
+    Ok(value)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    lookup: &'a dyn Fn(&str) -> Option<i64>,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Result<&str, Fault> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or((self.position, AsmErrorKind::UnexpectedEnd))?;
+        self.position += 1;
+
+        Ok(&token.text)
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    fn expression(&mut self, min_precedence: u8) -> Result<i64, Fault> {
+        let mut left = self.atom()?;
+
+        while let Some(token) = self.tokens.get(self.position) {
+            let Some(power) = precedence(&token.text) else {
+                break;
+            };
+            if power <= min_precedence {
+                break;
+            }
+
+            let index = self.position;
+            let operator = token.text.clone();
+            self.position += 1;
+            let right = self.expression(power)?;
+
+            left = match operator.as_str() {
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" | "%" if right == 0 => return Err((index, AsmErrorKind::DivisionByZero)),
+                "/" => left.wrapping_div(right),
+                _ => left.wrapping_rem(right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn atom(&mut self) -> Result<i64, Fault> {
+        let index = self.position;
+        let text = self.next()?.to_owned();
+
+        match text.as_str() {
+            "-" => Ok(self.atom()?.wrapping_neg()),
+            "~" => Ok(!self.atom()?),
+            "(" => {
+                let value = self.expression(0)?;
+                let index = self.position;
+                match self.next()? {
+                    ")" => Ok(value),
+                    other => Err((index, AsmErrorKind::UnexpectedToken(other.to_owned()))),
+                }
+            }
+            text => parse_number(text)
+                .or_else(|| (self.lookup)(text))
+                .ok_or_else(|| (index, AsmErrorKind::UndefinedSymbol(text.to_owned()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::octo::tokenize;
+
+    fn calc(source: &str) -> Result<i64, Fault> {
+        evaluate(&tokenize(source), |name| (name == "SIZE").then_some(8))
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(calc("1 + 2 * 3").ok(), Some(7));
+        assert_eq!(calc("( 1 + 2 ) * 3").ok(), Some(9));
+        assert_eq!(calc("1 << 4 | 1").ok(), Some(17));
+        assert_eq!(calc("10 - 4 - 3").ok(), Some(3));
+    }
+
+    #[test]
+    fn test_unary_and_symbols() {
+        assert_eq!(calc("- SIZE + 0x10").ok(), Some(8));
+        assert_eq!(calc("~ 0 & 0xFF").ok(), Some(0xFF));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            calc("1 / 0"),
+            Err((1, AsmErrorKind::DivisionByZero))
+        ));
+        assert!(matches!(
+            calc("1 + WIDTH"),
+            Err((2, AsmErrorKind::UndefinedSymbol(_)))
+        ));
+        assert!(matches!(calc("1 +"), Err((2, AsmErrorKind::UnexpectedEnd))));
+    }
+}
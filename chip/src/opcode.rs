@@ -0,0 +1,415 @@
+use std::fmt::Display;
+
+// x - A 4-bit value, the lower 4 bits of the high byte of the instruction
+// y - A 4-bit value, the upper 4 bits of the low byte of the instruction
+// n or nibble - A 4-bit value, the lowest 4 bits of the instruction
+// nnn or addr - A 12-bit value, the lowest 12 bits of the instruction
+// kk or byte - An 8-bit value, the lowest 8 bits of the instruction
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Opcode {
+    // 00E0 - CLS
+    Cls,
+    // 00EE - RET
+    Ret,
+    // 00Cn - SCD nibble
+    ScrollDown(u8),
+    // 00Dn - SCU nibble
+    ScrollUp(u8),
+    // 00FB - SCR
+    ScrollRight,
+    // 00FC - SCL
+    ScrollLeft,
+    // 00FD - EXIT
+    Exit,
+    // 00FE - LOW
+    Low,
+    // 00FF - HIGH
+    High,
+    // 0nnn - SYS addr
+    Sys(u16),
+    // 1nnn - JP addr
+    Jp(u16),
+    // 2nnn - CALL addr
+    Call(u16),
+    // 3xkk - SE Vx, byte
+    SeByte { x: u8, byte: u8 },
+    // 4xkk - SNE Vx, byte
+    SneByte { x: u8, byte: u8 },
+    // 5xy0 - SE Vx, Vy
+    SeReg { x: u8, y: u8 },
+    // 5xy2 - LD [I], Vx - Vy
+    SaveRange { x: u8, y: u8 },
+    // 5xy3 - LD Vx - Vy, [I]
+    LoadRange { x: u8, y: u8 },
+    // 6xkk - LD Vx, byte
+    LdByte { x: u8, byte: u8 },
+    // 7xkk - ADD Vx, byte
+    AddByte { x: u8, byte: u8 },
+    // 8xy0 - LD Vx, Vy
+    LdReg { x: u8, y: u8 },
+    // 8xy1 - OR Vx, Vy
+    Or { x: u8, y: u8 },
+    // 8xy2 - AND Vx, Vy
+    And { x: u8, y: u8 },
+    // 8xy3 - XOR Vx, Vy
+    Xor { x: u8, y: u8 },
+    // 8xy4 - ADD Vx, Vy
+    AddReg { x: u8, y: u8 },
+    // 8xy5 - SUB Vx, Vy
+    Sub { x: u8, y: u8 },
+    // 8xy6 - SHR Vx {, Vy}
+    Shr { x: u8, y: u8 },
+    // 8xy7 - SUBN Vx, Vy
+    Subn { x: u8, y: u8 },
+    // 8xyE - SHL Vx {, Vy}
+    Shl { x: u8, y: u8 },
+    // 9xy0 - SNE Vx, Vy
+    SneReg { x: u8, y: u8 },
+    // Annn - LD I, addr
+    LdI(u16),
+    // Bnnn - JP V0, addr
+    JpV0(u16),
+    // Cxkk - RND Vx, byte
+    Rnd { x: u8, byte: u8 },
+    // Dxyn - DRW Vx, Vy, nibble
+    Drw { x: u8, y: u8, n: u8 },
+    // Ex9E - SKP Vx
+    Skp(u8),
+    // ExA1 - SKNP Vx
+    Sknp(u8),
+    // F000 nnnn - LD I, long addr
+    // NOTE: The address is the word following the instruction
+    LdILong,
+    // Fn01 - PLANE n
+    Plane(u8),
+    // F002 - AUDIO
+    Audio,
+    // Fx07 - LD Vx, DT
+    LdVxDt(u8),
+    // Fx0A - LD Vx, K
+    LdVxK(u8),
+    // Fx15 - LD DT, Vx
+    LdDtVx(u8),
+    // Fx18 - LD ST, Vx
+    LdStVx(u8),
+    // Fx1E - ADD I, Vx
+    AddIVx(u8),
+    // Fx29 - LD F, Vx
+    LdF(u8),
+    // Fx30 - LD HF, Vx
+    LdHf(u8),
+    // Fx33 - LD B, Vx
+    LdB(u8),
+    // Fx3A - PITCH Vx
+    Pitch(u8),
+    // Fx55 - LD [I], Vx
+    LdIVx(u8),
+    // Fx65 - LD Vx, [I]
+    LdVxI(u8),
+    // Fx75 - LD R, Vx
+    LdRVx(u8),
+    // Fx85 - LD Vx, R
+    LdVxR(u8),
+}
+
+// NOTE: Cowgod mnemonics, registers in hex and values prefixed with 0x
+impl Display for Opcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Cls => write!(f, "CLS"),
+            Self::Ret => write!(f, "RET"),
+            Self::ScrollDown(n) => write!(f, "SCD {n}"),
+            Self::ScrollUp(n) => write!(f, "SCU {n}"),
+            Self::ScrollRight => write!(f, "SCR"),
+            Self::ScrollLeft => write!(f, "SCL"),
+            Self::Exit => write!(f, "EXIT"),
+            Self::Low => write!(f, "LOW"),
+            Self::High => write!(f, "HIGH"),
+            Self::Sys(addr) => write!(f, "SYS {addr:#05X}"),
+            Self::Jp(addr) => write!(f, "JP {addr:#05X}"),
+            Self::Call(addr) => write!(f, "CALL {addr:#05X}"),
+            Self::SeByte { x, byte } => write!(f, "SE V{x:X}, {byte:#04X}"),
+            Self::SneByte { x, byte } => write!(f, "SNE V{x:X}, {byte:#04X}"),
+            Self::SeReg { x, y } => write!(f, "SE V{x:X}, V{y:X}"),
+            Self::SaveRange { x, y } => write!(f, "LD [I], V{x:X} - V{y:X}"),
+            Self::LoadRange { x, y } => write!(f, "LD V{x:X} - V{y:X}, [I]"),
+            Self::LdByte { x, byte } => write!(f, "LD V{x:X}, {byte:#04X}"),
+            Self::AddByte { x, byte } => write!(f, "ADD V{x:X}, {byte:#04X}"),
+            Self::LdReg { x, y } => write!(f, "LD V{x:X}, V{y:X}"),
+            Self::Or { x, y } => write!(f, "OR V{x:X}, V{y:X}"),
+            Self::And { x, y } => write!(f, "AND V{x:X}, V{y:X}"),
+            Self::Xor { x, y } => write!(f, "XOR V{x:X}, V{y:X}"),
+            Self::AddReg { x, y } => write!(f, "ADD V{x:X}, V{y:X}"),
+            Self::Sub { x, y } => write!(f, "SUB V{x:X}, V{y:X}"),
+            Self::Shr { x, y } => write!(f, "SHR V{x:X}, V{y:X}"),
+            Self::Subn { x, y } => write!(f, "SUBN V{x:X}, V{y:X}"),
+            Self::Shl { x, y } => write!(f, "SHL V{x:X}, V{y:X}"),
+            Self::SneReg { x, y } => write!(f, "SNE V{x:X}, V{y:X}"),
+            Self::LdI(addr) => write!(f, "LD I, {addr:#05X}"),
+            Self::JpV0(addr) => write!(f, "JP V0, {addr:#05X}"),
+            Self::Rnd { x, byte } => write!(f, "RND V{x:X}, {byte:#04X}"),
+            Self::Drw { x, y, n } => write!(f, "DRW V{x:X}, V{y:X}, {n}"),
+            Self::Skp(x) => write!(f, "SKP V{x:X}"),
+            Self::Sknp(x) => write!(f, "SKNP V{x:X}"),
+            Self::LdILong => write!(f, "LD I, LONG"),
+            Self::Plane(n) => write!(f, "PLANE {n}"),
+            Self::Audio => write!(f, "AUDIO"),
+            Self::LdVxDt(x) => write!(f, "LD V{x:X}, DT"),
+            Self::LdVxK(x) => write!(f, "LD V{x:X}, K"),
+            Self::LdDtVx(x) => write!(f, "LD DT, V{x:X}"),
+            Self::LdStVx(x) => write!(f, "LD ST, V{x:X}"),
+            Self::AddIVx(x) => write!(f, "ADD I, V{x:X}"),
+            Self::LdF(x) => write!(f, "LD F, V{x:X}"),
+            Self::LdHf(x) => write!(f, "LD HF, V{x:X}"),
+            Self::LdB(x) => write!(f, "LD B, V{x:X}"),
+            Self::Pitch(x) => write!(f, "PITCH V{x:X}"),
+            Self::LdIVx(x) => write!(f, "LD [I], V{x:X}"),
+            Self::LdVxI(x) => write!(f, "LD V{x:X}, [I]"),
+            Self::LdRVx(x) => write!(f, "LD R, V{x:X}"),
+            Self::LdVxR(x) => write!(f, "LD V{x:X}, R"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown opcode {:04X}", self.opcode)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// Returns the nibbles of the instruction, from the highest to the lowest
+const fn nibbles(value: u16) -> (u8, u8, u8, u8) {
+    (
+        (value >> 12) as u8,
+        ((value >> 8) & 0xF) as u8,
+        ((value >> 4) & 0xF) as u8,
+        (value & 0xF) as u8,
+    )
+}
+
+const fn from_nibbles(s: u8, x: u8, y: u8, n: u8) -> u16 {
+    (s as u16) << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | (n as u16 & 0xF)
+}
+
+const fn from_byte(s: u8, x: u8, byte: u8) -> u16 {
+    (s as u16) << 12 | (x as u16 & 0xF) << 8 | byte as u16
+}
+
+const fn from_addr(s: u8, addr: u16) -> u16 {
+    (s as u16) << 12 | (addr & 0x0FFF)
+}
+
+impl Opcode {
+    pub const fn decode(value: u16) -> Result<Self, DecodeError> {
+        let nnn = value & 0x0FFF;
+        let kk = (value & 0x00FF) as u8;
+
+        let opcode = match nibbles(value) {
+            (0x0, 0x0, 0xE, 0x0) => Self::Cls,
+            (0x0, 0x0, 0xE, 0xE) => Self::Ret,
+            (0x0, 0x0, 0xC, n) => Self::ScrollDown(n),
+            (0x0, 0x0, 0xD, n) => Self::ScrollUp(n),
+            (0x0, 0x0, 0xF, 0xB) => Self::ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => Self::ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Self::Exit,
+            (0x0, 0x0, 0xF, 0xE) => Self::Low,
+            (0x0, 0x0, 0xF, 0xF) => Self::High,
+            (0x0, _, _, _) => Self::Sys(nnn),
+            (0x1, _, _, _) => Self::Jp(nnn),
+            (0x2, _, _, _) => Self::Call(nnn),
+            (0x3, x, _, _) => Self::SeByte { x, byte: kk },
+            (0x4, x, _, _) => Self::SneByte { x, byte: kk },
+            (0x5, x, y, 0x0) => Self::SeReg { x, y },
+            (0x5, x, y, 0x2) => Self::SaveRange { x, y },
+            (0x5, x, y, 0x3) => Self::LoadRange { x, y },
+            (0x6, x, _, _) => Self::LdByte { x, byte: kk },
+            (0x7, x, _, _) => Self::AddByte { x, byte: kk },
+            (0x8, x, y, 0x0) => Self::LdReg { x, y },
+            (0x8, x, y, 0x1) => Self::Or { x, y },
+            (0x8, x, y, 0x2) => Self::And { x, y },
+            (0x8, x, y, 0x3) => Self::Xor { x, y },
+            (0x8, x, y, 0x4) => Self::AddReg { x, y },
+            (0x8, x, y, 0x5) => Self::Sub { x, y },
+            (0x8, x, y, 0x6) => Self::Shr { x, y },
+            (0x8, x, y, 0x7) => Self::Subn { x, y },
+            (0x8, x, y, 0xE) => Self::Shl { x, y },
+            (0x9, x, y, 0x0) => Self::SneReg { x, y },
+            (0xA, _, _, _) => Self::LdI(nnn),
+            (0xB, _, _, _) => Self::JpV0(nnn),
+            (0xC, x, _, _) => Self::Rnd { x, byte: kk },
+            (0xD, x, y, n) => Self::Drw { x, y, n },
+            (0xE, x, 0x9, 0xE) => Self::Skp(x),
+            (0xE, x, 0xA, 0x1) => Self::Sknp(x),
+            (0xF, 0x0, 0x0, 0x0) => Self::LdILong,
+            (0xF, n, 0x0, 0x1) => Self::Plane(n),
+            (0xF, 0x0, 0x0, 0x2) => Self::Audio,
+            (0xF, x, 0x0, 0x7) => Self::LdVxDt(x),
+            (0xF, x, 0x0, 0xA) => Self::LdVxK(x),
+            (0xF, x, 0x1, 0x5) => Self::LdDtVx(x),
+            (0xF, x, 0x1, 0x8) => Self::LdStVx(x),
+            (0xF, x, 0x1, 0xE) => Self::AddIVx(x),
+            (0xF, x, 0x2, 0x9) => Self::LdF(x),
+            (0xF, x, 0x3, 0x0) => Self::LdHf(x),
+            (0xF, x, 0x3, 0x3) => Self::LdB(x),
+            (0xF, x, 0x3, 0xA) => Self::Pitch(x),
+            (0xF, x, 0x5, 0x5) => Self::LdIVx(x),
+            (0xF, x, 0x6, 0x5) => Self::LdVxI(x),
+            (0xF, x, 0x7, 0x5) => Self::LdRVx(x),
+            (0xF, x, 0x8, 0x5) => Self::LdVxR(x),
+            _ => return Err(DecodeError { opcode: value }),
+        };
+
+        Ok(opcode)
+    }
+
+    #[must_use]
+    pub const fn encode(self) -> u16 {
+        match self {
+            Self::Cls => 0x00E0,
+            Self::Ret => 0x00EE,
+            Self::ScrollDown(n) => from_nibbles(0x0, 0x0, 0xC, n),
+            Self::ScrollUp(n) => from_nibbles(0x0, 0x0, 0xD, n),
+            Self::ScrollRight => 0x00FB,
+            Self::ScrollLeft => 0x00FC,
+            Self::Exit => 0x00FD,
+            Self::Low => 0x00FE,
+            Self::High => 0x00FF,
+            Self::Sys(addr) => from_addr(0x0, addr),
+            Self::Jp(addr) => from_addr(0x1, addr),
+            Self::Call(addr) => from_addr(0x2, addr),
+            Self::SeByte { x, byte } => from_byte(0x3, x, byte),
+            Self::SneByte { x, byte } => from_byte(0x4, x, byte),
+            Self::SeReg { x, y } => from_nibbles(0x5, x, y, 0x0),
+            Self::SaveRange { x, y } => from_nibbles(0x5, x, y, 0x2),
+            Self::LoadRange { x, y } => from_nibbles(0x5, x, y, 0x3),
+            Self::LdByte { x, byte } => from_byte(0x6, x, byte),
+            Self::AddByte { x, byte } => from_byte(0x7, x, byte),
+            Self::LdReg { x, y } => from_nibbles(0x8, x, y, 0x0),
+            Self::Or { x, y } => from_nibbles(0x8, x, y, 0x1),
+            Self::And { x, y } => from_nibbles(0x8, x, y, 0x2),
+            Self::Xor { x, y } => from_nibbles(0x8, x, y, 0x3),
+            Self::AddReg { x, y } => from_nibbles(0x8, x, y, 0x4),
+            Self::Sub { x, y } => from_nibbles(0x8, x, y, 0x5),
+            Self::Shr { x, y } => from_nibbles(0x8, x, y, 0x6),
+            Self::Subn { x, y } => from_nibbles(0x8, x, y, 0x7),
+            Self::Shl { x, y } => from_nibbles(0x8, x, y, 0xE),
+            Self::SneReg { x, y } => from_nibbles(0x9, x, y, 0x0),
+            Self::LdI(addr) => from_addr(0xA, addr),
+            Self::JpV0(addr) => from_addr(0xB, addr),
+            Self::Rnd { x, byte } => from_byte(0xC, x, byte),
+            Self::Drw { x, y, n } => from_nibbles(0xD, x, y, n),
+            Self::Skp(x) => from_byte(0xE, x, 0x9E),
+            Self::Sknp(x) => from_byte(0xE, x, 0xA1),
+            Self::LdILong => 0xF000,
+            Self::Plane(n) => from_byte(0xF, n, 0x01),
+            Self::Audio => 0xF002,
+            Self::LdVxDt(x) => from_byte(0xF, x, 0x07),
+            Self::LdVxK(x) => from_byte(0xF, x, 0x0A),
+            Self::LdDtVx(x) => from_byte(0xF, x, 0x15),
+            Self::LdStVx(x) => from_byte(0xF, x, 0x18),
+            Self::AddIVx(x) => from_byte(0xF, x, 0x1E),
+            Self::LdF(x) => from_byte(0xF, x, 0x29),
+            Self::LdHf(x) => from_byte(0xF, x, 0x30),
+            Self::LdB(x) => from_byte(0xF, x, 0x33),
+            Self::Pitch(x) => from_byte(0xF, x, 0x3A),
+            Self::LdIVx(x) => from_byte(0xF, x, 0x55),
+            Self::LdVxI(x) => from_byte(0xF, x, 0x65),
+            Self::LdRVx(x) => from_byte(0xF, x, 0x75),
+            Self::LdVxR(x) => from_byte(0xF, x, 0x85),
+        }
+    }
+
+    // NOTE: Size in bytes of the instruction, F000 nnnn takes two words
+    #[must_use]
+    pub const fn size(self) -> u16 {
+        match self {
+            Self::LdILong => 4,
+            _ => 2,
+        }
+    }
+
+    // NOTE: Address the instruction may transfer control to, the base one for JP V0
+    #[must_use]
+    pub const fn target(self) -> Option<u16> {
+        match self {
+            Self::Jp(addr) | Self::Call(addr) | Self::JpV0(addr) => Some(addr),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nibbles() {
+        assert_eq!((1, 2, 3, 4), nibbles(0x1234));
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Opcode::decode(0x00E0), Ok(Opcode::Cls));
+        assert_eq!(Opcode::decode(0x00C4), Ok(Opcode::ScrollDown(4)));
+        assert_eq!(Opcode::decode(0x0123), Ok(Opcode::Sys(0x123)));
+        assert_eq!(Opcode::decode(0x1234), Ok(Opcode::Jp(0x234)));
+        assert_eq!(
+            Opcode::decode(0x3A42),
+            Ok(Opcode::SeByte { x: 0xA, byte: 0x42 })
+        );
+        assert_eq!(Opcode::decode(0x8AB6), Ok(Opcode::Shr { x: 0xA, y: 0xB }));
+        assert_eq!(Opcode::decode(0xD125), Ok(Opcode::Drw { x: 1, y: 2, n: 5 }));
+        assert_eq!(Opcode::decode(0xE59E), Ok(Opcode::Skp(5)));
+        assert_eq!(Opcode::decode(0xF000), Ok(Opcode::LdILong));
+        assert_eq!(Opcode::decode(0xF301), Ok(Opcode::Plane(3)));
+        assert_eq!(Opcode::decode(0xF765), Ok(Opcode::LdVxI(7)));
+    }
+
+    #[test]
+    fn test_decode_unknown() {
+        for value in [0x5001, 0x800F, 0xE000, 0xF102, 0xFFFF] {
+            assert_eq!(Opcode::decode(value), Err(DecodeError { opcode: value }));
+        }
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        for value in 0..=u16::MAX {
+            if let Ok(opcode) = Opcode::decode(value) {
+                assert_eq!(opcode.encode(), value, "{opcode:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Opcode::Cls.to_string(), "CLS");
+        assert_eq!(Opcode::Jp(0x234).to_string(), "JP 0x234");
+        assert_eq!(
+            Opcode::LdByte { x: 0xA, byte: 0x4 }.to_string(),
+            "LD VA, 0x04"
+        );
+        assert_eq!(
+            Opcode::Drw { x: 1, y: 2, n: 5 }.to_string(),
+            "DRW V1, V2, 5"
+        );
+        assert_eq!(
+            Opcode::SaveRange { x: 1, y: 3 }.to_string(),
+            "LD [I], V1 - V3"
+        );
+        assert_eq!(Opcode::LdVxI(0xF).to_string(), "LD VF, [I]");
+    }
+
+    #[test]
+    fn test_size() {
+        assert_eq!(Opcode::LdILong.size(), 4);
+        assert_eq!(Opcode::Cls.size(), 2);
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+// Behaviour of the instructions that differ between CHIP-8 interpreters
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Quirks {
+    // 8xy6/8xyE - Shift Vy into Vx instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    // Bnnn - Jump to nnn + Vx (Bxnn) instead of nnn + V0
+    pub jump_uses_vx: bool,
+    // Fx55/Fx65 - Leave I pointing after the last register transferred
+    pub load_store_increments_i: bool,
+    // 8xy1/8xy2/8xy3 - Reset VF to 0 after the logic operation
+    pub logic_resets_vf: bool,
+    // Dxyn - Wrap sprites around the screen edges instead of clipping them
+    pub sprite_wraps: bool,
+}
+
+impl Quirks {
+    #[must_use]
+    pub const fn cosmac_vip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            load_store_increments_i: true,
+            logic_resets_vf: true,
+            sprite_wraps: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn schip() -> Self {
+        Self {
+            shift_uses_vy: false,
+            jump_uses_vx: true,
+            load_store_increments_i: false,
+            logic_resets_vf: false,
+            sprite_wraps: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn xo_chip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            load_store_increments_i: true,
+            logic_resets_vf: false,
+            sprite_wraps: true,
+        }
+    }
+}
+
+// NOTE: Names of the quirks in the order of the fields, for combinations
+// that no interpreter uses
+const NAMES: [&str; 5] = [
+    "shift-uses-vy",
+    "jump-uses-vx",
+    "load-store-increments-i",
+    "logic-resets-vf",
+    "sprite-wraps",
+];
+
+impl Quirks {
+    fn presets() -> [(&'static str, Self); 4] {
+        [
+            ("default", Self::default()),
+            ("cosmac-vip", Self::cosmac_vip()),
+            ("schip", Self::schip()),
+            ("xo-chip", Self::xo_chip()),
+        ]
+    }
+
+    const fn flags(self) -> [bool; NAMES.len()] {
+        [
+            self.shift_uses_vy,
+            self.jump_uses_vx,
+            self.load_store_increments_i,
+            self.logic_resets_vf,
+            self.sprite_wraps,
+        ]
+    }
+
+    const fn flags_mut(&mut self) -> [&mut bool; NAMES.len()] {
+        [
+            &mut self.shift_uses_vy,
+            &mut self.jump_uses_vx,
+            &mut self.load_store_increments_i,
+            &mut self.logic_resets_vf,
+            &mut self.sprite_wraps,
+        ]
+    }
+}
+
+// NOTE: Quirks are named after the interpreter they imitate, like the
+// options of the frontends, other combinations list the quirks enabled
+impl Display for Quirks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((name, _)) = Self::presets()
+            .into_iter()
+            .find(|(_, quirks)| quirks == self)
+        {
+            return write!(f, "{name}");
+        }
+
+        let enabled: Vec<&str> = NAMES
+            .into_iter()
+            .zip(self.flags())
+            .filter_map(|(name, enabled)| enabled.then_some(name))
+            .collect();
+        write!(f, "{}", enabled.join(","))
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, quirks)) = Self::presets().into_iter().find(|(name, _)| *name == s) {
+            return Ok(quirks);
+        }
+
+        let mut quirks = Self::default();
+        for name in s.split(',') {
+            let index = NAMES.iter().position(|quirk| *quirk == name).ok_or_else(|| {
+                format!(
+                    "Unknown quirks {s}, expected default, cosmac-vip, schip, xo-chip or a list of {}",
+                    NAMES.join(",")
+                )
+            })?;
+            *quirks.flags_mut()[index] = true;
+        }
+
+        Ok(quirks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quirks_names() {
+        for (name, quirks) in Quirks::presets() {
+            assert_eq!(quirks.to_string(), name);
+            assert_eq!(name.parse(), Ok(quirks));
+        }
+
+        let quirks = Quirks {
+            jump_uses_vx: true,
+            sprite_wraps: true,
+            ..Quirks::default()
+        };
+        assert_eq!(quirks.to_string(), "jump-uses-vx,sprite-wraps");
+        assert_eq!("jump-uses-vx,sprite-wraps".parse(), Ok(quirks));
+
+        assert!("chip-48".parse::<Quirks>().is_err());
+        assert!("sprite-wraps,".parse::<Quirks>().is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::io::Write;
+
+use crate::{Screen, PLANES_COUNT};
+
+pub const PALETTE_SIZE: usize = 1 << PLANES_COUNT;
+
+// NOTE: RGB colors for each combination of planes: none, first, second and
+// both
+pub type Palette = [[u8; 3]; PALETTE_SIZE];
+
+pub const DEFAULT_PALETTE: Palette = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+];
+
+// NOTE: Lines of plain PBM files should not be longer than 70 characters
+const PBM_LINE_LENGTH: usize = 64;
+
+impl Screen {
+    // NOTE: Each pixel of the screen is a square of scale by scale pixels in
+    // the image, colored by the palette
+    pub fn write_png(
+        &self,
+        writer: impl Write,
+        scale: usize,
+        palette: &Palette,
+    ) -> std::io::Result<()> {
+        let (width, height) = (self.width() * scale, self.height() * scale);
+
+        let mut data = Vec::with_capacity(width * height);
+        for row in self.rows() {
+            let line: Vec<u8> = row
+                .iter()
+                .flat_map(|pixel| std::iter::repeat_n(*pixel, scale))
+                .collect();
+            for _ in 0..scale {
+                data.extend(&line);
+            }
+        }
+
+        let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(palette.concat());
+
+        encoder.write_header()?.write_image_data(&data)?;
+
+        Ok(())
+    }
+
+    // NOTE: Plain PBM at the size of the screen, pixels lit in any plane are
+    // 1, which viewers show in black
+    pub fn write_pbm(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "P1")?;
+        writeln!(writer, "{} {}", self.width(), self.height())?;
+
+        for row in self.rows() {
+            for pixels in row.chunks(PBM_LINE_LENGTH) {
+                let line: String = pixels
+                    .iter()
+                    .map(|pixel| if *pixel == 0 { '0' } else { '1' })
+                    .collect();
+                writeln!(writer, "{line}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::Display;
+    use crate::Resolution;
+
+    fn display() -> Display {
+        let mut display = Display::default();
+        display.draw_sprite(&[0b1010_0000], 0, 0, false);
+        display.select_planes(0b10);
+        display.draw_sprite(&[0b1100_0000], 0, 1, false);
+        display
+    }
+
+    #[test]
+    fn test_write_png() {
+        let display = display();
+        let mut png = Vec::new();
+        display
+            .screen()
+            .write_png(&mut png, 2, &DEFAULT_PALETTE)
+            .expect("Written to memory");
+
+        let decoder = png::Decoder::new(png.as_slice());
+        let mut reader = decoder.read_info().expect("Valid png");
+        let info = reader.info();
+        assert_eq!((info.width, info.height), (128, 64));
+        assert_eq!(
+            info.palette.as_deref(),
+            Some(DEFAULT_PALETTE.concat().as_slice())
+        );
+
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).expect("Valid png");
+        assert_eq!(pixels[..6], [1, 1, 0, 0, 1, 1]);
+        assert_eq!(pixels[128..134], [1, 1, 0, 0, 1, 1]);
+        assert_eq!(pixels[256..262], [2, 2, 2, 2, 0, 0]);
+    }
+
+    #[test]
+    fn test_write_pbm() {
+        let mut display = display();
+        let mut pbm = Vec::new();
+        display
+            .screen()
+            .write_pbm(&mut pbm)
+            .expect("Written to memory");
+
+        let text = String::from_utf8(pbm).expect("Plain pbm");
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[..2], ["P1", "64 32"]);
+        assert_eq!(lines.len(), 2 + 32);
+        assert!(lines[2].starts_with("1010000"));
+        assert!(lines[3].starts_with("1100000"));
+
+        display.set_resolution(Resolution::High);
+        let mut pbm = Vec::new();
+        display
+            .screen()
+            .write_pbm(&mut pbm)
+            .expect("Written to memory");
+        let text = String::from_utf8(pbm).expect("Plain pbm");
+        assert_eq!(text.lines().nth(1), Some("128 64"));
+        assert_eq!(text.lines().count(), 2 + 64 * 2);
+        assert!(text.lines().all(|line| line.len() <= PBM_LINE_LENGTH));
+    }
+}
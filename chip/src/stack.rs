@@ -1,3 +1,6 @@
+use crate::state::{Snapshot, StateReader, StateWriter};
+use crate::ChipError;
+
 const STACK_SIZE: usize = 16;
 
 #[derive(Default)]
@@ -7,18 +10,72 @@ pub struct Stack {
 }
 
 impl Stack {
-    pub fn push(&mut self, value: u16) {
-        assert!(self.stack_pointer < STACK_SIZE, "Stack is full");
-        self.array[self.stack_pointer] = value;
+    pub fn push(&mut self, value: u16) -> Result<(), ChipError> {
+        let slot = self
+            .array
+            .get_mut(self.stack_pointer)
+            .ok_or(ChipError::StackOverflow)?;
+
+        *slot = value;
         self.stack_pointer += 1;
+
+        Ok(())
     }
 
-    pub fn pop(&mut self) -> u16 {
-        assert!(self.stack_pointer != 0, "Stack is empty");
+    pub const fn pop(&mut self) -> Result<u16, ChipError> {
+        if self.stack_pointer == 0 {
+            return Err(ChipError::StackUnderflow);
+        }
 
         self.stack_pointer -= 1;
 
-        self.array[self.stack_pointer]
+        Ok(self.array[self.stack_pointer])
+    }
+
+    pub fn values(&self) -> &[u16] {
+        &self.array[..self.stack_pointer]
+    }
+
+    pub const fn pointer(&self) -> usize {
+        self.stack_pointer
+    }
+
+    // NOTE: Entries above the pointer keep their old values, like on the
+    // original interpreters
+    pub const fn set_pointer(&mut self, pointer: usize) -> Result<(), ChipError> {
+        if pointer > STACK_SIZE {
+            return Err(ChipError::StackOverflow);
+        }
+
+        self.stack_pointer = pointer;
+
+        Ok(())
+    }
+}
+
+impl Snapshot for Stack {
+    fn save(&self, writer: &mut StateWriter) {
+        for value in self.array {
+            writer.write_u16(value);
+        }
+        writer.write_u8(self.stack_pointer as u8);
+    }
+
+    fn load(reader: &mut StateReader) -> Result<Self, ChipError> {
+        let mut array = [0; STACK_SIZE];
+        for value in &mut array {
+            *value = reader.read_u16()?;
+        }
+
+        let stack_pointer = reader.read_u8()? as usize;
+        if stack_pointer > STACK_SIZE {
+            return Err(ChipError::InvalidState);
+        }
+
+        Ok(Self {
+            array,
+            stack_pointer,
+        })
     }
 }
 
@@ -30,30 +87,45 @@ mod tests {
     fn test_stack_capacity() {
         let mut stack = Stack::default();
 
-        for _ in 0..STACK_SIZE {
-            stack.push(1);
+        for i in 0..STACK_SIZE {
+            assert!(stack.push(i as u16).is_ok());
         }
 
-        for _ in 0..STACK_SIZE {
-            stack.pop();
+        for i in (0..STACK_SIZE).rev() {
+            assert_eq!(stack.pop().ok(), Some(i as u16));
         }
     }
 
     #[test]
-    #[should_panic(expected = "Stack is full")]
     fn test_stack_full() {
         let mut stack = Stack::default();
 
-        for _ in 0..=STACK_SIZE {
-            stack.push(1);
+        for _ in 0..STACK_SIZE {
+            assert!(stack.push(1).is_ok());
         }
+
+        assert!(matches!(stack.push(1), Err(ChipError::StackOverflow)));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut stack = Stack::default();
+        stack.push(0x123).expect("Stack has space");
+
+        let mut writer = StateWriter::default();
+        stack.save(&mut writer);
+        let bytes = writer.into_bytes();
+
+        let mut loaded = Stack::load(&mut StateReader::new(&bytes)).expect("Valid state");
+
+        assert_eq!(loaded.pop().ok(), Some(0x123));
+        assert!(loaded.pop().is_err());
     }
 
     #[test]
-    #[should_panic(expected = "Stack is empty")]
     fn test_stack_empty() {
         let mut stack = Stack::default();
 
-        stack.pop();
+        assert!(matches!(stack.pop(), Err(ChipError::StackUnderflow)));
     }
 }
@@ -0,0 +1,122 @@
+use crate::ChipError;
+
+pub const STATE_MAGIC: [u8; 4] = *b"CHPY";
+pub const STATE_VERSION: u8 = 1;
+
+// Binary representation of a part of the machine, used for save states
+pub trait Snapshot: Sized {
+    fn save(&self, writer: &mut StateWriter);
+    fn load(reader: &mut StateReader) -> Result<Self, ChipError>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    buffer: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(u8::from(value));
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+}
+
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, ChipError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, ChipError> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, ChipError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ChipError::InvalidState),
+        }
+    }
+
+    pub const fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], ChipError> {
+        if self.bytes.len() < count {
+            return Err(ChipError::InvalidState);
+        }
+
+        let (bytes, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], ChipError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+
+        Ok(array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = StateWriter::default();
+        writer.write_u8(0x12);
+        writer.write_u16(0x3456);
+        writer.write_bool(true);
+        writer.write_bytes(&[7, 8, 9]);
+
+        let bytes = writer.into_bytes();
+        let mut reader = StateReader::new(&bytes);
+
+        assert_eq!(reader.read_u8().ok(), Some(0x12));
+        assert_eq!(reader.read_u16().ok(), Some(0x3456));
+        assert_eq!(reader.read_bool().ok(), Some(true));
+        assert_eq!(reader.read_array().ok(), Some([7, 8, 9]));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_read_past_end() {
+        let mut reader = StateReader::new(&[1]);
+
+        assert!(matches!(reader.read_u16(), Err(ChipError::InvalidState)));
+    }
+
+    #[test]
+    fn test_invalid_bool() {
+        let mut reader = StateReader::new(&[2]);
+
+        assert!(matches!(reader.read_bool(), Err(ChipError::InvalidState)));
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::{Opcode, Registers, REGISTERS_COUNT};
+
+// NOTE: Called before every instruction runs, even one that fails
+pub type TraceHook = Box<dyn FnMut(&TraceEntry) + Send>;
+
+const MNEMONIC_WIDTH: usize = 18;
+const FIELDS_AFTER_MNEMONIC: usize = 5;
+
+// State of the chip right before an instruction runs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    // NOTE: Number of instructions run before this one
+    pub cycle: u64,
+    pub opcode: u16,
+    pub registers: Registers,
+}
+
+impl TraceEntry {
+    // NOTE: Unknown opcodes are traced too, the chip fails right after
+    #[must_use]
+    pub fn mnemonic(&self) -> String {
+        Opcode::decode(self.opcode).map_or_else(|_| String::from("UNKNOWN"), |op| op.to_string())
+    }
+}
+
+// NOTE: The format is stable so traces can be compared between versions and
+// with other emulators. Every line is the cycle in decimal, PC, the opcode,
+// its mnemonic and the registers in hex, like:
+// 0000000042 0204 2208 CALL 0x208         V=12000000000000000000000000000000 I=02FF SP=00 DT=00 ST=00
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let registers = &self.registers;
+
+        write!(
+            f,
+            "{:010} {:04X} {:04X} {:<MNEMONIC_WIDTH$} V=",
+            self.cycle,
+            registers.pc,
+            self.opcode,
+            self.mnemonic(),
+        )?;
+        for v in registers.v {
+            write!(f, "{v:02X}")?;
+        }
+
+        write!(
+            f,
+            " I={:04X} SP={:02X} DT={:02X} ST={:02X}",
+            registers.i, registers.sp, registers.delay_timer, registers.sound_timer,
+        )
+    }
+}
+
+// NOTE: The mnemonic is skipped, it is derived from the opcode and other
+// emulators may write it differently
+impl FromStr for TraceEntry {
+    type Err = ParseTraceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseTraceError { text: s.to_owned() };
+
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() < 3 + FIELDS_AFTER_MNEMONIC {
+            return Err(error());
+        }
+        let (start, end) = (
+            &fields[..3],
+            &fields[fields.len() - FIELDS_AFTER_MNEMONIC..],
+        );
+
+        let field = |text, name| field(text, name).ok_or_else(error);
+        let hex = |text: &str| u16::from_str_radix(text, 16).map_err(|_| error());
+        let byte = |text: &str| u8::from_str_radix(text, 16).map_err(|_| error());
+
+        let v = field(end[0], "V")?;
+        if v.len() != 2 * REGISTERS_COUNT || !v.is_ascii() {
+            return Err(error());
+        }
+        let mut v_registers = [0; REGISTERS_COUNT];
+        for (register, index) in v_registers.iter_mut().zip((0..v.len()).step_by(2)) {
+            *register = byte(&v[index..index + 2])?;
+        }
+
+        Ok(Self {
+            cycle: start[0].parse().map_err(|_| error())?,
+            opcode: hex(start[2])?,
+            registers: Registers {
+                v: v_registers,
+                i: hex(field(end[1], "I")?)?,
+                pc: hex(start[1])?,
+                delay_timer: byte(field(end[3], "DT")?)?,
+                sound_timer: byte(field(end[4], "ST")?)?,
+                sp: byte(field(end[2], "SP")?)?,
+            },
+        })
+    }
+}
+
+// NOTE: Value of a field written as NAME=value
+fn field<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    text.strip_prefix(name)?.strip_prefix('=')
+}
+
+#[derive(Debug)]
+pub struct ParseTraceError {
+    pub text: String,
+}
+
+impl Display for ParseTraceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid trace line '{}'", self.text)
+    }
+}
+
+impl std::error::Error for ParseTraceError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> TraceEntry {
+        let mut v = [0; REGISTERS_COUNT];
+        v[0x0] = 0x12;
+        v[0xF] = 0x01;
+
+        TraceEntry {
+            cycle: 42,
+            opcode: 0x2208,
+            registers: Registers {
+                v,
+                i: 0x2FF,
+                pc: 0x204,
+                delay_timer: 0x3C,
+                sound_timer: 0,
+                sp: 1,
+            },
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            entry().to_string(),
+            "0000000042 0204 2208 CALL 0x208         \
+             V=12000000000000000000000000000001 I=02FF SP=01 DT=3C ST=00"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            entry().to_string().parse::<TraceEntry>().ok(),
+            Some(entry())
+        );
+
+        let other =
+            "42 0204 2208 call 0x208 V=12000000000000000000000000000001 I=02FF SP=01 DT=3C ST=00";
+        assert_eq!(other.parse::<TraceEntry>().ok(), Some(entry()));
+
+        assert!("42 0204 2208".parse::<TraceEntry>().is_err());
+        assert!("42 0204 2208 CALL V=12 I=02FF SP=01 DT=3C ST=00"
+            .parse::<TraceEntry>()
+            .is_err());
+        assert!(
+            "42 0204 2208 CALL V=12000000000000000000000000000001 I=02FF DT=3C SP=01 ST=00"
+                .parse::<TraceEntry>()
+                .is_err()
+        );
+    }
+}
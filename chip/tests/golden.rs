@@ -0,0 +1,182 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use chip::{Chip, Key, Quirks, DEFAULT_ROM_START};
+
+// NOTE: The same pace as the frontend by default, the timers tick once every
+// this many instructions
+const CYCLES_PER_FRAME: u64 = 10;
+const SEED: u64 = 0;
+
+// NOTE: Set to write the screens of the roms as the new golden images
+// instead of comparing them
+const BLESS_VARIABLE: &str = "CHIPPY_BLESS";
+
+// A test rom, assembled if it ends in .asm, and how to run it
+struct Case {
+    rom: &'static str,
+    quirks: Quirks,
+    frames: u64,
+    // NOTE: Frame where each key is pressed and for how many frames
+    keys: &'static [(u64, Key, u64)],
+    // NOTE: Whether the rom exits when it is done, roms that loop forever
+    // are stopped after the frames
+    exits: bool,
+}
+
+impl Case {
+    const fn new(rom: &'static str, quirks: Quirks, frames: u64) -> Self {
+        Self {
+            rom,
+            quirks,
+            frames,
+            keys: &[],
+            exits: false,
+        }
+    }
+
+    const fn keys(mut self, keys: &'static [(u64, Key, u64)]) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    const fn exits(mut self) -> Self {
+        self.exits = true;
+        self
+    }
+}
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn load(rom: &str) -> Vec<u8> {
+    let path = tests_dir().join("roms").join(rom);
+
+    if path.extension().is_some_and(|extension| extension == "asm") {
+        chip::asm::assemble_file(&path).unwrap_or_else(|error| panic!("{error}"))
+    } else {
+        std::fs::read(&path).unwrap_or_else(|error| panic!("{}: {error}", path.display()))
+    }
+}
+
+// NOTE: Returns whether the rom exited before running all the frames
+fn run(chip: &mut Chip, case: &Case) -> bool {
+    for frame in 0..case.frames {
+        for &(start, key, frames) in case.keys {
+            if frame == start + frames {
+                chip.unpress_key(key);
+            }
+            if frame == start {
+                chip.press_key(key);
+            }
+        }
+
+        for _ in 0..CYCLES_PER_FRAME {
+            let actions = chip
+                .run_cycle()
+                .unwrap_or_else(|error| panic!("{}: {error}", case.rom));
+            if actions.exit {
+                return true;
+            }
+        }
+        chip.tick_timers();
+    }
+
+    false
+}
+
+fn pbm(chip: &Chip) -> String {
+    let mut pbm = Vec::new();
+    chip.screen()
+        .write_pbm(&mut pbm)
+        .expect("Written to memory");
+
+    String::from_utf8(pbm).expect("Plain pbm")
+}
+
+// NOTE: Only the rows that differ are shown, the whole screen is written
+// next to the other temporary files
+fn differences(expected: &str, actual: &str) -> String {
+    let mut message = String::new();
+
+    for (row, (expected, actual)) in expected.lines().zip(actual.lines()).enumerate() {
+        if expected != actual {
+            let _ = write!(message, "\nline {}\n- {expected}\n+ {actual}", row + 1);
+        }
+    }
+    if expected.lines().count() != actual.lines().count() {
+        message.push_str("\nthe images have a different number of lines");
+    }
+
+    message
+}
+
+fn check_golden(case: &Case) {
+    let mut chip = Chip::with_seed(DEFAULT_ROM_START, case.quirks, SEED);
+    chip.load_rom_bytes(&load(case.rom))
+        .expect("The rom fits in memory");
+
+    let exited = run(&mut chip, case);
+    assert_eq!(exited, case.exits, "{} exited: {exited}", case.rom);
+
+    let name = Path::new(case.rom)
+        .file_stem()
+        .expect("Roms have a name")
+        .to_string_lossy()
+        .into_owned();
+    let golden = tests_dir().join("golden").join(format!("{name}.pbm"));
+    let actual = pbm(&chip);
+
+    if std::env::var_os(BLESS_VARIABLE).is_some() {
+        std::fs::write(&golden, &actual).expect("Golden image written");
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&golden).unwrap_or_else(|error| {
+        panic!(
+            "{}: {error}, run with {BLESS_VARIABLE}=1 to create it",
+            golden.display()
+        )
+    });
+    if expected != actual {
+        let path = std::env::temp_dir().join(format!("chippy-golden-{name}.pbm"));
+        std::fs::write(&path, &actual).expect("Screen written");
+        panic!(
+            "{} doesn't match {}, the screen is in {}{}",
+            case.rom,
+            golden.display(),
+            path.display(),
+            differences(&expected, &actual)
+        );
+    }
+}
+
+#[test]
+fn test_flags() {
+    check_golden(&Case::new("flags.asm", Quirks::default(), 60).exits());
+}
+
+#[test]
+fn test_opcodes() {
+    check_golden(&Case::new("opcodes.asm", Quirks::default(), 60).exits());
+}
+
+#[test]
+fn test_keypad() {
+    check_golden(
+        &Case::new("keypad.asm", Quirks::default(), 60)
+            .keys(&[(10, Key::Key5, 5)])
+            .exits(),
+    );
+}
+
+#[test]
+fn test_schip() {
+    check_golden(&Case::new("schip.asm", Quirks::schip(), 60).exits());
+}
+
+#[test]
+fn test_xo_chip() {
+    check_golden(&Case::new("xochip.asm", Quirks::xo_chip(), 60).exits());
+}
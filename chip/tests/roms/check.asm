@@ -0,0 +1,18 @@
+; Draws a tick if VA equals VB and a cross otherwise, in rows of 12 from the
+; top left corner. VC and VD hold the position of the next mark
+check:
+    LD I, pass
+    SE VA, VB
+    LD I, fail
+    DRW VC, VD, 4
+    ADD VC, 5
+    SE VC, 60
+    RET
+    LD VC, 0
+    ADD VD, 5
+    RET
+
+pass:
+    DB 0b00010000, 0b00010000, 0b10100000, 0b01000000
+fail:
+    DB 0b10100000, 0b01000000, 0b10100000, 0b00000000
@@ -0,0 +1,141 @@
+; 8xy1 to 8xyE with the default quirks, the results first and then VF, also
+; with VF as the destination
+
+    ; OR, AND and XOR leave VF alone
+    LD VF, 0x07
+    LD V1, 0b1100
+    LD V2, 0b1010
+    OR V1, V2
+    LD VA, V1
+    LD VB, 0b1110
+    CALL check
+    LD V1, 0b1100
+    AND V1, V2
+    LD VA, V1
+    LD VB, 0b1000
+    CALL check
+    LD VF, 0x07
+    LD V1, 0b1100
+    XOR V1, V2
+    LD V3, VF
+    LD VA, V1
+    LD VB, 0b0110
+    CALL check
+    LD VA, V3
+    LD VB, 0x07
+    CALL check
+
+    ; ADD without and with carry
+    LD V1, 0x01
+    LD V2, 0x01
+    ADD V1, V2
+    LD V3, VF
+    LD VA, V1
+    LD VB, 0x02
+    CALL check
+    LD VA, V3
+    LD VB, 0
+    CALL check
+    LD V1, 0xFF
+    ADD V1, V2
+    LD V3, VF
+    LD VA, V1
+    LD VB, 0
+    CALL check
+    LD VA, V3
+    LD VB, 1
+    CALL check
+
+    ; SUB without borrow when both are equal and with borrow
+    LD V1, 0x05
+    LD V2, 0x05
+    SUB V1, V2
+    LD V3, VF
+    LD VA, V1
+    LD VB, 0
+    CALL check
+    LD VA, V3
+    LD VB, 1
+    CALL check
+    LD V1, 0x04
+    SUB V1, V2
+    LD V3, VF
+    LD VA, V1
+    LD VB, 0xFF
+    CALL check
+    LD VA, V3
+    LD VB, 0
+    CALL check
+
+    ; SUBN the same way
+    LD V1, 0x05
+    SUBN V1, V2
+    LD V3, VF
+    LD VA, V1
+    LD VB, 0
+    CALL check
+    LD VA, V3
+    LD VB, 1
+    CALL check
+    LD V1, 0x06
+    SUBN V1, V2
+    LD V3, VF
+    LD VA, V1
+    LD VB, 0xFF
+    CALL check
+    LD VA, V3
+    LD VB, 0
+    CALL check
+
+    ; SHR and SHL shift Vx in place
+    LD V1, 0x03
+    SHR V1, V2
+    LD V3, VF
+    LD VA, V1
+    LD VB, 0x01
+    CALL check
+    LD VA, V3
+    LD VB, 1
+    CALL check
+    LD V1, 0x81
+    SHL V1, V2
+    LD V3, VF
+    LD VA, V1
+    LD VB, 0x02
+    CALL check
+    LD VA, V3
+    LD VB, 1
+    CALL check
+
+    ; The flag wins over the result in VF
+    LD VF, 0x10
+    LD V1, 0x02
+    ADD VF, V1
+    LD VA, VF
+    LD VB, 0
+    CALL check
+    LD VF, 0x10
+    SUB VF, V1
+    LD VA, VF
+    LD VB, 1
+    CALL check
+    LD VF, 0x20
+    LD V1, 0x10
+    SUBN VF, V1
+    LD VA, VF
+    LD VB, 0
+    CALL check
+    LD VF, 0x10
+    SHR VF
+    LD VA, VF
+    LD VB, 0
+    CALL check
+    LD VF, 0x80
+    SHL VF
+    LD VA, VF
+    LD VB, 1
+    CALL check
+
+    EXIT
+
+INCLUDE "check.asm"
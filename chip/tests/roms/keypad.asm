@@ -0,0 +1,35 @@
+; LD K, SKP and SKNP with the 5 key held for a few frames, the test presses
+; it after the rom starts waiting
+
+    LD V1, K
+    LD VA, V1
+    LD VB, 0x5
+    CALL check
+
+    ; Still held after LD K
+    LD VA, 0
+    SKNP V1
+    LD VA, 1
+    LD VB, 1
+    CALL check
+    LD VA, 1
+    SKP V1
+    LD VA, 0
+    CALL check
+
+    ; Released later
+release:
+    SKNP V1
+    JP release
+    LD VA, 1
+    SKNP V1
+    LD VA, 0
+    CALL check
+    LD VA, 0
+    SKP V1
+    LD VA, 1
+    CALL check
+
+    EXIT
+
+INCLUDE "check.asm"
@@ -0,0 +1,188 @@
+; The CHIP-8 instructions besides the arithmetic ones, with the default
+; quirks. The marks are drawn with DRW, CALL and RET as they go
+
+    ; CLS clears what was drawn before
+    LD I, block
+    DRW V0, V0, 4
+    CLS
+    SYS 0x123
+
+    ; JP skips the next instruction
+    LD VA, 1
+    JP jumped
+    LD VA, 0
+jumped:
+    LD VB, 1
+    CALL check
+
+    ; SE and SNE with bytes and registers, a mark for each taken and not
+    ; taken skip
+    LD V1, 0x42
+    LD V2, 0x42
+    LD VA, 1
+    SE V1, 0x42
+    LD VA, 0
+    CALL check
+    LD VA, 0
+    SE V1, 0x43
+    LD VA, 1
+    CALL check
+    LD VA, 1
+    SNE V1, 0x43
+    LD VA, 0
+    CALL check
+    LD VA, 0
+    SNE V1, 0x42
+    LD VA, 1
+    CALL check
+    LD VA, 1
+    SE V1, V2
+    LD VA, 0
+    CALL check
+    LD VA, 0
+    SNE V1, V2
+    LD VA, 1
+    CALL check
+    LD V2, 0x00
+    LD VA, 1
+    SNE V1, V2
+    LD VA, 0
+    CALL check
+
+    ; ADD to a register wraps without touching VF
+    LD VF, 0x07
+    LD V1, 0xFE
+    ADD V1, 3
+    LD V3, VF
+    LD VA, V1
+    LD VB, 0x01
+    CALL check
+    LD VA, V3
+    LD VB, 0x07
+    CALL check
+
+    ; JP V0 jumps relative to V0
+    LD V0, 4
+    LD VA, 1
+    JP V0, table
+table:
+    LD VA, 0
+    LD VA, 0
+    LD VB, 1
+    CALL check
+
+    ; LD B, LD [I] and LD V, [I] through memory, I stays in place
+    LD V1, 123
+    LD I, scratch
+    LD B, V1
+    LD V2, [I]
+    LD VA, V0
+    LD VB, 1
+    CALL check
+    LD I, scratch
+    LD V2, [I]
+    LD VA, V1
+    LD VB, 2
+    CALL check
+    LD I, scratch
+    LD V2, [I]
+    LD VA, V2
+    LD VB, 3
+    CALL check
+    LD V0, 0x11
+    LD V1, 0x22
+    LD I, scratch
+    LD [I], V1
+    LD V0, 0
+    LD V1, 0
+    LD V0, [I]
+    LD VA, V0
+    LD VB, 0x11
+    CALL check
+
+    ; ADD I moves I along the memory
+    LD I, scratch
+    LD V1, 1
+    ADD I, V1
+    LD V0, [I]
+    LD VA, V0
+    LD VB, 0x22
+    CALL check
+
+    ; LD F points I to the font
+    LD V1, 0xA
+    LD F, V1
+    LD V4, [I]
+    LD VA, V1
+    LD VB, 0x90
+    CALL check
+    LD VA, V2
+    LD VB, 0xF0
+    CALL check
+
+    ; RND with a zero mask is always zero
+    LD V1, 0xFF
+    RND V1, 0
+    LD VA, V1
+    LD VB, 0
+    CALL check
+
+    ; DRW sets VF when it erases a pixel
+    LD V1, 50
+    LD V2, 20
+    LD I, block
+    DRW V1, V2, 4
+    LD VA, VF
+    LD VB, 0
+    CALL check
+    LD V1, 50
+    LD I, block
+    DRW V1, V2, 4
+    LD VA, VF
+    LD VB, 1
+    CALL check
+    LD V1, 52
+    LD I, block
+    DRW V1, V2, 4
+    LD VA, VF
+    LD VB, 0
+    CALL check
+
+    ; SKP and SKNP without keys pressed
+    LD V1, 0x5
+    LD VA, 0
+    SKP V1
+    LD VA, 1
+    LD VB, 1
+    CALL check
+    LD VA, 1
+    SKNP V1
+    LD VA, 0
+    CALL check
+
+    ; The delay timer counts down to zero at 60 Hz
+    LD V1, 3
+    LD DT, V1
+    LD ST, V1
+    LD V2, DT
+    LD VA, 1
+    SNE V2, 0
+    LD VA, 0
+    LD VB, 1
+    CALL check
+wait:
+    LD V2, DT
+    SE V2, 0
+    JP wait
+    LD VA, V2
+    LD VB, 0
+    CALL check
+
+    EXIT
+
+block:
+    DB 0xF0, 0xF0, 0xF0, 0xF0
+scratch:
+    DB 0, 0, 0, 0
+
+INCLUDE "check.asm"
@@ -0,0 +1,56 @@
+; The SUPER-CHIP instructions with its quirks: resolutions, large sprites,
+; big digits, scrolling and the RPL flags
+
+    HIGH
+    LOW
+    HIGH
+
+    ; A 16x16 sprite moved 4 pixels down, 4 right and 2 up
+    LD V1, 56
+    LD V2, 24
+    LD I, big
+    DRW V1, V2, 0
+    SCD 4
+    SCR
+    SCR
+    SCL
+    SCU 2
+
+    ; A big 9 next to it
+    LD V3, 9
+    LD HF, V3
+    LD V1, 90
+    LD V2, 30
+    DRW V1, V2, 10
+
+    ; The RPL flags keep the registers
+    LD V0, 0x12
+    LD V1, 0x34
+    LD R, V1
+    LD V0, 0
+    LD V1, 0
+    LD V1, R
+    LD VA, V0
+    LD VB, 0x12
+    CALL check
+    LD VA, V1
+    LD VB, 0x34
+    CALL check
+
+    ; JP V0 jumps relative to the register of the high nibble of the address
+    LD V0, 0
+    LD V2, 2
+    LD VA, 1
+    JP V0, landing - 2
+    LD VA, 0
+landing:
+    LD VB, 1
+    CALL check
+
+    EXIT
+
+big:
+    DW 0xFFFF, 0x8001, 0x8001, 0x8FF1, 0x8811, 0x8811, 0x8811, 0x8811
+    DW 0x8811, 0x8811, 0x8811, 0x8811, 0x8FF1, 0x8001, 0x8001, 0xFFFF
+
+INCLUDE "check.asm"
@@ -0,0 +1,102 @@
+; The XO-CHIP instructions with its quirks: ranges of registers, long
+; addresses, bit planes, audio and wrapping sprites
+
+    ; LD I, LONG reaches past the first 4 KB
+    LD I, LONG far
+    LD V0 - V3, [I]
+    LD VA, V3
+    LD VB, 0x44
+    CALL check
+    LD I, LONG far
+    LD V3 - V0, [I]
+    LD VA, V0
+    LD VB, 0x44
+    CALL check
+
+    ; Ranges leave I in place
+    LD V0, 0xAA
+    LD V1, 0xBB
+    LD I, scratch
+    LD [I], V0 - V1
+    LD V2 - V3, [I]
+    LD VA, V3
+    LD VB, 0xBB
+    CALL check
+
+    ; LD [I] and LD V, [I] move I past the registers
+    LD I, scratch
+    LD V0, [I]
+    LD V0, [I]
+    LD VA, V0
+    LD VB, 0xBB
+    CALL check
+
+    ; Shifts read Vy
+    LD V1, 0
+    LD V2, 0x04
+    SHR V1, V2
+    LD VA, V1
+    LD VB, 0x02
+    CALL check
+
+    ; A block in each plane and one in both, which collides with the first
+    LD V5, 20
+    LD V6, 20
+    LD I, block
+    PLANE 1
+    DRW V5, V6, 4
+    PLANE 2
+    LD V5, 30
+    DRW V5, V6, 4
+    LD VA, VF
+    LD VB, 0
+    PLANE 1
+    CALL check
+    PLANE 3
+    LD V5, 20
+    LD V6, 26
+    LD I, planes
+    DRW V5, V6, 4
+    LD VA, VF
+    LD VB, 0
+    PLANE 1
+    CALL check
+    PLANE 3
+    LD V6, 20
+    LD I, planes
+    DRW V5, V6, 4
+    LD VA, VF
+    LD VB, 1
+    PLANE 1
+    CALL check
+
+    ; Sprites wrap around the edges
+    LD V5, 60
+    LD V6, 30
+    LD I, block
+    DRW V5, V6, 4
+
+    ; Audio doesn't touch the screen
+    LD I, pattern
+    AUDIO
+    LD V1, 112
+    PITCH V1
+    LD ST, V1
+
+    EXIT
+
+block:
+    DB 0xF0, 0xF0, 0xF0, 0xF0
+planes:
+    DB 0xF0, 0x90, 0x90, 0xF0, 0x60, 0x60, 0x60, 0x60
+pattern:
+    DB 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF
+    DB 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF
+scratch:
+    DB 0, 0, 0, 0
+
+INCLUDE "check.asm"
+
+    ORG 0x2000
+far:
+    DB 0x11, 0x22, 0x33, 0x44
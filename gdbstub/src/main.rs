@@ -0,0 +1,183 @@
+mod packet;
+mod target;
+
+use std::error::Error;
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::str::FromStr;
+
+use chip::{Chip, Quirks, DEFAULT_ROM_START};
+use clap::Parser;
+
+use crate::packet::{Connection, Incoming};
+use crate::target::{Action, Target};
+
+#[derive(Parser)]
+#[command(name = "chippy-gdbstub")]
+#[command(author = "Mauro Sambartolomeo")]
+#[command(version = "1.0")]
+#[command(about = "Debug chip-8 roms with gdb or any client of its remote protocol")]
+struct Args {
+    /// Rom to debug
+    path: String,
+
+    /// Local port to listen on
+    #[arg(long, default_value_t = 1234)]
+    port: u16,
+
+    /// Instructions executed per frame while continuing, at 60 frames per second
+    #[arg(long, default_value_t = 10)]
+    cycles_per_frame: u32,
+
+    /// Seed for the random number generator, random if not given
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Behaviour of the ambiguous instructions: default, cosmac-vip, schip,
+    /// xo-chip or single quirks separated by commas
+    #[arg(long, value_parser = Quirks::from_str, default_value = "default")]
+    quirks: Quirks,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if let Err(error) = run(&args) {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let mut chip = args.seed.map_or_else(
+        || Chip::new(DEFAULT_ROM_START, args.quirks),
+        |seed| Chip::with_seed(DEFAULT_ROM_START, args.quirks, seed),
+    );
+    chip.load_rom(args.path.clone())?;
+
+    // NOTE: Only local clients, the protocol has no authentication
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, args.port))?;
+    println!("Listening on {}", listener.local_addr()?);
+
+    let (stream, client) = listener.accept()?;
+    println!("Client connected from {client}");
+
+    serve(stream, Target::new(chip, args.cycles_per_frame))?;
+    println!("Client disconnected");
+
+    Ok(())
+}
+
+// NOTE: Serves a single client until it detaches, kills the target or
+// closes the connection
+fn serve(stream: TcpStream, mut target: Target) -> std::io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut connection = Connection::new(stream);
+
+    loop {
+        let packet = match connection.receive()? {
+            Incoming::Packet(packet) => packet,
+            // NOTE: The target is already stopped between packets
+            Incoming::Interrupt => continue,
+            Incoming::Closed => return Ok(()),
+        };
+
+        match target.handle(&String::from_utf8_lossy(&packet)) {
+            Action::Reply(reply) => connection.send(&reply)?,
+            Action::Resume { step } => {
+                let reply = target.resume(step, || connection.interrupted())?;
+                connection.send(&reply)?;
+            }
+            Action::StopAcknowledging => {
+                connection.send("OK")?;
+                connection.stop_acknowledging();
+            }
+            Action::Detach => {
+                connection.send("OK")?;
+                return Ok(());
+            }
+            Action::Kill => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    use crate::packet::encode;
+
+    // LD V0, 0x01 - ADD V0, 0x01 - JP 0x202
+    const PROGRAM: [u8; 6] = [0x60, 0x01, 0x70, 0x01, 0x12, 0x02];
+
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn send(&mut self, packet: &str) {
+            self.stream
+                .write_all(&encode(packet.as_bytes()))
+                .expect("Connected");
+        }
+
+        fn read(&mut self, count: usize) -> String {
+            let mut bytes = vec![0; count];
+            self.stream.read_exact(&mut bytes).expect("Connected");
+            String::from_utf8(bytes).expect("Ascii reply")
+        }
+
+        // NOTE: Reads the acknowledgement and the reply, which must have the
+        // expected length
+        fn request(&mut self, packet: &str, reply: &str) {
+            self.send(packet);
+            assert_eq!(self.read(1), "+");
+            let expected = String::from_utf8(encode(reply.as_bytes())).expect("Ascii reply");
+            assert_eq!(self.read(expected.len()), expected);
+            self.stream.write_all(b"+").expect("Connected");
+        }
+    }
+
+    #[test]
+    fn test_session() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).expect("Free port");
+        let address = listener.local_addr().expect("Bound listener");
+
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().expect("Client connects");
+            let mut chip = Chip::new(DEFAULT_ROM_START, Quirks::default());
+            chip.load_rom_bytes(&PROGRAM).expect("Valid program");
+            serve(stream, Target::new(chip, 10))
+        });
+
+        let mut client = Client {
+            stream: TcpStream::connect(address).expect("Server listening"),
+        };
+
+        client.request("?", "S05");
+        client.request("s", "S05");
+        client.request("p0", "01");
+        client.request("Z0,204,2", "OK");
+        client.request("c", "T05swbreak:;");
+        client.request("p0", "02");
+        client.request("z0,204,2", "OK");
+
+        // NOTE: Interrupts a rom that never stops by itself
+        client.send("c");
+        assert_eq!(client.read(1), "+");
+        client.stream.write_all(&[0x03]).expect("Connected");
+        let expected = String::from_utf8(encode(b"S02")).expect("Ascii reply");
+        assert_eq!(client.read(expected.len()), expected);
+
+        client.request("QStartNoAckMode", "OK");
+        client.send("m200,2");
+        assert_eq!(client.read(8), "$6001#c7");
+        client.send("D");
+        assert_eq!(client.read(6), "$OK#9a");
+
+        server
+            .join()
+            .expect("Server does not panic")
+            .expect("No io errors");
+    }
+}
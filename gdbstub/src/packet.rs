@@ -0,0 +1,166 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+const INTERRUPT: u8 = 0x03;
+const ESCAPE: u8 = b'}';
+
+pub enum Incoming {
+    Packet(Vec<u8>),
+    Interrupt,
+    Closed,
+}
+
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+// NOTE: $data#checksum, with the bytes that delimit packets escaped
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for byte in data {
+        if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+            escaped.extend([ESCAPE, byte ^ 0x20]);
+        } else {
+            escaped.push(*byte);
+        }
+    }
+
+    let mut packet = vec![b'$'];
+    packet.extend(&escaped);
+    packet.extend(format!("#{:02x}", checksum(&escaped)).bytes());
+
+    packet
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+
+    while let Some(byte) = bytes.next() {
+        match (byte, bytes.as_slice().first()) {
+            (&ESCAPE, Some(next)) => {
+                unescaped.push(next ^ 0x20);
+                bytes.next();
+            }
+            _ => unescaped.push(*byte),
+        }
+    }
+
+    unescaped
+}
+
+// Connection with a client, acknowledging packets until it asks to stop
+pub struct Connection {
+    reader: BufReader<TcpStream>,
+    acknowledge: bool,
+    last_sent: Vec<u8>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            reader: BufReader::new(stream),
+            acknowledge: true,
+            last_sent: Vec::new(),
+        }
+    }
+
+    pub const fn stop_acknowledging(&mut self) {
+        self.acknowledge = false;
+    }
+
+    pub fn send(&mut self, data: &str) -> std::io::Result<()> {
+        self.last_sent = encode(data.as_bytes());
+
+        self.reader.get_mut().write_all(&self.last_sent)
+    }
+
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    pub fn receive(&mut self) -> std::io::Result<Incoming> {
+        loop {
+            let Some(byte) = self.read_byte()? else {
+                return Ok(Incoming::Closed);
+            };
+
+            match byte {
+                INTERRUPT => return Ok(Incoming::Interrupt),
+                b'-' => {
+                    let packet = self.last_sent.clone();
+                    self.reader.get_mut().write_all(&packet)?;
+                }
+                b'$' => {
+                    if let Some(packet) = self.read_packet()? {
+                        return Ok(Incoming::Packet(packet));
+                    }
+                }
+                // NOTE: Acknowledgements and noise between packets
+                _ => (),
+            }
+        }
+    }
+
+    // NOTE: None if the checksum is wrong, the client is asked to resend it
+    fn read_packet(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let mut data = Vec::new();
+        self.reader.read_until(b'#', &mut data)?;
+        if data.pop() != Some(b'#') {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+
+        let mut sum = [0; 2];
+        self.reader.read_exact(&mut sum)?;
+        let valid = std::str::from_utf8(&sum)
+            .ok()
+            .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+            == Some(checksum(&data));
+
+        if self.acknowledge {
+            let reply = if valid { b"+" } else { b"-" };
+            self.reader.get_mut().write_all(reply)?;
+        }
+
+        Ok(valid.then(|| unescape(&data)))
+    }
+
+    // NOTE: Checks without blocking if the client asked to stop a running
+    // target, anything else it sent is dropped
+    pub fn interrupted(&mut self) -> std::io::Result<bool> {
+        self.reader.get_ref().set_nonblocking(true)?;
+        let result = self.reader.fill_buf().map(<[u8]>::to_vec);
+        self.reader.get_ref().set_nonblocking(false)?;
+
+        match result {
+            Ok(bytes) => {
+                self.reader.consume(bytes.len());
+                Ok(bytes.contains(&INTERRUPT))
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(b"OK"), b"$OK#9a");
+        assert_eq!(encode(b""), b"$#00");
+        assert_eq!(encode(b"a#b"), b"$a}\x03b#43");
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape(b"a}\x03b"), b"a#b");
+        assert_eq!(unescape(b"abc}"), b"abc}");
+    }
+}
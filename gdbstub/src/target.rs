@@ -0,0 +1,474 @@
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use chip::{Access, BreakReason, Breakpoint, Chip, Registers, Watchpoint};
+
+const FRAME: Duration = Duration::from_micros(1_000_000 / 60);
+
+// NOTE: Registers are numbered V0-VF, I, PC and SP, the ones of 16 bits are
+// sent big endian like the rest of the chip
+const REGISTERS_COUNT: usize = 19;
+const I: usize = 16;
+const PC: usize = 17;
+const SP: usize = 18;
+
+const TARGET_XML: &str = include_str!("target.xml");
+
+// Signals in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+pub enum Action {
+    Reply(String),
+    Resume { step: bool },
+    StopAcknowledging,
+    Detach,
+    Kill,
+}
+
+// Chip controlled by the packets of a client
+pub struct Target {
+    chip: Chip,
+    cycles_per_frame: u32,
+}
+
+impl Target {
+    pub const fn new(chip: Chip, cycles_per_frame: u32) -> Self {
+        Self {
+            chip,
+            cycles_per_frame,
+        }
+    }
+
+    // NOTE: Packets that are not supported get an empty reply
+    pub fn handle(&mut self, packet: &str) -> Action {
+        let reply = match packet.split_at(packet.len().min(1)) {
+            ("?", _) => format!("S{SIGTRAP:02x}"),
+            ("g", _) => self.read_registers(),
+            ("G", data) => self.write_registers(data),
+            ("p", number) => self.read_register(number),
+            ("P", assignment) => self.write_register(assignment),
+            ("m", arguments) => self.read_memory(arguments),
+            ("M", arguments) => self.write_memory(arguments),
+            ("Z", arguments) => self.set_breakpoint(arguments, true),
+            ("z", arguments) => self.set_breakpoint(arguments, false),
+            ("s", _) => return Action::Resume { step: true },
+            ("c", _) => return Action::Resume { step: false },
+            ("D", _) => return Action::Detach,
+            ("k", _) => return Action::Kill,
+            ("H", _) => String::from("OK"),
+            _ => return Self::query(packet),
+        };
+
+        Action::Reply(reply)
+    }
+
+    fn query(packet: &str) -> Action {
+        let reply = match packet {
+            "QStartNoAckMode" => return Action::StopAcknowledging,
+            "qAttached" => String::from("1"),
+            "qC" => String::from("QC1"),
+            "qfThreadInfo" => String::from("m1"),
+            "qsThreadInfo" => String::from("l"),
+            _ if packet.starts_with("qSupported") => {
+                String::from("PacketSize=1000;QStartNoAckMode+;qXfer:features:read+;swbreak+")
+            }
+            _ => packet
+                .strip_prefix("qXfer:features:read:target.xml:")
+                .map(target_xml)
+                .unwrap_or_default(),
+        };
+
+        Action::Reply(reply)
+    }
+
+    fn read_registers(&self) -> String {
+        let registers = self.chip.registers();
+
+        (0..REGISTERS_COUNT)
+            .map(|number| hex(&register_bytes(&registers, number)))
+            .collect()
+    }
+
+    fn write_registers(&mut self, data: &str) -> String {
+        let Some(bytes) = unhex(data) else {
+            return error(1);
+        };
+
+        let mut registers = self.chip.registers();
+        let mut rest = bytes.as_slice();
+        for number in 0..REGISTERS_COUNT {
+            let size = register_bytes(&registers, number).len();
+            let Some((value, next)) = rest.split_at_checked(size) else {
+                return error(1);
+            };
+            set_register(&mut registers, number, value);
+            rest = next;
+        }
+
+        self.set_registers(&registers)
+    }
+
+    fn read_register(&self, number: &str) -> String {
+        let registers = self.chip.registers();
+
+        match usize::from_str_radix(number, 16) {
+            Ok(number) if number < REGISTERS_COUNT => hex(&register_bytes(&registers, number)),
+            _ => error(1),
+        }
+    }
+
+    fn write_register(&mut self, assignment: &str) -> String {
+        let mut registers = self.chip.registers();
+
+        let parsed = assignment.split_once('=').and_then(|(number, value)| {
+            let number = usize::from_str_radix(number, 16).ok()?;
+            let value = unhex(value)?;
+            let size = register_bytes(&registers, number).len();
+
+            (number < REGISTERS_COUNT && value.len() == size).then_some((number, value))
+        });
+
+        let Some((number, value)) = parsed else {
+            return error(1);
+        };
+        set_register(&mut registers, number, &value);
+
+        self.set_registers(&registers)
+    }
+
+    fn set_registers(&mut self, registers: &Registers) -> String {
+        match self.chip.set_registers(registers) {
+            Ok(()) => String::from("OK"),
+            Err(_) => error(2),
+        }
+    }
+
+    fn read_memory(&self, arguments: &str) -> String {
+        let Some((address, length)) = address_length(arguments) else {
+            return error(1);
+        };
+
+        self.chip
+            .memory()
+            .get(address..address.saturating_add(length))
+            .map_or_else(|| error(3), hex)
+    }
+
+    fn write_memory(&mut self, arguments: &str) -> String {
+        let parsed = arguments.split_once(':').and_then(|(range, data)| {
+            let (address, length) = address_length(range)?;
+            let bytes = unhex(data)?;
+
+            (bytes.len() == length).then_some((u16::try_from(address).ok()?, bytes))
+        });
+
+        let Some((address, bytes)) = parsed else {
+            return error(1);
+        };
+
+        match self.chip.write_memory(address, &bytes) {
+            Ok(()) => String::from("OK"),
+            Err(_) => error(3),
+        }
+    }
+
+    // NOTE: Software and hardware breakpoints are the same, watchpoints are
+    // types 2 to 4 for writes, reads and both
+    fn set_breakpoint(&mut self, arguments: &str, add: bool) -> String {
+        let mut parts = arguments.split(',');
+        let (Some(kind), Some(address), Some(length)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return error(1);
+        };
+        let (Ok(address), Ok(length)) = (
+            u16::from_str_radix(address, 16),
+            u16::from_str_radix(length, 16),
+        ) else {
+            return error(1);
+        };
+
+        let watchpoint = |read, write| Watchpoint {
+            range: address..=address.saturating_add(length.max(1) - 1),
+            read,
+            write,
+        };
+
+        match (kind, add) {
+            ("0" | "1", true) => self.chip.add_breakpoint(Breakpoint::Address(address)),
+            ("0" | "1", false) => {
+                self.chip.remove_breakpoint(&Breakpoint::Address(address));
+            }
+            ("2", true) => self.chip.add_watchpoint(watchpoint(false, true)),
+            ("3", true) => self.chip.add_watchpoint(watchpoint(true, false)),
+            ("4", true) => self.chip.add_watchpoint(watchpoint(true, true)),
+            ("2", false) => {
+                self.chip.remove_watchpoint(&watchpoint(false, true));
+            }
+            ("3", false) => {
+                self.chip.remove_watchpoint(&watchpoint(true, false));
+            }
+            ("4", false) => {
+                self.chip.remove_watchpoint(&watchpoint(true, true));
+            }
+            _ => return String::new(),
+        }
+
+        String::from("OK")
+    }
+
+    // NOTE: Runs at the speed of the frontend so the timers behave the same,
+    // checking between frames if the client wants to stop
+    pub fn resume(
+        &mut self,
+        step: bool,
+        mut interrupted: impl FnMut() -> std::io::Result<bool>,
+    ) -> std::io::Result<String> {
+        if step {
+            return Ok(self
+                .run_cycle()
+                .unwrap_or_else(|| format!("S{SIGTRAP:02x}")));
+        }
+
+        loop {
+            let frame_start = Instant::now();
+
+            for _ in 0..self.cycles_per_frame {
+                if let Some(reply) = self.run_cycle() {
+                    return Ok(reply);
+                }
+            }
+            self.chip.tick_timers();
+
+            if interrupted()? {
+                return Ok(format!("S{SIGINT:02x}"));
+            }
+
+            std::thread::sleep(FRAME.saturating_sub(frame_start.elapsed()));
+        }
+    }
+
+    // NOTE: The stop reply if the chip has to stop after the cycle
+    fn run_cycle(&mut self) -> Option<String> {
+        let actions = match self.chip.run_cycle() {
+            Ok(actions) => actions,
+            Err(error) => {
+                eprintln!("{error}");
+                return Some(format!("S{SIGILL:02x}"));
+            }
+        };
+
+        if actions.exit {
+            return Some(String::from("W00"));
+        }
+
+        actions.break_reason.map(|reason| match reason {
+            BreakReason::Breakpoint(Breakpoint::Address(_)) => format!("T{SIGTRAP:02x}swbreak:;"),
+            BreakReason::Breakpoint(_) => format!("S{SIGTRAP:02x}"),
+            BreakReason::Watchpoint {
+                watchpoint,
+                access,
+                address,
+            } => {
+                let kind = match (watchpoint.read && watchpoint.write, access) {
+                    (true, _) => "awatch",
+                    (false, Access::Read) => "rwatch",
+                    (false, Access::Write) => "watch",
+                };
+                format!("T{SIGTRAP:02x}{kind}:{address:x};")
+            }
+        })
+    }
+}
+
+fn register_bytes(registers: &Registers, number: usize) -> Vec<u8> {
+    match number {
+        I => registers.i.to_be_bytes().to_vec(),
+        PC => registers.pc.to_be_bytes().to_vec(),
+        SP => vec![registers.sp],
+        _ => vec![registers.v[number]],
+    }
+}
+
+fn set_register(registers: &mut Registers, number: usize, value: &[u8]) {
+    let word = || u16::from_be_bytes([value[0], value[1]]);
+
+    match number {
+        I => registers.i = word(),
+        PC => registers.pc = word(),
+        SP => registers.sp = value[0],
+        _ => registers.v[number] = value[0],
+    }
+}
+
+// NOTE: The description is sent in parts of the requested size, the last
+// one starting with l instead of m
+fn target_xml(range: &str) -> String {
+    let Some((offset, length)) = address_length(range) else {
+        return error(1);
+    };
+
+    let xml = TARGET_XML.as_bytes();
+    let start = offset.min(xml.len());
+    let end = xml.len().min(start.saturating_add(length));
+    let marker = if end == xml.len() { 'l' } else { 'm' };
+
+    format!("{marker}{}", String::from_utf8_lossy(&xml[start..end]))
+}
+
+fn address_length(arguments: &str) -> Option<(usize, usize)> {
+    let (address, length) = arguments.split_once(',')?;
+
+    Some((
+        usize::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+fn error(code: u8) -> String {
+    format!("E{code:02x}")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut text, byte| {
+        let _ = write!(text, "{byte:02x}");
+        text
+    })
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip::{Quirks, DEFAULT_ROM_START};
+
+    // LD V0, 0x12 - LD I, 0x2FF - CALL 0x208 - JP 0x206 - LD B, V0 - RET
+    const PROGRAM: [u8; 12] = [
+        0x60, 0x12, 0xA2, 0xFF, 0x22, 0x08, 0x12, 0x06, 0xF0, 0x33, 0x00, 0xEE,
+    ];
+
+    fn new_target() -> Target {
+        let mut chip = Chip::new(DEFAULT_ROM_START, Quirks::default());
+        chip.load_rom_bytes(&PROGRAM).expect("Valid program");
+
+        Target::new(chip, 10)
+    }
+
+    fn reply(target: &mut Target, packet: &str) -> String {
+        match target.handle(packet) {
+            Action::Reply(reply) => reply,
+            _ => panic!("{packet} does not have a reply"),
+        }
+    }
+
+    fn step(target: &mut Target) -> String {
+        target.resume(true, || Ok(false)).expect("No io")
+    }
+
+    fn resume(target: &mut Target) -> String {
+        target.resume(false, || Ok(false)).expect("No io")
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut target = new_target();
+        step(&mut target);
+        step(&mut target);
+
+        let registers = reply(&mut target, "g");
+        assert_eq!(registers.len(), 2 * (16 + 2 + 2 + 1));
+        assert!(registers.starts_with("1200"));
+        assert!(registers.ends_with("02ff020400"));
+
+        assert_eq!(reply(&mut target, "p10"), "02ff");
+        assert_eq!(reply(&mut target, "P11=0300"), "OK");
+        assert_eq!(reply(&mut target, "p11"), "0300");
+        assert_eq!(reply(&mut target, "P3=ab"), "OK");
+        assert_eq!(reply(&mut target, "p3"), "ab");
+
+        let mut changed = reply(&mut target, "g");
+        changed.replace_range(0..2, "34");
+        assert_eq!(reply(&mut target, &format!("G{changed}")), "OK");
+        assert_eq!(reply(&mut target, "p0"), "34");
+
+        assert_eq!(reply(&mut target, "p13"), "E01");
+        assert_eq!(reply(&mut target, "P12=11"), "E02");
+        assert_eq!(reply(&mut target, "P11=03"), "E01");
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut target = new_target();
+
+        assert_eq!(reply(&mut target, "m200,4"), "6012a2ff");
+        assert_eq!(reply(&mut target, "M300,2:abcd"), "OK");
+        assert_eq!(reply(&mut target, "m300,2"), "abcd");
+        assert_eq!(reply(&mut target, "mffff,2"), "E03");
+        assert_eq!(reply(&mut target, "mffffffffffffffff,1"), "E03");
+        assert_eq!(reply(&mut target, "M300,2:ab"), "E01");
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut target = new_target();
+
+        assert_eq!(reply(&mut target, "Z0,208,2"), "OK");
+        assert_eq!(resume(&mut target), "T05swbreak:;");
+        assert_eq!(reply(&mut target, "p11"), "0208");
+
+        assert_eq!(reply(&mut target, "z0,208,2"), "OK");
+        assert_eq!(reply(&mut target, "Z2,300,3"), "OK");
+        assert_eq!(resume(&mut target), "T05watch:300;");
+
+        assert_eq!(reply(&mut target, "m2ff,4"), "00010800");
+    }
+
+    #[test]
+    fn test_exit_and_errors() {
+        let mut target = new_target();
+        assert_eq!(reply(&mut target, "M20c,2:00fd"), "OK");
+        assert_eq!(reply(&mut target, "P11=020c"), "OK");
+        assert_eq!(resume(&mut target), "W00");
+
+        let mut target = new_target();
+        assert_eq!(reply(&mut target, "M200,2:ffff"), "OK");
+        assert_eq!(step(&mut target), "S04");
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut target = new_target();
+
+        assert_eq!(target.resume(false, || Ok(true)).expect("No io"), "S02");
+    }
+
+    #[test]
+    fn test_target_xml() {
+        let mut target = new_target();
+
+        let first = reply(&mut target, "qXfer:features:read:target.xml:0,10");
+        assert!(first.starts_with("m<?xml"));
+
+        let all = reply(&mut target, "qXfer:features:read:target.xml:0,fff");
+        assert!(all.starts_with('l'));
+        assert!(all.contains("name=\"pc\""));
+
+        let rest = reply(
+            &mut target,
+            "qXfer:features:read:target.xml:10,ffffffffffffffff",
+        );
+        assert!(rest.starts_with('l'));
+        assert_eq!(rest[1..], all[0x11..]);
+    }
+}
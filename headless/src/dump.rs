@@ -0,0 +1,80 @@
+use chip::{Chip, Screen};
+
+// NOTE: Characters for each combination of planes: none, first, second and
+// both
+const PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+pub fn ascii(screen: &Screen) -> String {
+    screen
+        .rows()
+        .map(|row| {
+            let mut line: String = row.iter().map(|pixel| PIXELS[*pixel as usize]).collect();
+            line.push('\n');
+            line
+        })
+        .collect()
+}
+
+pub fn registers(chip: &Chip) -> String {
+    let registers = chip.registers();
+
+    let mut lines: Vec<String> = registers
+        .v
+        .chunks(4)
+        .enumerate()
+        .map(|(index, values)| {
+            let line: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(column, value)| format!("V{:X} {value:02X}", index * 4 + column))
+                .collect();
+            line.join("  ")
+        })
+        .collect();
+
+    lines.push(format!(
+        "I  {:04X}  PC {:04X}  SP {:02X}  DT {:02X}  ST {:02X}",
+        registers.i, registers.pc, registers.sp, registers.delay_timer, registers.sound_timer
+    ));
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip::{Quirks, DEFAULT_ROM_START};
+
+    // LD V0, 0x0A - LD F, V0 - DRW V1, V1, 5
+    const PROGRAM: [u8; 6] = [0x60, 0x0A, 0xF0, 0x29, 0xD1, 0x15];
+
+    fn chip() -> Chip {
+        let mut chip = Chip::new(DEFAULT_ROM_START, Quirks::default());
+        chip.load_rom_bytes(&PROGRAM).expect("Valid program");
+        for _ in 0..PROGRAM.len() / 2 {
+            chip.run_cycle().expect("Valid program");
+        }
+        chip
+    }
+
+    #[test]
+    fn test_ascii() {
+        let screen = ascii(chip().screen());
+        let lines: Vec<&str> = screen.lines().collect();
+
+        assert_eq!(lines.len(), 32);
+        assert!(lines.iter().all(|line| line.len() == 64));
+        assert_eq!(&lines[0][..4], "####");
+        assert_eq!(&lines[1][..4], "#..#");
+        assert_eq!(&lines[3][..4], "#..#");
+        assert_eq!(lines[5], ".".repeat(64));
+    }
+
+    #[test]
+    fn test_registers() {
+        let text = registers(&chip());
+
+        assert!(text.starts_with("V0 0A  V1 00  V2 00  V3 00\n"));
+        assert!(text.contains("I  0032  PC 0206  SP 00"));
+    }
+}
@@ -0,0 +1,281 @@
+mod dump;
+mod script;
+
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::str::FromStr;
+
+use chip::{Chip, ChipError, Movie, Quirks, Screen, DEFAULT_PALETTE, DEFAULT_ROM_START};
+use clap::Parser;
+
+use crate::script::{KeyPress, Script};
+
+#[derive(Parser)]
+#[command(name = "chippy-headless")]
+#[command(author = "Mauro Sambartolomeo")]
+#[command(version = "1.0")]
+#[command(about = "Run chip-8 roms without a window and print the final state")]
+struct Args {
+    /// Rom to run
+    path: String,
+
+    /// Frames to run, at the given instructions per frame
+    #[arg(long, default_value_t = 60, conflicts_with = "cycles")]
+    frames: u64,
+
+    /// Instructions to run instead of whole frames
+    #[arg(long)]
+    cycles: Option<u64>,
+
+    /// Instructions executed per frame, the timers tick once per frame
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    cycles_per_frame: u32,
+
+    /// Seed for the random number generator, random if not given
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Behaviour of the ambiguous instructions: default, cosmac-vip, schip,
+    /// xo-chip or single quirks separated by commas
+    #[arg(long, value_parser = Quirks::from_str, default_value = "default")]
+    quirks: Quirks,
+
+    /// Hold a key from a frame, for 1 frame if not given (30:A or 30:A:5).
+    /// Can be repeated
+    #[arg(long = "key", value_name = "FRAME:KEY[:FRAMES]")]
+    keys: Vec<KeyPress>,
+
+    /// File with key presses like --key, separated by spaces or lines
+    #[arg(long)]
+    script: Option<String>,
+
+    /// Write the final screen to a file, as PNG or PBM if it ends in .png or
+    /// .pbm and as text otherwise. Printed as text if not given
+    #[arg(long)]
+    screen: Option<String>,
+
+    /// Size of each pixel in the PNG screen
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=32))]
+    scale: u32,
+
+    /// Play a movie recorded with the frontend, with its seed, quirks, keys
+    /// and length
+    #[arg(
+        long,
+        conflicts_with_all = ["frames", "cycles", "cycles_per_frame", "seed", "quirks", "keys", "script"]
+    )]
+    movie: Option<String>,
+}
+
+// How the run ended
+struct Run {
+    cycles: u64,
+    exited: bool,
+    error: Option<ChipError>,
+}
+
+// NOTE: The exit status is 1 if the rom failed or the state couldn't be
+// written, a rom that exits by itself is a success
+fn main() {
+    let args = Args::parse();
+
+    match run(&args) {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(1),
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<bool, Box<dyn Error>> {
+    let rom = std::fs::read(&args.path)?;
+    let (chip, result, cycles_per_frame) = match &args.movie {
+        Some(path) => play_movie(path, &rom)?,
+        None => run_script(args, &rom)?,
+    };
+
+    let frames = result.cycles / u64::from(cycles_per_frame);
+    let ending = match (&result.error, result.exited) {
+        (Some(error), _) => format!("failed: {error}"),
+        (None, true) => String::from("exited"),
+        (None, false) => String::from("finished"),
+    };
+    println!("Ran {} cycles, {frames} frames, {ending}", result.cycles);
+
+    match &args.screen {
+        Some(path) => write_screen(chip.screen(), path, args.scale)?,
+        None => print!("{}", dump::ascii(chip.screen())),
+    }
+
+    println!("{}", dump::registers(&chip));
+    println!("Memory {}", chip::sha256_hex(chip.memory()));
+
+    Ok(result.error.is_none())
+}
+
+fn run_script(args: &Args, rom: &[u8]) -> Result<(Chip, Run, u32), Box<dyn Error>> {
+    let mut script = match &args.script {
+        Some(path) => Script::parse(&std::fs::read_to_string(path)?)?,
+        None => Script::default(),
+    };
+    script.extend(args.keys.iter().copied());
+
+    let mut chip = args.seed.map_or_else(
+        || Chip::new(DEFAULT_ROM_START, args.quirks),
+        |seed| Chip::with_seed(DEFAULT_ROM_START, args.quirks, seed),
+    );
+    chip.load_rom_bytes(rom)?;
+
+    let cycles = args
+        .cycles
+        .unwrap_or_else(|| args.frames * u64::from(args.cycles_per_frame));
+    let result = execute(
+        &mut chip,
+        |chip, frame| script.update(chip, frame),
+        cycles,
+        args.cycles_per_frame,
+    );
+
+    Ok((chip, result, args.cycles_per_frame))
+}
+
+// NOTE: The movie only plays with the rom it was recorded with
+fn play_movie(path: &str, rom: &[u8]) -> Result<(Chip, Run, u32), Box<dyn Error>> {
+    let movie: Movie = std::fs::read_to_string(path)?.parse()?;
+
+    let mut chip = movie.chip(rom)?;
+    let cycles = movie.frames * u64::from(movie.cycles_per_frame);
+    let result = execute(
+        &mut chip,
+        |chip, frame| movie.apply(chip, frame),
+        cycles,
+        movie.cycles_per_frame,
+    );
+
+    Ok((chip, result, movie.cycles_per_frame))
+}
+
+fn write_screen(screen: &Screen, path: &str, scale: u32) -> std::io::Result<()> {
+    let extension = Path::new(path).extension().and_then(OsStr::to_str);
+    if !matches!(extension, Some("png" | "pbm")) {
+        return std::fs::write(path, dump::ascii(screen));
+    }
+
+    let writer = BufWriter::new(File::create(path)?);
+    if extension == Some("png") {
+        screen.write_png(writer, scale as usize, &DEFAULT_PALETTE)
+    } else {
+        screen.write_pbm(writer)
+    }
+}
+
+// NOTE: Keys change and the timers tick at frame boundaries, like in the
+// frontend
+fn execute(
+    chip: &mut Chip,
+    input: impl Fn(&mut Chip, u64),
+    cycles: u64,
+    cycles_per_frame: u32,
+) -> Run {
+    let cycles_per_frame = u64::from(cycles_per_frame);
+    let mut run = Run {
+        cycles: 0,
+        exited: false,
+        error: None,
+    };
+
+    while run.cycles < cycles {
+        if run.cycles.is_multiple_of(cycles_per_frame) {
+            input(chip, run.cycles / cycles_per_frame);
+        }
+
+        match chip.run_cycle() {
+            Ok(actions) => run.exited = actions.exit,
+            Err(error) => {
+                run.error = Some(error);
+                return run;
+            }
+        }
+        run.cycles += 1;
+
+        if run.exited {
+            return run;
+        }
+        if run.cycles.is_multiple_of(cycles_per_frame) {
+            chip.tick_timers();
+        }
+    }
+
+    run
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip::Key;
+
+    fn chip(program: &[u8]) -> Chip {
+        let mut chip = Chip::new(DEFAULT_ROM_START, Quirks::default());
+        chip.load_rom_bytes(program).expect("Valid program");
+        chip
+    }
+
+    #[test]
+    fn test_execute() {
+        // LD V0, 0x05 - LD DT, V0 - JP 0x204
+        let mut chip = chip(&[0x60, 0x05, 0xF0, 0x15, 0x12, 0x04]);
+
+        let run = execute(&mut chip, |_, _| (), 30, 10);
+
+        assert_eq!(run.cycles, 30);
+        assert!(!run.exited && run.error.is_none());
+        assert_eq!(chip.registers().delay_timer, 2);
+    }
+
+    #[test]
+    fn test_execute_keys() {
+        // LD V0, K - EXIT
+        let mut chip = chip(&[0xF0, 0x0A, 0x00, 0xFD]);
+        let script = Script::parse("3:7").expect("Valid script");
+
+        let run = execute(&mut chip, |chip, frame| script.update(chip, frame), 100, 10);
+
+        assert!(run.exited);
+        assert_eq!(run.cycles, 32);
+        assert_eq!(chip.registers().v[0], 0x7);
+    }
+
+    #[test]
+    fn test_execute_movie() {
+        // RND V0, 0xFF - LD V1, K - EXIT
+        let rom = [0xC0, 0xFF, 0xF1, 0x0A, 0x00, 0xFD];
+        let mut movie = Movie::new(&rom, 7, Quirks::default(), 10);
+        movie.record(4, Key::Key9, true);
+
+        let mut played = Vec::new();
+        for _ in 0..2 {
+            let mut chip = movie.chip(&rom).expect("Same rom");
+            let run = execute(&mut chip, |chip, frame| movie.apply(chip, frame), 100, 10);
+            assert!(run.exited);
+            played.push(chip.registers());
+        }
+
+        assert_eq!(played[0], played[1]);
+        assert_eq!(played[0].v[1], 0x9);
+    }
+
+    #[test]
+    fn test_execute_error() {
+        let mut chip = chip(&[0xFF, 0xFF]);
+
+        let run = execute(&mut chip, |_, _| (), 100, 10);
+
+        assert_eq!(run.cycles, 0);
+        assert!(matches!(run.error, Some(ChipError::UnknownOpcode { .. })));
+    }
+}
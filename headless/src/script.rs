@@ -0,0 +1,159 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chip::{Chip, Key};
+
+// A key held down for some frames, written as FRAME:KEY[:FRAMES]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyPress {
+    pub frame: u64,
+    pub key: Key,
+    pub frames: u64,
+}
+
+impl FromStr for KeyPress {
+    type Err = ParseKeyPressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseKeyPressError { text: s.to_owned() };
+
+        let mut parts = s.trim().split(':');
+        let (Some(frame), Some(key)) = (parts.next(), parts.next()) else {
+            return Err(error());
+        };
+        let frames = parts
+            .next()
+            .map_or(Ok(1), str::parse)
+            .map_err(|_| error())?;
+        if parts.next().is_some() || frames == 0 {
+            return Err(error());
+        }
+
+        let key = u8::from_str_radix(key, 16)
+            .ok()
+            .and_then(Key::from_digit)
+            .ok_or_else(error)?;
+
+        Ok(Self {
+            frame: frame.parse().map_err(|_| error())?,
+            key,
+            frames,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseKeyPressError {
+    pub text: String,
+}
+
+impl Display for ParseKeyPressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid key press '{}', expected FRAME:KEY[:FRAMES]",
+            self.text
+        )
+    }
+}
+
+impl std::error::Error for ParseKeyPressError {}
+
+// Keys pressed and released at the start of each frame
+#[derive(Default)]
+pub struct Script {
+    presses: Vec<KeyPress>,
+}
+
+impl Script {
+    pub const fn new(presses: Vec<KeyPress>) -> Self {
+        Self { presses }
+    }
+
+    // NOTE: Presses are separated by whitespace or lines, comments start
+    // with #
+    pub fn parse(source: &str) -> Result<Self, ParseKeyPressError> {
+        let presses = source
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(str::split_whitespace)
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self::new(presses))
+    }
+
+    pub fn extend(&mut self, presses: impl IntoIterator<Item = KeyPress>) {
+        self.presses.extend(presses);
+    }
+
+    // NOTE: Releases go first, so a key pressed again right after being
+    // released stays down
+    pub fn update(&self, chip: &mut Chip, frame: u64) {
+        for press in &self.presses {
+            if press.frame + press.frames == frame {
+                chip.unpress_key(press.key);
+            }
+        }
+
+        for press in &self.presses {
+            if press.frame == frame {
+                chip.press_key(press.key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key_press() {
+        assert_eq!(
+            "30:a".parse::<KeyPress>().ok(),
+            Some(KeyPress {
+                frame: 30,
+                key: Key::KeyA,
+                frames: 1,
+            })
+        );
+        assert_eq!(
+            "0:5:10".parse::<KeyPress>().ok(),
+            Some(KeyPress {
+                frame: 0,
+                key: Key::Key5,
+                frames: 10,
+            })
+        );
+
+        assert!("30".parse::<KeyPress>().is_err());
+        assert!("30:G".parse::<KeyPress>().is_err());
+        assert!("30:5:0".parse::<KeyPress>().is_err());
+        assert!("30:5:1:2".parse::<KeyPress>().is_err());
+    }
+
+    #[test]
+    fn test_parse_script() {
+        let script = Script::parse("# Start\n10:5 20:6:2\n\n30:F # Quit\n").expect("Valid script");
+
+        assert_eq!(script.presses.len(), 3);
+        assert_eq!(script.presses[2].key, Key::KeyF);
+        assert!(Script::parse("10:5 oops").is_err());
+    }
+
+    #[test]
+    fn test_update() {
+        let script = Script::parse("1:5:2 3:5").expect("Valid script");
+        let mut chip = Chip::default();
+
+        let pressed: Vec<bool> = (0..5)
+            .map(|frame| {
+                script.update(&mut chip, frame);
+                chip.pressed_keys()[0x5]
+            })
+            .collect();
+
+        assert_eq!(pressed, [false, true, true, true, false]);
+    }
+}
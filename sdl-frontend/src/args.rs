@@ -1,10 +1,181 @@
-use clap::Parser;
+use std::str::FromStr;
+
+use chip::{Breakpoint, Quirks, Watchpoint, DEFAULT_ROM_START, PALETTE_SIZE};
+
+use crate::beep::{Tone, Waveform};
+use clap::{Parser, Subcommand};
+use sdl2::pixels::Color;
+
+pub type Palette = [Color; PALETTE_SIZE];
 
 #[derive(Parser)]
 #[command(name = "Chippy")]
 #[command(author = "Mauro Sambartolomeo")]
 #[command(version = "1.0")]
 #[command(about = "Chip-8 interpreter written in rust")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Rom to run, or an Octo source ending in .8o
+    #[arg(required = true)]
+    pub path: Option<String>,
+
+    /// Instructions executed per frame, at 60 frames per second
+    #[arg(long, default_value_t = 10)]
+    pub cycles_per_frame: u32,
+
+    /// Seed for the random number generator, random if not given
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Seconds of gameplay kept to rewind with backspace, 0 disables it
+    #[arg(long, default_value_t = 10)]
+    pub rewind_seconds: u32,
+
+    /// Behaviour of the ambiguous instructions: default, cosmac-vip, schip,
+    /// xo-chip or single quirks separated by commas
+    #[arg(long, value_parser = Quirks::from_str, default_value = "default")]
+    pub quirks: Quirks,
+
+    /// Colors for each combination of planes: none, first, second and both
+    #[arg(long, value_parser = parse_palette, default_value = "000000,FFFFFF,AAAAAA,555555")]
+    pub palette: Palette,
+
+    /// Start paused in the debugger, F1 toggles it while running
+    #[arg(long)]
+    pub debug: bool,
+
+    /// Pause in the debugger when PC reaches an address (0x2A0), before an
+    /// opcode pattern (Dxyn) or when a register condition becomes true
+    /// (V3 == 0x10). Can be repeated
+    #[arg(long = "break", value_name = "BREAKPOINT")]
+    pub breakpoints: Vec<Breakpoint>,
+
+    /// Pause in the debugger after memory in a range (0x300-0x30F) is read or
+    /// written, add :r or :w to watch only one of them. Can be repeated
+    #[arg(long = "watch", value_name = "WATCHPOINT")]
+    pub watchpoints: Vec<Watchpoint>,
+
+    /// Write every instruction run with the registers before it to a file,
+    /// one per line
+    #[arg(long, value_name = "FILE")]
+    pub trace: Option<String>,
+
+    /// Record the keys pressed in each frame to a movie that plays the
+    /// session back exactly. The seed is random if not given
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["play_movie", "debug", "breakpoints", "watchpoints"]
+    )]
+    pub record_movie: Option<String>,
+
+    /// Play a movie recorded with --record-movie, with its seed, quirks and
+    /// instructions per frame. The keypad is ignored until it ends
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["seed", "quirks", "cycles_per_frame", "debug", "breakpoints", "watchpoints"]
+    )]
+    pub play_movie: Option<String>,
+
+    /// Waveform of the tone played while the sound timer is active
+    #[arg(long, value_enum, default_value_t = Waveform::Square)]
+    pub waveform: Waveform,
+
+    /// Frequency of the tone in Hz
+    #[arg(long, default_value_t = 440, value_parser = clap::value_parser!(u16).range(20..=20000))]
+    pub frequency: u16,
+
+    /// Volume of the tone, from 0 to 100
+    #[arg(long, default_value_t = 25, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub volume: u8,
+}
+
+impl Args {
+    pub fn tone(&self) -> Tone {
+        Tone {
+            waveform: self.waveform,
+            frequency: f32::from(self.frequency),
+            volume: f32::from(self.volume) / 100.0,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Print the instructions of a rom
+    Disasm(DisasmArgs),
+    /// Assemble a source file into a rom
+    Asm(AsmArgs),
+    /// Find the first instruction where two traces written with --trace differ
+    TraceDiff(TraceDiffArgs),
+}
+
+#[derive(clap::Args)]
+pub struct DisasmArgs {
     pub path: String,
+
+    /// Address the rom is loaded at, in decimal or 0x prefixed hex
+    #[arg(long, value_parser = parse_address, default_value_t = DEFAULT_ROM_START)]
+    pub start: u16,
+
+    /// Read every word as an instruction instead of following the program
+    #[arg(long)]
+    pub linear: bool,
+}
+
+#[derive(clap::Args)]
+pub struct AsmArgs {
+    pub path: String,
+
+    /// Where to write the rom, the source path with the ch8 extension if not given
+    #[arg(short, long)]
+    pub output: Option<String>,
+}
+
+#[derive(clap::Args)]
+pub struct TraceDiffArgs {
+    pub left: String,
+    pub right: String,
+
+    /// Lines shown before the first difference
+    #[arg(long, default_value_t = 5)]
+    pub context: usize,
+
+    /// Compare the timers too, they differ when the instructions per frame do
+    #[arg(long)]
+    pub timers: bool,
+}
+
+fn parse_address(value: &str) -> Result<u16, String> {
+    value
+        .strip_prefix("0x")
+        .map_or_else(|| value.parse(), |hex| u16::from_str_radix(hex, 16))
+        .map_err(|e| e.to_string())
+}
+
+fn parse_palette(value: &str) -> Result<Palette, String> {
+    let colors = value
+        .split(',')
+        .map(parse_color)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    colors
+        .try_into()
+        .map_err(|_| format!("Palette must have {PALETTE_SIZE} colors"))
+}
+
+fn parse_color(value: &str) -> Result<Color, String> {
+    let value = value.trim_start_matches('#');
+
+    if value.len() != 6 {
+        return Err(String::from("Color must be in RRGGBB format"));
+    }
+
+    let rgb = u32::from_str_radix(value, 16).map_err(|e| e.to_string())?;
+
+    Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
 }
@@ -1,15 +1,80 @@
 use std::error::Error;
+use std::f32::consts::TAU;
 
-use sdl2::audio::{AudioCVT, AudioCallback, AudioDevice, AudioSpecDesired, AudioSpecWAV};
+use chip::{AudioState, PATTERN_BITS};
+use clap::ValueEnum;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
 use sdl2::Sdl;
 
+const SAMPLE_RATE: i32 = 44100;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl Waveform {
+    // NOTE: Phase goes from 0 to 1, the result from -1 to 1
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Self::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Self::Sine => (phase * TAU).sin(),
+            Self::Triangle => 4.0f32.mul_add((phase - 0.5).abs(), -1.0),
+        }
+    }
+}
+
+pub struct Tone {
+    pub waveform: Waveform,
+    pub frequency: f32,
+    // NOTE: From 0 to 1
+    pub volume: f32,
+}
+
 pub struct Beep {
     audio_device: AudioDevice<Callback>,
 }
 
+// NOTE: Plays the tone, or the XO-CHIP 1-bit audio pattern once the rom
+// loads one, while the sound timer is active
 pub struct Callback {
-    buffer: Vec<u8>,
-    position: usize,
+    audio: AudioState,
+    tone: Tone,
+    sample_rate: f32,
+    phase: f32,
+    position: f32,
+}
+
+impl Callback {
+    #[allow(clippy::cast_precision_loss, clippy::cast_sign_loss)]
+    fn next_sample(&mut self) -> f32 {
+        if self.audio.pattern.is_some() {
+            let sample = if self.audio.sample(self.position as usize) {
+                1.0
+            } else {
+                -1.0
+            };
+
+            let step = self.audio.playback_rate() / self.sample_rate;
+            self.position = (self.position + step) % PATTERN_BITS as f32;
+
+            sample
+        } else {
+            let sample = self.tone.waveform.sample(self.phase);
+
+            self.phase = (self.phase + self.tone.frequency / self.sample_rate).fract();
+
+            sample
+        }
+    }
 }
 
 impl AudioCallback for Callback {
@@ -17,57 +82,44 @@ impl AudioCallback for Callback {
 
     fn callback(&mut self, out: &mut [Self::Channel]) {
         for value in out.iter_mut() {
-            *value = if self.position < self.buffer.len() {
-                let sample = i16::from_le_bytes([
-                    self.buffer[self.position],
-                    self.buffer[self.position + 1],
-                ]);
-                self.position += 2;
-                sample
-            } else {
-                0
+            if !self.audio.playing {
+                *value = 0;
+                continue;
             }
+
+            let sample = self.next_sample() * self.tone.volume;
+
+            *value = (sample * f32::from(i16::MAX)) as i16;
         }
     }
 }
 
 impl Beep {
-    pub fn init(sdl: &Sdl) -> Result<Self, Box<dyn Error>> {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn init(sdl: &Sdl, tone: Tone) -> Result<Self, Box<dyn Error>> {
         let audio = sdl.audio()?;
 
         let spec = AudioSpecDesired {
-            freq: None,
-            channels: None,
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
             samples: None,
         };
 
-        let wav = AudioSpecWAV::load_wav("./audio/beep.wav")?;
-
-        let audio_device = audio.open_playback(None, &spec, |spec| {
-            let converter = AudioCVT::new(
-                wav.format,
-                wav.channels,
-                wav.freq,
-                spec.format,
-                spec.channels,
-                spec.freq,
-            )
-            .expect("converter creation error");
-
-            let buffer = converter.convert(wav.buffer().to_vec());
-
-            Callback {
-                buffer,
-                position: 0,
-            }
+        let audio_device = audio.open_playback(None, &spec, |spec| Callback {
+            audio: AudioState::default(),
+            tone,
+            sample_rate: spec.freq as f32,
+            phase: 0.0,
+            position: 0.0,
         })?;
 
+        audio_device.resume();
+
         Ok(Self { audio_device })
     }
 
-    pub fn beep(&mut self) {
-        self.audio_device.resume();
+    pub fn update(&mut self, audio: AudioState) {
         let mut lock = self.audio_device.lock();
-        lock.position = 0;
+        lock.audio = audio;
     }
 }
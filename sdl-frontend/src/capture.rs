@@ -0,0 +1,286 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use chip::Screen;
+
+use crate::args::Palette;
+use crate::display::pixel_scale;
+
+// NOTE: Recordings are smaller than the window, hi-res pixels are half as
+// big as low-res ones
+const RECORDING_WIDTH: usize = 512;
+
+// NOTE: Gif delays are in hundredths of a second and most viewers slow
+// down frames shorter than 2, so faster changes are merged
+const CENTISECONDS_PER_SECOND: u64 = 100;
+const FRAMES_PER_SECOND: u64 = 60;
+const MIN_DELAY: u64 = 2;
+
+#[derive(Clone, Copy)]
+pub enum Format {
+    Png,
+    Pbm,
+}
+
+// A screen shown from a moment of the recording until the next one
+struct Frame {
+    time: u64,
+    width: usize,
+    pixels: Vec<u8>,
+}
+
+struct Recording {
+    frames: Vec<Frame>,
+    elapsed: u64,
+}
+
+// NOTE: Screenshots and recordings are stored next to the rom, numbered so
+// none is overwritten
+pub struct Capture {
+    rom_path: String,
+    palette: chip::Palette,
+    recording: Option<Recording>,
+}
+
+impl Capture {
+    pub fn new(rom_path: &str, palette: &Palette) -> Self {
+        Self {
+            rom_path: rom_path.to_owned(),
+            palette: palette.map(|color| [color.r, color.g, color.b]),
+            recording: None,
+        }
+    }
+
+    // NOTE: PNG images have the size and colors of the window, PBM ones
+    // have one pixel per pixel of the screen
+    pub fn screenshot(&self, screen: &Screen, format: Format) -> Result<String, Box<dyn Error>> {
+        let extension = match format {
+            Format::Png => "png",
+            Format::Pbm => "pbm",
+        };
+        let path = self.free_path("screenshot", extension)?;
+
+        let writer = BufWriter::new(File::create(&path)?);
+        match format {
+            Format::Png => screen.write_png(writer, pixel_scale(screen) as usize, &self.palette)?,
+            Format::Pbm => screen.write_pbm(writer)?,
+        }
+
+        Ok(path)
+    }
+
+    pub const fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // NOTE: The recording starts with the current screen, the rom may not
+    // draw again for a while
+    pub fn start_recording(&mut self, screen: &Screen) {
+        let mut recording = Recording {
+            frames: Vec::new(),
+            elapsed: 0,
+        };
+        recording.frames.push(frame(screen, 0));
+
+        self.recording = Some(recording);
+    }
+
+    // NOTE: Must be called once per frame, at 60 Hz, whether the screen
+    // changed or not
+    pub fn record_frame(&mut self, screen: &Screen, draw: bool) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+        recording.elapsed += 1;
+
+        if !draw {
+            return;
+        }
+
+        let time = centiseconds(recording.elapsed);
+        match recording.frames.last_mut() {
+            Some(last) if time - last.time < MIN_DELAY => *last = frame(screen, last.time),
+            _ => recording.frames.push(frame(screen, time)),
+        }
+    }
+
+    // NOTE: Returns the path of the gif, nothing is written if there was no
+    // recording
+    pub fn stop_recording(&mut self) -> Result<Option<String>, Box<dyn Error>> {
+        let Some(recording) = self.recording.take() else {
+            return Ok(None);
+        };
+
+        let path = self.free_path("recording", "gif")?;
+        write_gif(&path, &recording, &self.palette)?;
+
+        Ok(Some(path))
+    }
+
+    fn free_path(&self, name: &str, extension: &str) -> Result<String, Box<dyn Error>> {
+        let path = (0..u32::MAX)
+            .map(|number| format!("{}.{name}{number}.{extension}", self.rom_path))
+            .find(|path| !Path::new(path).exists())
+            .ok_or("Too many files with the same name")?;
+
+        Ok(path)
+    }
+}
+
+fn frame(screen: &Screen, time: u64) -> Frame {
+    Frame {
+        time,
+        width: screen.width(),
+        pixels: screen.rows().flatten().copied().collect(),
+    }
+}
+
+// NOTE: Rounded to the nearest hundredth so the delays don't drift
+const fn centiseconds(frames: u64) -> u64 {
+    (frames * CENTISECONDS_PER_SECOND + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND
+}
+
+// NOTE: Every frame lasts until the next one starts, the last one until
+// the recording stopped
+fn delays(recording: &Recording) -> Vec<u16> {
+    let end = centiseconds(recording.elapsed);
+
+    recording
+        .frames
+        .iter()
+        .enumerate()
+        .map(|(index, frame)| {
+            let next = recording
+                .frames
+                .get(index + 1)
+                .map_or(end, |next| next.time);
+            next.saturating_sub(frame.time).max(MIN_DELAY) as u16
+        })
+        .collect()
+}
+
+fn write_gif(
+    path: &str,
+    recording: &Recording,
+    palette: &chip::Palette,
+) -> Result<(), Box<dyn Error>> {
+    let height = RECORDING_WIDTH / 2;
+
+    let writer = BufWriter::new(File::create(path)?);
+    let mut encoder = gif::Encoder::new(
+        writer,
+        RECORDING_WIDTH as u16,
+        height as u16,
+        &palette.concat(),
+    )?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+
+    for (frame, delay) in recording.frames.iter().zip(delays(recording)) {
+        let scale = RECORDING_WIDTH / frame.width;
+        let pixels: Vec<u8> = frame
+            .pixels
+            .chunks(frame.width)
+            .flat_map(|row| {
+                let line: Vec<u8> = row
+                    .iter()
+                    .flat_map(|pixel| std::iter::repeat_n(*pixel, scale))
+                    .collect();
+                std::iter::repeat_n(line, scale).flatten()
+            })
+            .collect();
+
+        let mut image =
+            gif::Frame::from_indexed_pixels(RECORDING_WIDTH as u16, height as u16, pixels, None);
+        image.delay = delay;
+        encoder.write_frame(&image)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip::{Chip, DEFAULT_PALETTE};
+
+    fn capture() -> Capture {
+        Capture {
+            rom_path: String::new(),
+            palette: DEFAULT_PALETTE,
+            recording: None,
+        }
+    }
+
+    #[test]
+    fn test_centiseconds() {
+        assert_eq!(centiseconds(0), 0);
+        assert_eq!(centiseconds(1), 2);
+        assert_eq!(centiseconds(3), 5);
+        assert_eq!(centiseconds(60), 100);
+    }
+
+    #[test]
+    fn test_record_frames() {
+        let chip = Chip::default();
+        let mut capture = capture();
+
+        capture.record_frame(chip.screen(), true);
+        assert!(!capture.is_recording());
+
+        capture.start_recording(chip.screen());
+        for frame in 1..=12 {
+            capture.record_frame(chip.screen(), matches!(frame, 3 | 4 | 5 | 9 | 12));
+        }
+
+        let recording = capture.recording.as_ref().expect("Recording");
+        let times: Vec<u64> = recording.frames.iter().map(|frame| frame.time).collect();
+        assert_eq!(times, [0, 5, 7, 15, 20]);
+        assert_eq!(delays(recording), [5, 2, 8, 5, 2]);
+        assert_eq!(recording.frames[0].pixels.len(), 64 * 32);
+    }
+
+    #[test]
+    fn test_write_gif() {
+        let chip = Chip::default();
+        let mut capture = capture();
+        capture.rom_path = std::env::temp_dir()
+            .join(format!("chippy-capture-{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+
+        capture.start_recording(chip.screen());
+        for frame in 1..=6 {
+            capture.record_frame(chip.screen(), frame == 3);
+        }
+        let path = capture
+            .stop_recording()
+            .expect("Gif written")
+            .expect("Recording");
+
+        let file = File::open(&path).expect("Gif written");
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(file)
+            .expect("Valid gif");
+        assert_eq!((decoder.width(), decoder.height()), (512, 256));
+
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().expect("Valid gif") {
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, [5, 5]);
+
+        std::fs::remove_file(path).expect("Gif written");
+    }
+
+    #[test]
+    fn test_stop_without_recording() {
+        let mut capture = capture();
+
+        assert!(capture
+            .stop_recording()
+            .expect("Nothing to write")
+            .is_none());
+    }
+}
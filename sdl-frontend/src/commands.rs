@@ -0,0 +1,112 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Write};
+
+use std::path::Path;
+
+use chip::{asm, disasm, DEFAULT_ROM_START};
+
+use crate::args::{AsmArgs, Command, DisasmArgs, TraceDiffArgs};
+use crate::trace_diff::{first_divergence, Outcome, Side};
+
+// NOTE: Commands that work on roms without running them
+pub fn run(command: &Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Disasm(args) => disassemble(args),
+        Command::Asm(args) => assemble(args),
+        Command::TraceDiff(args) => trace_diff(args),
+    }
+}
+
+fn disassemble(args: &DisasmArgs) -> Result<(), Box<dyn Error>> {
+    let rom = std::fs::read(&args.path)?;
+
+    let lines = if args.linear {
+        disasm::disassemble(&rom, args.start)
+    } else {
+        disasm::disassemble_recursive(&rom, args.start)
+    };
+
+    let mut out = std::io::stdout().lock();
+    if args.start != DEFAULT_ROM_START {
+        writeln!(out, "ORG {:#05X}", args.start)?;
+    }
+    for line in lines {
+        writeln!(out, "{line}")?;
+    }
+
+    Ok(())
+}
+
+fn assemble(args: &AsmArgs) -> Result<(), Box<dyn Error>> {
+    let rom = asm::assemble_file(&args.path)?;
+
+    let output = args.output.clone().unwrap_or_else(|| {
+        Path::new(&args.path)
+            .with_extension("ch8")
+            .to_string_lossy()
+            .into_owned()
+    });
+    std::fs::write(&output, &rom)?;
+
+    println!("Wrote {} bytes to {output}", rom.len());
+
+    Ok(())
+}
+
+// NOTE: Differences are an error so the exit status tells if there were any
+fn trace_diff(args: &TraceDiffArgs) -> Result<(), Box<dyn Error>> {
+    let left = BufReader::new(File::open(&args.left)?);
+    let right = BufReader::new(File::open(&args.right)?);
+
+    let outcome = first_divergence(
+        (&args.left, left),
+        (&args.right, right),
+        args.context,
+        args.timers,
+    )?;
+
+    let divergence = match outcome {
+        Outcome::Same(compared) => {
+            println!("No differences in {compared} instructions");
+            return Ok(());
+        }
+        Outcome::Diverged(divergence) => divergence,
+        Outcome::Ended {
+            compared,
+            side,
+            line,
+        } => {
+            let (longer, shorter) = match side {
+                Side::Left => (&args.left, &args.right),
+                Side::Right => (&args.right, &args.left),
+            };
+            println!(
+                "No differences in {compared} instructions, then {shorter} ends and {longer} goes on at line {}",
+                line.number
+            );
+            println!("+ {}", line.text);
+
+            return Err(format!("{shorter} ends before {longer}").into());
+        }
+    };
+
+    let mut out = std::io::stdout().lock();
+    writeln!(
+        out,
+        "First difference at line {} of {} and line {} of {}",
+        divergence.left.number, args.left, divergence.right.number, args.right
+    )?;
+    for line in &divergence.context {
+        writeln!(out, "  {line}")?;
+    }
+    writeln!(out, "- {}", divergence.left.text)?;
+    writeln!(out, "+ {}", divergence.right.text)?;
+
+    Err(format!(
+        "The traces differ in {} at cycle {}",
+        divergence.fields.join(", "),
+        divergence.left.entry.cycle
+    )
+    .into())
+}
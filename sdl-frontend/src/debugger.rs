@@ -0,0 +1,302 @@
+use chip::disasm::disassemble;
+use chip::{Actions, BreakReason, Chip, ChipError, Opcode};
+
+// NOTE: Bytes of memory disassembled before and after PC in the panel
+const CONTEXT_BEFORE: usize = 8;
+const CONTEXT_AFTER: usize = 24;
+const STACK_PER_LINE: usize = 4;
+
+enum State {
+    Running,
+    Paused,
+    Step,
+    // NOTE: Runs until the call returns to the instruction after it
+    StepOver { address: u16, depth: usize },
+}
+
+pub struct Debugger {
+    active: bool,
+    state: State,
+    reason: Option<BreakReason>,
+}
+
+impl Debugger {
+    // NOTE: The debugger starts paused when active from the beginning
+    pub const fn new(active: bool) -> Self {
+        let state = if active {
+            State::Paused
+        } else {
+            State::Running
+        };
+
+        Self {
+            active,
+            state,
+            reason: None,
+        }
+    }
+
+    pub const fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub const fn is_paused(&self) -> bool {
+        matches!(self.state, State::Paused)
+    }
+
+    pub const fn is_running(&self) -> bool {
+        matches!(self.state, State::Running)
+    }
+
+    // NOTE: Breakpoints open the debugger even if it was hidden
+    pub const fn pause(&mut self, reason: BreakReason) {
+        self.active = true;
+        self.state = State::Paused;
+        self.reason = Some(reason);
+    }
+
+    pub const fn toggle(&mut self) {
+        self.active = !self.active;
+        self.set_state(if self.active {
+            State::Paused
+        } else {
+            State::Running
+        });
+    }
+
+    // NOTE: Continues while running and pauses otherwise
+    pub const fn toggle_pause(&mut self) {
+        if !self.active {
+            return;
+        }
+
+        self.set_state(match self.state {
+            State::Paused => State::Running,
+            _ => State::Paused,
+        });
+    }
+
+    pub const fn step(&mut self) {
+        if self.active {
+            self.set_state(State::Step);
+        }
+    }
+
+    pub fn step_over(&mut self, chip: &Chip) {
+        if !self.active {
+            return;
+        }
+
+        self.set_state(match chip.current_opcode() {
+            Some(Opcode::Call(_)) => State::StepOver {
+                address: chip.registers().pc.wrapping_add(2),
+                depth: chip.stack().len(),
+            },
+            _ => State::Step,
+        });
+    }
+
+    // NOTE: The reason of the last break is shown until the user moves on
+    const fn set_state(&mut self, state: State) {
+        self.state = state;
+        self.reason = None;
+    }
+
+    // NOTE: Stepping over a call runs whole frames until it returns, so the
+    // timers keep ticking while the subroutine runs
+    pub fn run(&mut self, chip: &mut Chip, cycles: u32) -> Result<Actions, ChipError> {
+        let mut frame = Actions {
+            draw: false,
+            exit: false,
+            break_reason: None,
+        };
+
+        match self.state {
+            State::Running | State::Paused => (),
+            State::Step => {
+                frame = chip.run_cycle()?;
+                self.state = State::Paused;
+            }
+            State::StepOver { address, depth } => {
+                for _ in 0..cycles {
+                    let actions = chip.run_cycle()?;
+                    frame.draw |= actions.draw;
+                    frame.exit |= actions.exit;
+
+                    if actions.break_reason.is_some() {
+                        frame.break_reason = actions.break_reason;
+                        return Ok(frame);
+                    }
+
+                    if chip.registers().pc == address && chip.stack().len() == depth {
+                        self.state = State::Paused;
+                        return Ok(frame);
+                    }
+                    if frame.exit {
+                        return Ok(frame);
+                    }
+                }
+
+                chip.tick_timers();
+            }
+        }
+
+        Ok(frame)
+    }
+
+    pub fn panel(&self, chip: &Chip) -> Vec<String> {
+        let registers = chip.registers();
+
+        let mut lines = vec![
+            String::from(if self.is_running() {
+                "RUNNING"
+            } else {
+                "PAUSED"
+            }),
+            self.reason
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        ];
+
+        for (index, values) in registers.v.chunks(4).enumerate() {
+            let line = values
+                .iter()
+                .enumerate()
+                .map(|(column, value)| format!("V{:X} {value:02X}", index * 4 + column))
+                .collect::<Vec<_>>()
+                .join("  ");
+            lines.push(line);
+        }
+
+        lines.push(format!("I  {:04X}   PC {:04X}", registers.i, registers.pc));
+        lines.push(format!(
+            "DT {:02X}     ST {:02X}",
+            registers.delay_timer, registers.sound_timer
+        ));
+
+        // NOTE: The newest return address first, a few on each line
+        let stack: Vec<String> = chip
+            .stack()
+            .iter()
+            .rev()
+            .map(|address| format!("{address:04X}"))
+            .collect();
+        let mut chunks = stack.chunks(STACK_PER_LINE);
+        lines.push(format!(
+            "STACK {}",
+            chunks.next().unwrap_or_default().join(" ")
+        ));
+        for addresses in chunks {
+            lines.push(format!("      {}", addresses.join(" ")));
+        }
+
+        let keys: Vec<String> = chip
+            .pressed_keys()
+            .iter()
+            .enumerate()
+            .filter(|(_, pressed)| **pressed)
+            .map(|(key, _)| format!("{key:X}"))
+            .collect();
+        lines.push(format!("KEYS {}", keys.join(" ")));
+
+        lines.push(String::new());
+        lines.extend(code(chip.memory(), registers.pc));
+
+        lines
+    }
+}
+
+// NOTE: Instructions are read from PC, so the ones before it may be
+// misaligned when code and data are mixed
+fn code(memory: &[u8], pc: u16) -> Vec<String> {
+    let pc = usize::from(pc);
+    let start = pc.saturating_sub(CONTEXT_BEFORE);
+    let end = memory.len().min(pc + CONTEXT_AFTER);
+
+    let before = disassemble(&memory[start..pc], start as u16);
+    let after = disassemble(&memory[pc..end], pc as u16);
+
+    before
+        .iter()
+        .map(|line| format!("  {:04X} {}", line.address, line.mnemonic))
+        .chain(after.iter().enumerate().map(|(index, line)| {
+            let marker = if index == 0 { '>' } else { ' ' };
+            format!("{marker} {:04X} {}", line.address, line.mnemonic)
+        }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip::{Breakpoint, Quirks, DEFAULT_ROM_START};
+
+    // CALL 0x206 - LD V0, 0x01 - JP 0x204 - LD V1, 0x02 - LD V2, 0x03 - RET
+    const PROGRAM: [u8; 12] = [
+        0x22, 0x06, 0x60, 0x01, 0x12, 0x04, 0x61, 0x02, 0x62, 0x03, 0x00, 0xEE,
+    ];
+
+    fn chip() -> Chip {
+        let mut chip = Chip::new(DEFAULT_ROM_START, Quirks::default());
+        chip.load_rom_bytes(&PROGRAM).expect("Valid program");
+        chip
+    }
+
+    #[test]
+    fn test_step() {
+        let mut chip = chip();
+        let mut debugger = Debugger::new(true);
+
+        debugger.run(&mut chip, 10).expect("Valid program");
+        assert_eq!(chip.registers().pc, DEFAULT_ROM_START);
+
+        debugger.step();
+        debugger.run(&mut chip, 10).expect("Valid program");
+        assert_eq!(chip.registers().pc, 0x206);
+        assert!(debugger.is_paused());
+    }
+
+    #[test]
+    fn test_step_over() {
+        let mut chip = chip();
+        let mut debugger = Debugger::new(true);
+
+        debugger.step_over(&chip);
+        debugger.run(&mut chip, 10).expect("Valid program");
+
+        let registers = chip.registers();
+        assert_eq!(registers.pc, 0x202);
+        assert_eq!(registers.v[1..3], [0x02, 0x03]);
+        assert!(chip.stack().is_empty());
+        assert!(debugger.is_paused());
+    }
+
+    #[test]
+    fn test_step_over_breakpoint() {
+        let mut chip = chip();
+        chip.add_breakpoint(Breakpoint::Address(0x208));
+        let mut debugger = Debugger::new(true);
+
+        debugger.step_over(&chip);
+        let actions = debugger.run(&mut chip, 10).expect("Valid program");
+
+        assert_eq!(
+            actions.break_reason,
+            Some(BreakReason::Breakpoint(Breakpoint::Address(0x208)))
+        );
+        assert_eq!(chip.registers().pc, 0x208);
+    }
+
+    #[test]
+    fn test_panel() {
+        let chip = chip();
+        let debugger = Debugger::new(true);
+
+        let panel = debugger.panel(&chip);
+
+        assert_eq!(panel[0], "PAUSED");
+        assert!(panel.contains(&String::from("I  0000   PC 0200")));
+        assert!(panel.contains(&String::from("> 0200 CALL L206")));
+    }
+}
@@ -1,61 +1,152 @@
 use std::error::Error;
 
+use sdl2::rect::Rect;
 use sdl2::render::WindowCanvas;
 use sdl2::Sdl;
-use sdl2::{pixels::Color, rect::Rect};
 
 use chip::{Screen, DISPLAY_HEIGHT, DISPLAY_WIDTH};
 
+use crate::args::Palette;
+use crate::font::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
+
 const SCALE: u32 = 20;
-const BACKGROUND_COLOR: Color = Color::RGB(0, 0, 0);
-const FOREGROUND_COLOR: Color = Color::RGB(255, 255, 255);
+const SCREEN_WIDTH: u32 = SCALE * DISPLAY_WIDTH as u32;
+const SCREEN_HEIGHT: u32 = SCALE * DISPLAY_HEIGHT as u32;
+
+// NOTE: Text is drawn with pixels of this size, leaving one empty pixel
+// between characters and lines
+const TEXT_SCALE: u32 = 3;
+const CHAR_WIDTH: u32 = (GLYPH_WIDTH as u32 + 1) * TEXT_SCALE;
+const LINE_HEIGHT: u32 = (GLYPH_HEIGHT as u32 + 1) * TEXT_SCALE;
+const PANEL_COLUMNS: u32 = 30;
+const PANEL_WIDTH: u32 = (PANEL_COLUMNS + 2) * CHAR_WIDTH;
 
 pub struct Display {
     canvas: WindowCanvas,
+    palette: Palette,
+    panel: Option<Vec<String>>,
 }
 
 impl Display {
-    pub fn init(sdl: &Sdl) -> Result<Self, Box<dyn Error>> {
+    pub fn init(sdl: &Sdl, palette: Palette) -> Result<Self, Box<dyn Error>> {
         let video_subsystem = sdl.video()?;
         let window = video_subsystem
-            .window(
-                "chippy",
-                SCALE * DISPLAY_WIDTH as u32,
-                SCALE * DISPLAY_HEIGHT as u32,
-            )
+            .window("chippy", SCREEN_WIDTH, SCREEN_HEIGHT)
             .build()?;
 
         let mut canvas = window.into_canvas().build()?;
 
-        canvas.set_draw_color(BACKGROUND_COLOR);
+        canvas.set_draw_color(palette[0]);
         canvas.clear();
 
         canvas.present();
 
-        Ok(Self { canvas })
+        Ok(Self {
+            canvas,
+            palette,
+            panel: None,
+        })
+    }
+
+    pub fn show_slot(&mut self, slot: u8) {
+        // NOTE: The title never contains a nul byte
+        let _ = self
+            .canvas
+            .window_mut()
+            .set_title(&format!("chippy - slot {slot}"));
+    }
+
+    // NOTE: The window grows to fit a panel of text at the right of the
+    // screen while it is shown
+    pub fn set_panel(&mut self, panel: Option<Vec<String>>) {
+        if panel.is_some() != self.panel.is_some() {
+            let width = if panel.is_some() {
+                SCREEN_WIDTH + PANEL_WIDTH
+            } else {
+                SCREEN_WIDTH
+            };
+            // NOTE: The size is never zero
+            let _ = self.canvas.window_mut().set_size(width, SCREEN_HEIGHT);
+        }
+
+        self.panel = panel;
+    }
+
+    pub const fn has_panel(&self) -> bool {
+        self.panel.is_some()
     }
 
     pub fn draw(&mut self, screen: &Screen) -> Result<(), String> {
-        for (y, row) in screen.iter().enumerate() {
+        let scale = pixel_scale(screen);
+
+        for (y, row) in screen.rows().enumerate() {
             for (x, pixel) in row.iter().enumerate() {
                 let rect = Rect::new(
-                    x as i32 * SCALE as i32,
-                    y as i32 * SCALE as i32,
-                    SCALE,
-                    SCALE,
+                    x as i32 * scale as i32,
+                    y as i32 * scale as i32,
+                    scale,
+                    scale,
                 );
 
-                if *pixel {
-                    self.canvas.set_draw_color(FOREGROUND_COLOR);
-                } else {
-                    self.canvas.set_draw_color(BACKGROUND_COLOR);
-                }
+                self.canvas.set_draw_color(self.palette[*pixel as usize]);
 
                 self.canvas.fill_rect(rect)?;
             }
         }
 
+        if let Some(panel) = &self.panel {
+            draw_panel(&mut self.canvas, &self.palette, panel)?;
+        }
+
         self.canvas.present();
         Ok(())
     }
 }
+
+// NOTE: The window keeps its size, hi-res pixels are drawn smaller
+pub const fn pixel_scale(screen: &Screen) -> u32 {
+    SCALE * DISPLAY_WIDTH as u32 / screen.width() as u32
+}
+
+fn draw_panel(
+    canvas: &mut WindowCanvas,
+    palette: &Palette,
+    lines: &[String],
+) -> Result<(), String> {
+    canvas.set_draw_color(palette[0]);
+    canvas.fill_rect(Rect::new(
+        SCREEN_WIDTH as i32,
+        0,
+        PANEL_WIDTH,
+        SCREEN_HEIGHT,
+    ))?;
+
+    canvas.set_draw_color(palette[1]);
+    for (row, line) in lines.iter().enumerate() {
+        let top = (row as u32 + 1) * LINE_HEIGHT;
+        for (column, c) in line.chars().take(PANEL_COLUMNS as usize).enumerate() {
+            let left = SCREEN_WIDTH + (column as u32 + 1) * CHAR_WIDTH;
+            draw_glyph(canvas, c, left, top)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn draw_glyph(canvas: &mut WindowCanvas, c: char, left: u32, top: u32) -> Result<(), String> {
+    for (y, row) in glyph(c).iter().enumerate() {
+        for x in 0..GLYPH_WIDTH {
+            if row >> (GLYPH_WIDTH - 1 - x) & 1 == 1 {
+                let rect = Rect::new(
+                    (left + x as u32 * TEXT_SCALE) as i32,
+                    (top + y as u32 * TEXT_SCALE) as i32,
+                    TEXT_SCALE,
+                    TEXT_SCALE,
+                );
+                canvas.fill_rect(rect)?;
+            }
+        }
+    }
+
+    Ok(())
+}
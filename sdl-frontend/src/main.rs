@@ -1,75 +1,538 @@
 mod args;
 mod beep;
+mod capture;
+mod commands;
+mod debugger;
 mod display;
+mod font;
+mod movie;
+mod program;
+mod rewind;
+mod states;
+mod trace_diff;
 
 use args::Args;
-use chip::{Chip, Key};
+use chip::{
+    Actions, Chip, ChipError, Key, Movie, TraceEntry, TraceHook, DEFAULT_ROM_START, RPL_FLAGS_COUNT,
+};
 use display::Display;
 
 use clap::Parser;
 use sdl2::event::Event;
-use sdl2::keyboard::Scancode;
+use sdl2::keyboard::{Mod, Scancode};
+use sdl2::messagebox::{show_simple_message_box, MessageBoxFlag};
+use sdl2::EventPump;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::time::{Duration, Instant};
 
 use crate::beep::Beep;
+use crate::capture::{Capture, Format};
+use crate::debugger::Debugger;
+use crate::movie::MovieSession;
+use crate::program::Program;
+use crate::rewind::Rewind;
+use crate::states::SaveStates;
 
-const CPU_TICK: Duration = Duration::from_micros(1_000_000 / 500);
+const FRAMES_PER_SECOND: u32 = 60;
+const FRAME: Duration = Duration::from_micros(1_000_000 / FRAMES_PER_SECOND as u64);
 
 fn main() {
-    let rom_path = Args::parse().path;
+    let args = Args::parse();
 
-    let mut chip: Chip = Chip::default();
-    chip.load_rom(rom_path).expect("Valid_rom");
+    if let Some(command) = &args.command {
+        if let Err(error) = commands::run(command) {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    let path = args
+        .path
+        .clone()
+        .expect("The rom path is required without a command");
 
     let sdl = sdl2::init().expect("Sdl creation error");
 
-    let mut display = Display::init(&sdl).expect("Screen initialization error");
+    let mut program = Program::new(path.clone());
+    let Some((mut chip, mut movie)) = start(&mut program, &args) else {
+        return;
+    };
+    let cycles_per_frame = movie
+        .as_ref()
+        .map_or(args.cycles_per_frame, MovieSession::cycles_per_frame);
 
-    let mut beep = Beep::init(&sdl).expect("beep initialization error");
+    // NOTE: Movies start with the RPL flags cleared and don't change them
+    let rpl_path = movie.is_none().then(|| format!("{path}.rpl"));
+    if let Some(flags) = rpl_path.as_deref().and_then(load_rpl_flags) {
+        chip.set_rpl_flags(flags);
+    }
+    let initial_rpl_flags = *chip.rpl_flags();
 
-    let mut events = sdl.event_pump().expect("event pump creation error");
+    let mut display = Display::init(&sdl, args.palette).expect("Screen initialization error");
+
+    let mut beep = Beep::init(&sdl, args.tone()).expect("beep initialization error");
 
-    let mut last_cpu_tick = Instant::now();
+    let mut save_states = SaveStates::new(&path);
+    let mut capture = Capture::new(&path, &args.palette);
+    display.show_slot(save_states.slot());
+
+    let mut rewind = Rewind::new((args.rewind_seconds * FRAMES_PER_SECOND) as usize);
+    let mut rewinding = false;
+
+    let mut debugger = Debugger::new(args.debug);
+    let mut cycles_left = 0;
+
+    let mut events = sdl.event_pump().expect("event pump creation error");
 
     'main: loop {
-        for event in events.poll_iter() {
-            match event {
-                Event::Quit { .. } => break 'main,
-                Event::KeyDown { scancode, .. } => {
-                    if let Some(key) = get_key(scancode) {
-                        chip.press_key(key);
-                    }
-                }
-                Event::KeyUp { scancode, .. } => {
-                    if let Some(key) = get_key(scancode) {
-                        chip.unpress_key(key);
-                    }
-                }
-                _ => (),
+        let frame_start = Instant::now();
+
+        if !handle_events(
+            &mut events,
+            &mut chip,
+            &mut display,
+            &mut save_states,
+            &mut capture,
+            &mut debugger,
+            &mut movie,
+            &mut rewinding,
+        ) {
+            break 'main;
+        }
+
+        if movie.is_none() && program.changed() {
+            if let Some(reloaded) = reload(&mut program, &args, &mut chip) {
+                chip = reloaded;
+                cycles_left = 0;
+                rewind = Rewind::new((args.rewind_seconds * FRAMES_PER_SECOND) as usize);
+                display
+                    .draw(chip.screen())
+                    .expect("Error drawing to screen");
             }
         }
 
-        let actions = chip.run_cycle();
+        let draw = if rewinding {
+            cycles_left = 0;
+            rewind_frame(&mut chip, &mut rewind)
+        } else {
+            let Some(draw) = emulate_frame(
+                &mut chip,
+                cycles_per_frame,
+                &mut cycles_left,
+                &mut debugger,
+                &mut rewind,
+                movie.as_mut(),
+            ) else {
+                break 'main;
+            };
+            draw
+        };
 
-        if actions.draw {
+        if movie.as_ref().is_some_and(MovieSession::is_finished) {
+            finish_movie(movie.take());
+        }
+
+        capture.record_frame(chip.screen(), draw);
+
+        let panel = debugger.is_active().then(|| debugger.panel(&chip));
+        let draw = draw || panel.is_some() || display.has_panel();
+        display.set_panel(panel);
+
+        if draw {
             display
                 .draw(chip.screen())
                 .expect("Error drawing to screen");
         }
 
-        if actions.beep {
-            beep.beep();
-        }
+        beep.update(chip.audio_state());
 
-        // TODO: Sleep?
-        while last_cpu_tick.elapsed() < CPU_TICK {}
+        std::thread::sleep(FRAME.saturating_sub(frame_start.elapsed()));
+    }
 
-        last_cpu_tick = Instant::now();
+    if let Some(rpl_path) = rpl_path.filter(|_| *chip.rpl_flags() != initial_rpl_flags) {
+        save_rpl_flags(&rpl_path, chip.rpl_flags());
     }
+    stop_recording(&mut capture);
+    finish_movie(movie);
 
     println!();
 }
 
+fn new_chip(args: &Args, seed: Option<u64>) -> Chip {
+    let mut chip = seed.map_or_else(
+        || Chip::new(DEFAULT_ROM_START, args.quirks),
+        |seed| Chip::with_seed(DEFAULT_ROM_START, args.quirks, seed),
+    );
+
+    for breakpoint in &args.breakpoints {
+        chip.add_breakpoint(*breakpoint);
+    }
+    for watchpoint in &args.watchpoints {
+        chip.add_watchpoint(watchpoint.clone());
+    }
+
+    chip
+}
+
+fn start(program: &mut Program, args: &Args) -> Option<(Chip, Option<MovieSession>)> {
+    let rom = program.load().map_err(|error| show_error(&*error)).ok()?;
+
+    let (mut chip, movie) = if let Some(path) = &args.play_movie {
+        let (movie, chip) = MovieSession::play(path, &rom)
+            .map_err(|error| show_error(&*error))
+            .ok()?;
+        (chip, Some(movie))
+    } else {
+        // NOTE: Recordings need a known seed to play back the same numbers
+        let seed = args
+            .seed
+            .or_else(|| args.record_movie.is_some().then(rand::random));
+        let mut chip = new_chip(args, seed);
+        chip.load_rom_bytes(&rom)
+            .map_err(|error| show_error(&error))
+            .ok()?;
+
+        let movie = args.record_movie.clone().zip(seed).map(|(path, seed)| {
+            let movie = Movie::new(&rom, seed, args.quirks, args.cycles_per_frame);
+            MovieSession::record(path, movie)
+        });
+        (chip, movie)
+    };
+
+    if let Some(path) = &args.trace {
+        let hook = trace_hook(path).map_err(|error| show_error(&error)).ok()?;
+        chip.set_trace_hook(hook);
+    }
+
+    Some((chip, movie))
+}
+
+// NOTE: The program restarts from scratch, only the RPL flags and the trace
+// are kept. On errors the old one keeps running so the source can be fixed
+fn reload(program: &mut Program, args: &Args, chip: &mut Chip) -> Option<Chip> {
+    let rom = program
+        .load()
+        .map_err(|error| eprintln!("Error reloading {}: {error}", program.path()))
+        .ok()?;
+
+    let mut reloaded = new_chip(args, args.seed);
+    if let Err(error) = reloaded.load_rom_bytes(&rom) {
+        eprintln!("Error reloading {}: {error}", program.path());
+        return None;
+    }
+    reloaded.set_rpl_flags(*chip.rpl_flags());
+    if let Some(hook) = chip.take_trace_hook() {
+        reloaded.set_trace_hook(hook);
+    }
+
+    println!("Reloaded {}", program.path());
+
+    Some(reloaded)
+}
+
+// NOTE: Tracing stops at the first error writing the file, the rom keeps
+// running
+fn trace_hook(path: &str) -> std::io::Result<TraceHook> {
+    let mut writer = Some(BufWriter::new(File::create(path)?));
+
+    Ok(Box::new(move |entry: &TraceEntry| {
+        if let Some(file) = &mut writer {
+            if let Err(error) = writeln!(file, "{entry}") {
+                eprintln!("Error writing the trace: {error}");
+                writer = None;
+            }
+        }
+    }))
+}
+
+// NOTE: Returns false when there is nothing left to rewind. The keys held
+// right now stay held, not the ones held in the frame that is restored
+fn rewind_frame(chip: &mut Chip, rewind: &mut Rewind) -> bool {
+    let Some(state) = rewind.pop() else {
+        return false;
+    };
+
+    let keys = chip.pressed_keys();
+    if let Err(error) = chip.load_state(&state) {
+        eprintln!("Error rewinding: {error}");
+        return false;
+    }
+    for (digit, pressed) in (0..).zip(keys) {
+        let key = Key::from_digit(digit).expect("One key per digit");
+        if pressed {
+            chip.press_key(key);
+        } else {
+            chip.unpress_key(key);
+        }
+    }
+
+    true
+}
+
+// NOTE: Returns whether the screen changed, or None when the rom stopped
+fn emulate_frame(
+    chip: &mut Chip,
+    cycles: u32,
+    cycles_left: &mut u32,
+    debugger: &mut Debugger,
+    rewind: &mut Rewind,
+    movie: Option<&mut MovieSession>,
+) -> Option<bool> {
+    let paused = debugger.is_paused();
+    let result = if debugger.is_running() {
+        run_frame(chip, cycles, cycles_left, movie)
+    } else {
+        debugger.run(chip, cycles)
+    };
+
+    let actions = match result {
+        Ok(actions) => actions,
+        Err(error) => {
+            show_error(&error);
+            return None;
+        }
+    };
+
+    if actions.exit {
+        return None;
+    }
+
+    if let Some(reason) = actions.break_reason {
+        println!("{reason} at {:#05X}", chip.registers().pc);
+        debugger.pause(reason);
+    }
+
+    if !paused {
+        rewind.push(chip.save_state());
+    }
+
+    Some(actions.draw)
+}
+
+// NOTE: Actions of every cycle in the frame combined. A frame interrupted by
+// the debugger keeps its cycles left, they run when it continues so every
+// frame runs the same number of instructions before the timers tick
+fn run_frame(
+    chip: &mut Chip,
+    cycles: u32,
+    cycles_left: &mut u32,
+    mut movie: Option<&mut MovieSession>,
+) -> Result<Actions, ChipError> {
+    if *cycles_left == 0 {
+        *cycles_left = cycles;
+        if let Some(movie) = &movie {
+            movie.start_frame(chip);
+        }
+    }
+
+    let mut frame = Actions {
+        draw: false,
+        exit: false,
+        break_reason: None,
+    };
+
+    while *cycles_left > 0 {
+        let actions = chip.run_cycle()?;
+        *cycles_left -= 1;
+
+        frame.draw |= actions.draw;
+
+        if actions.exit {
+            frame.exit = true;
+            return Ok(frame);
+        }
+
+        // NOTE: A break on the last cycle still ends the frame
+        if actions.break_reason.is_some() {
+            frame.break_reason = actions.break_reason;
+            if *cycles_left > 0 {
+                return Ok(frame);
+            }
+        }
+    }
+
+    chip.tick_timers();
+    if let Some(movie) = &mut movie {
+        movie.end_frame();
+    }
+
+    Ok(frame)
+}
+
+// NOTE: Returns false when the window is closed
+#[allow(clippy::too_many_arguments)]
+fn handle_events(
+    events: &mut EventPump,
+    chip: &mut Chip,
+    display: &mut Display,
+    save_states: &mut SaveStates,
+    capture: &mut Capture,
+    debugger: &mut Debugger,
+    movie: &mut Option<MovieSession>,
+    rewinding: &mut bool,
+) -> bool {
+    for event in events.poll_iter() {
+        if movie.is_some() && breaks_movie(&event) {
+            println!("Rewinding, loading states and stepping are disabled in movies");
+            continue;
+        }
+
+        match event {
+            Event::Quit { .. } => return false,
+            Event::KeyDown {
+                scancode: Some(Scancode::Backspace),
+                ..
+            } => *rewinding = true,
+            Event::KeyUp {
+                scancode: Some(Scancode::Backspace),
+                ..
+            } => *rewinding = false,
+            Event::KeyDown {
+                scancode,
+                keymod,
+                repeat,
+                ..
+            } => {
+                if let Some(key) = get_key(scancode) {
+                    set_key(chip, movie, key, true);
+                } else if handle_debugger_key(scancode, chip, debugger) {
+                    // NOTE: Held keys repeat, to step through many instructions
+                } else if !repeat {
+                    let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    handle_capture_key(scancode, shift, chip, capture);
+                    handle_hotkey(scancode, chip, display, save_states);
+                }
+            }
+            Event::KeyUp { scancode, .. } => {
+                if let Some(key) = get_key(scancode) {
+                    set_key(chip, movie, key, false);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    true
+}
+
+// NOTE: Movies only reproduce when the frames run one after the other
+const fn breaks_movie(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::KeyDown {
+            scancode: Some(Scancode::Backspace | Scancode::F9 | Scancode::F10 | Scancode::F11),
+            ..
+        }
+    )
+}
+
+fn set_key(chip: &mut Chip, movie: &mut Option<MovieSession>, key: Key, pressed: bool) {
+    match movie {
+        Some(movie) => movie.set_key(chip, key, pressed),
+        None if pressed => chip.press_key(key),
+        None => chip.unpress_key(key),
+    }
+}
+
+fn handle_debugger_key(scancode: Option<Scancode>, chip: &Chip, debugger: &mut Debugger) -> bool {
+    match scancode {
+        Some(Scancode::F1) => debugger.toggle(),
+        Some(Scancode::F8) => debugger.toggle_pause(),
+        Some(Scancode::F10) => debugger.step_over(chip),
+        Some(Scancode::F11) => debugger.step(),
+        _ => return false,
+    }
+
+    true
+}
+
+fn handle_hotkey(
+    scancode: Option<Scancode>,
+    chip: &mut Chip,
+    display: &mut Display,
+    save_states: &mut SaveStates,
+) {
+    match scancode {
+        Some(Scancode::F5) => match save_states.save(chip) {
+            Ok(()) => println!("Saved state to slot {}", save_states.slot()),
+            Err(error) => eprintln!("Error saving state: {error}"),
+        },
+        Some(Scancode::F9) => match save_states.load(chip) {
+            Ok(()) => {
+                println!("Loaded state from slot {}", save_states.slot());
+                display
+                    .draw(chip.screen())
+                    .expect("Error drawing to screen");
+            }
+            Err(error) => eprintln!("Error loading state: {error}"),
+        },
+        Some(Scancode::F6) => {
+            save_states.previous_slot();
+            display.show_slot(save_states.slot());
+        }
+        Some(Scancode::F7) => {
+            save_states.next_slot();
+            display.show_slot(save_states.slot());
+        }
+        _ => (),
+    }
+}
+
+// NOTE: Shift saves a PBM screenshot instead of a PNG
+fn handle_capture_key(scancode: Option<Scancode>, shift: bool, chip: &Chip, capture: &mut Capture) {
+    match scancode {
+        Some(Scancode::F12) => {
+            let format = if shift { Format::Pbm } else { Format::Png };
+            match capture.screenshot(chip.screen(), format) {
+                Ok(path) => println!("Saved screenshot to {path}"),
+                Err(error) => eprintln!("Error saving screenshot: {error}"),
+            }
+        }
+        Some(Scancode::F3) if capture.is_recording() => stop_recording(capture),
+        Some(Scancode::F3) => {
+            capture.start_recording(chip.screen());
+            println!("Recording started");
+        }
+        _ => (),
+    }
+}
+
+fn stop_recording(capture: &mut Capture) {
+    match capture.stop_recording() {
+        Ok(Some(path)) => println!("Saved recording to {path}"),
+        Ok(None) => (),
+        Err(error) => eprintln!("Error saving recording: {error}"),
+    }
+}
+
+fn finish_movie(movie: Option<MovieSession>) {
+    match movie.map(MovieSession::finish) {
+        Some(Ok(message)) => println!("{message}"),
+        Some(Err(error)) => eprintln!("Error saving movie: {error}"),
+        None => (),
+    }
+}
+
+// NOTE: RPL flags persist between runs of the same rom, like on the HP48
+fn load_rpl_flags(path: &str) -> Option<[u8; RPL_FLAGS_COUNT]> {
+    std::fs::read(path).ok()?.try_into().ok()
+}
+
+fn save_rpl_flags(path: &str, flags: &[u8; RPL_FLAGS_COUNT]) {
+    if let Err(error) = std::fs::write(path, flags) {
+        eprintln!("Error saving RPL flags: {error}");
+    }
+}
+
+fn show_error(error: &dyn Error) {
+    eprintln!("{error}");
+
+    // NOTE: The message box is best effort, the error is already on stderr
+    let _ = show_simple_message_box(MessageBoxFlag::ERROR, "chippy", &error.to_string(), None);
+}
+
 const fn get_key(scancode: Option<Scancode>) -> Option<Key> {
     match scancode {
         Some(Scancode::Num1) => Some(Key::Key1),
@@ -92,3 +555,84 @@ const fn get_key(scancode: Option<Scancode>) -> Option<Key> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip::{Breakpoint, Movie, Quirks};
+
+    // LD V0, 0x3C - LD DT, V0 - ADD V1, 0x01 - JP 0x204
+    const ROM: [u8; 8] = [0x60, 0x3C, 0xF0, 0x15, 0x71, 0x01, 0x12, 0x04];
+
+    #[test]
+    fn test_interrupted_frame() {
+        let mut chip = Chip::new(DEFAULT_ROM_START, Quirks::default());
+        chip.load_rom_bytes(&ROM).expect("Valid rom");
+        chip.add_breakpoint(Breakpoint::Address(0x202));
+        let mut cycles_left = 0;
+
+        let actions = run_frame(&mut chip, 10, &mut cycles_left, None).expect("Valid rom");
+        assert!(actions.break_reason.is_some());
+        assert_eq!(cycles_left, 9);
+
+        let actions = run_frame(&mut chip, 10, &mut cycles_left, None).expect("Valid rom");
+        assert!(actions.break_reason.is_none());
+        assert_eq!(cycles_left, 0);
+        assert_eq!(chip.registers().v[1], 4);
+        assert_eq!(chip.registers().delay_timer, 0x3B);
+    }
+
+    #[test]
+    fn test_replay_interrupted_frame() {
+        let path = std::env::temp_dir()
+            .join(format!("chippy-interrupted-{}.txt", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+
+        let movie = Movie::new(&ROM, 1, Quirks::default(), 10);
+        let mut session = MovieSession::record(path.clone(), movie);
+        let mut chip = Chip::with_seed(DEFAULT_ROM_START, Quirks::default(), 1);
+        chip.load_rom_bytes(&ROM).expect("Valid rom");
+        chip.add_breakpoint(Breakpoint::Address(0x202));
+        let mut cycles_left = 0;
+
+        for _ in 0..3 {
+            run_frame(&mut chip, 10, &mut cycles_left, Some(&mut session)).expect("Valid rom");
+        }
+        session.finish().expect("Movie written");
+
+        let (mut session, mut replayed) = MovieSession::play(&path, &ROM).expect("Valid movie");
+        while !session.is_finished() {
+            run_frame(&mut replayed, 10, &mut cycles_left, Some(&mut session)).expect("Valid rom");
+        }
+        assert_eq!(replayed.registers(), chip.registers());
+
+        std::fs::remove_file(path).expect("Movie written");
+    }
+
+    #[test]
+    fn test_rewind_keeps_keys() {
+        let mut chip = Chip::new(DEFAULT_ROM_START, Quirks::default());
+        chip.load_rom_bytes(&ROM).expect("Valid rom");
+        let mut rewind = Rewind::new(4);
+
+        chip.press_key(Key::Key1);
+        rewind.push(chip.save_state());
+        chip.run_cycle().expect("Valid rom");
+        chip.unpress_key(Key::Key1);
+        chip.press_key(Key::Key2);
+        rewind.push(chip.save_state());
+
+        assert!(rewind_frame(&mut chip, &mut rewind));
+        assert_eq!(chip.registers().pc, DEFAULT_ROM_START);
+        assert!(!chip.pressed_keys()[0x1]);
+        assert!(chip.pressed_keys()[0x2]);
+
+        let mut invalid = chip.save_state();
+        invalid[0] ^= 0xFF;
+        rewind.push(invalid);
+        rewind.push(chip.save_state());
+        assert!(!rewind_frame(&mut chip, &mut rewind));
+        assert_eq!(chip.registers().pc, DEFAULT_ROM_START);
+    }
+}
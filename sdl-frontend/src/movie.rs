@@ -0,0 +1,145 @@
+use std::error::Error;
+
+use chip::{Chip, Key, Movie};
+
+enum Mode {
+    Recording { path: String },
+    Playing,
+}
+
+// NOTE: Frames are counted as they run, so pausing the rom doesn't move the
+// keys of the movie. Keys pressed while paused go to the next frame
+pub struct MovieSession {
+    movie: Movie,
+    mode: Mode,
+    frame: u64,
+}
+
+impl MovieSession {
+    pub const fn record(path: String, movie: Movie) -> Self {
+        Self {
+            movie,
+            mode: Mode::Recording { path },
+            frame: 0,
+        }
+    }
+
+    // NOTE: The chip is created from the movie, which only plays with the
+    // rom it was recorded with
+    pub fn play(path: &str, rom: &[u8]) -> Result<(Self, Chip), Box<dyn Error>> {
+        let movie: Movie = std::fs::read_to_string(path)?.parse()?;
+        let chip = movie.chip(rom)?;
+
+        let session = Self {
+            movie,
+            mode: Mode::Playing,
+            frame: 0,
+        };
+
+        Ok((session, chip))
+    }
+
+    pub const fn cycles_per_frame(&self) -> u32 {
+        self.movie.cycles_per_frame
+    }
+
+    // NOTE: The keypad is ignored while playing
+    pub fn set_key(&mut self, chip: &mut Chip, key: Key, pressed: bool) {
+        if matches!(self.mode, Mode::Playing) {
+            return;
+        }
+
+        self.movie.record(self.frame, key, pressed);
+        if pressed {
+            chip.press_key(key);
+        } else {
+            chip.unpress_key(key);
+        }
+    }
+
+    // NOTE: Must be called around every frame that runs, the keys of the
+    // movie are pressed before it
+    pub fn start_frame(&self, chip: &mut Chip) {
+        if matches!(self.mode, Mode::Playing) {
+            self.movie.apply(chip, self.frame);
+        }
+    }
+
+    pub const fn end_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub const fn is_finished(&self) -> bool {
+        matches!(self.mode, Mode::Playing) && self.frame >= self.movie.frames
+    }
+
+    // NOTE: Writes the movie if it was being recorded, returns what happened
+    // for the user
+    pub fn finish(mut self) -> Result<String, Box<dyn Error>> {
+        match self.mode {
+            Mode::Recording { path } => {
+                self.movie.frames = self.frame;
+                std::fs::write(&path, self.movie.to_string())?;
+                Ok(format!("Saved movie to {path}"))
+            }
+            Mode::Playing => Ok(format!("Movie finished at frame {}", self.frame)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip::{Quirks, DEFAULT_ROM_START};
+
+    // LD V0, K - ADD V1, V0 - JP 0x200
+    const ROM: [u8; 6] = [0xF0, 0x0A, 0x81, 0x04, 0x12, 0x00];
+
+    fn run_frames(session: &mut MovieSession, chip: &mut Chip, frames: u64) {
+        for _ in 0..frames {
+            session.start_frame(chip);
+            for _ in 0..session.cycles_per_frame() {
+                chip.run_cycle().expect("Valid rom");
+            }
+            chip.tick_timers();
+            session.end_frame();
+        }
+    }
+
+    #[test]
+    fn test_record_and_play() {
+        let path = std::env::temp_dir()
+            .join(format!("chippy-movie-{}.txt", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+
+        let movie = Movie::new(&ROM, 1, Quirks::default(), 10);
+        let mut session = MovieSession::record(path.clone(), movie);
+        let mut chip = Chip::with_seed(DEFAULT_ROM_START, Quirks::default(), 1);
+        chip.load_rom_bytes(&ROM).expect("Valid rom");
+
+        run_frames(&mut session, &mut chip, 2);
+        session.set_key(&mut chip, Key::Key3, true);
+        run_frames(&mut session, &mut chip, 1);
+        session.set_key(&mut chip, Key::Key3, false);
+        session.set_key(&mut chip, Key::Key4, true);
+        run_frames(&mut session, &mut chip, 3);
+        assert!(!session.is_finished());
+        assert_eq!(
+            session.finish().expect("Movie written"),
+            format!("Saved movie to {path}")
+        );
+
+        let (mut session, mut replayed) = MovieSession::play(&path, &ROM).expect("Valid movie");
+        session.set_key(&mut replayed, Key::Key5, true);
+        assert!(!replayed.pressed_keys()[0x5]);
+        run_frames(&mut session, &mut replayed, 6);
+        assert!(session.is_finished());
+        assert_eq!(replayed.registers(), chip.registers());
+        assert_eq!(chip.registers().v[1], 0x7);
+
+        assert!(MovieSession::play(&path, &ROM[..4]).is_err());
+
+        std::fs::remove_file(path).expect("Movie written");
+    }
+}
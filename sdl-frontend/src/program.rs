@@ -0,0 +1,50 @@
+use std::error::Error;
+use std::path::Path;
+use std::time::SystemTime;
+
+const OCTO_EXTENSION: &str = "8o";
+
+// Rom being run, sources in Octo are compiled when loaded and reloaded
+// whenever the file changes
+pub struct Program {
+    path: String,
+    modified: Option<SystemTime>,
+}
+
+impl Program {
+    pub const fn new(path: String) -> Self {
+        Self {
+            path,
+            modified: None,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn is_source(&self) -> bool {
+        Path::new(&self.path)
+            .extension()
+            .is_some_and(|extension| extension == OCTO_EXTENSION)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path).ok()?.modified().ok()
+    }
+
+    pub fn load(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.modified = self.modified();
+
+        if self.is_source() {
+            Ok(chip::octo::compile_file(&self.path)?)
+        } else {
+            Ok(std::fs::read(&self.path)?)
+        }
+    }
+
+    // NOTE: Only sources are watched, binary roms are not edited while running
+    pub fn changed(&self) -> bool {
+        self.is_source() && self.modified() != self.modified
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+
+// NOTE: Differences shorter than this are merged into a single run
+const MERGE_DISTANCE: usize = 8;
+
+// Bytes of the older state at the offsets where it differs from the newer one
+struct Delta {
+    runs: Vec<(usize, Vec<u8>)>,
+}
+
+impl Delta {
+    fn between(newer: &[u8], older: &[u8]) -> Self {
+        let mut runs: Vec<(usize, Vec<u8>)> = Vec::new();
+
+        for (offset, (new, old)) in newer.iter().zip(older).enumerate() {
+            if new == old {
+                continue;
+            }
+
+            match runs.last_mut() {
+                Some((start, bytes)) if offset - (*start + bytes.len()) < MERGE_DISTANCE => {
+                    let from = *start + bytes.len();
+                    bytes.extend_from_slice(&older[from..=offset]);
+                }
+                _ => runs.push((offset, vec![*old])),
+            }
+        }
+
+        Self { runs }
+    }
+
+    fn apply(&self, state: &mut [u8]) {
+        for (offset, bytes) in &self.runs {
+            state[*offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+    }
+}
+
+// Ring buffer of machine states, only the newest one is kept in full and
+// every older one is stored as the difference with the one after it
+pub struct Rewind {
+    current: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+    capacity: usize,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            current: None,
+            deltas: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if let Some(previous) = self.current.take() {
+            // NOTE: States of different sizes can not be diffed, history is lost
+            if previous.len() == state.len() {
+                if self.deltas.len() == self.capacity {
+                    self.deltas.pop_front();
+                }
+                self.deltas.push_back(Delta::between(&state, &previous));
+            } else {
+                self.deltas.clear();
+            }
+        }
+
+        self.current = Some(state);
+    }
+
+    // NOTE: Returns the state before the current one, which becomes the current
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        let current = self.current.as_mut()?;
+
+        delta.apply(current);
+
+        Some(current.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewind_order() {
+        let mut rewind = Rewind::new(10);
+        rewind.push(vec![0; 32]);
+        rewind.push(vec![1; 32]);
+        let mut state = vec![1; 32];
+        state[5] = 2;
+        state[20] = 2;
+        rewind.push(state);
+
+        assert_eq!(rewind.pop(), Some(vec![1; 32]));
+        assert_eq!(rewind.pop(), Some(vec![0; 32]));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn test_rewind_capacity() {
+        let mut rewind = Rewind::new(2);
+        for value in 0..5 {
+            rewind.push(vec![value; 4]);
+        }
+
+        assert_eq!(rewind.pop(), Some(vec![3; 4]));
+        assert_eq!(rewind.pop(), Some(vec![2; 4]));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn test_push_after_pop() {
+        let mut rewind = Rewind::new(10);
+        rewind.push(vec![0; 4]);
+        rewind.push(vec![1; 4]);
+        rewind.push(vec![2; 4]);
+
+        rewind.pop();
+        rewind.push(vec![3; 4]);
+
+        assert_eq!(rewind.pop(), Some(vec![1; 4]));
+        assert_eq!(rewind.pop(), Some(vec![0; 4]));
+    }
+
+    #[test]
+    fn test_delta_runs() {
+        let older = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut newer = older;
+        newer[1] = 1;
+        newer[3] = 1;
+        newer[19] = 1;
+
+        let delta = Delta::between(&newer, &older);
+
+        assert_eq!(delta.runs.len(), 2);
+
+        delta.apply(&mut newer);
+        assert_eq!(newer, older);
+    }
+}
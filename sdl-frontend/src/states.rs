@@ -0,0 +1,49 @@
+use std::error::Error;
+
+use chip::Chip;
+
+const SLOTS_COUNT: u8 = 10;
+
+// NOTE: Save states are stored next to the rom, one file per slot
+pub struct SaveStates {
+    rom_path: String,
+    slot: u8,
+}
+
+impl SaveStates {
+    pub fn new(rom_path: &str) -> Self {
+        Self {
+            rom_path: rom_path.to_owned(),
+            slot: 0,
+        }
+    }
+
+    pub const fn slot(&self) -> u8 {
+        self.slot
+    }
+
+    pub const fn next_slot(&mut self) {
+        self.slot = (self.slot + 1) % SLOTS_COUNT;
+    }
+
+    pub const fn previous_slot(&mut self) {
+        self.slot = (self.slot + SLOTS_COUNT - 1) % SLOTS_COUNT;
+    }
+
+    pub fn save(&self, chip: &Chip) -> Result<(), Box<dyn Error>> {
+        std::fs::write(self.path(), chip.save_state())?;
+
+        Ok(())
+    }
+
+    pub fn load(&self, chip: &mut Chip) -> Result<(), Box<dyn Error>> {
+        let state = std::fs::read(self.path())?;
+        chip.load_state(&state)?;
+
+        Ok(())
+    }
+
+    fn path(&self) -> String {
+        format!("{}.state{}", self.rom_path, self.slot)
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::BufRead;
+
+use chip::TraceEntry;
+
+// Parsed line of a trace and its number in the file
+pub struct Line {
+    pub number: usize,
+    pub text: String,
+    pub entry: TraceEntry,
+}
+
+pub struct Divergence {
+    // NOTE: Lines of the first trace right before the divergence
+    pub context: Vec<String>,
+    pub left: Line,
+    pub right: Line,
+    pub fields: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+pub enum Outcome {
+    // NOTE: Number of instructions compared, both traces end after them
+    Same(usize),
+    Diverged(Divergence),
+    // NOTE: The traces are the same until one of them ends, the line is the
+    // first one of the other trace that has no counterpart
+    Ended {
+        compared: usize,
+        side: Side,
+        line: Line,
+    },
+}
+
+// Lines of a trace file read one at a time, traces can be huge
+struct Trace<R> {
+    name: String,
+    lines: std::io::Lines<R>,
+    number: usize,
+}
+
+impl<R: BufRead> Trace<R> {
+    fn new(name: &str, reader: R) -> Self {
+        Self {
+            name: name.to_owned(),
+            lines: reader.lines(),
+            number: 0,
+        }
+    }
+
+    // NOTE: Empty lines are skipped, anything else must be a trace line
+    fn next(&mut self) -> Result<Option<Line>, Box<dyn Error>> {
+        for text in self.lines.by_ref() {
+            let text = text?;
+            self.number += 1;
+
+            if text.trim().is_empty() {
+                continue;
+            }
+
+            let entry = text
+                .parse()
+                .map_err(|error| format!("{}:{}: {error}", self.name, self.number))?;
+
+            return Ok(Some(Line {
+                number: self.number,
+                text,
+                entry,
+            }));
+        }
+
+        Ok(None)
+    }
+}
+
+// NOTE: The traces are aligned on the first cycle both have, so one that
+// started later can be compared with a complete one. The timers depend on
+// the instructions run per frame, so comparing them is optional
+pub fn first_divergence(
+    left: (&str, impl BufRead),
+    right: (&str, impl BufRead),
+    context: usize,
+    timers: bool,
+) -> Result<Outcome, Box<dyn Error>> {
+    let mut left_trace = Trace::new(left.0, left.1);
+    let mut right_trace = Trace::new(right.0, right.1);
+
+    let (mut left, mut right) = (left_trace.next()?, right_trace.next()?);
+    while let (Some(left_line), Some(right_line)) = (&left, &right) {
+        match left_line.entry.cycle.cmp(&right_line.entry.cycle) {
+            std::cmp::Ordering::Less => left = left_trace.next()?,
+            std::cmp::Ordering::Greater => right = right_trace.next()?,
+            std::cmp::Ordering::Equal => break,
+        }
+    }
+
+    let mut previous = VecDeque::with_capacity(context);
+    let mut compared = 0;
+
+    loop {
+        let (left_line, right_line) = match (left, right) {
+            (Some(left_line), Some(right_line)) => (left_line, right_line),
+            (Some(line), None) => {
+                return Ok(Outcome::Ended {
+                    compared,
+                    side: Side::Left,
+                    line,
+                })
+            }
+            (None, Some(line)) => {
+                return Ok(Outcome::Ended {
+                    compared,
+                    side: Side::Right,
+                    line,
+                })
+            }
+            (None, None) => return Ok(Outcome::Same(compared)),
+        };
+
+        let fields = differences(&left_line.entry, &right_line.entry, timers);
+        if !fields.is_empty() {
+            return Ok(Outcome::Diverged(Divergence {
+                context: previous.into(),
+                left: left_line,
+                right: right_line,
+                fields,
+            }));
+        }
+
+        if context > 0 {
+            if previous.len() == context {
+                previous.pop_front();
+            }
+            previous.push_back(left_line.text);
+        }
+        compared += 1;
+
+        (left, right) = (left_trace.next()?, right_trace.next()?);
+    }
+}
+
+// NOTE: Names of the fields that are not the same in both entries
+fn differences(left: &TraceEntry, right: &TraceEntry, timers: bool) -> Vec<String> {
+    let (a, b) = (&left.registers, &right.registers);
+    let mut fields = Vec::new();
+
+    if a.pc != b.pc {
+        fields.push(String::from("PC"));
+    }
+    if left.opcode != right.opcode {
+        fields.push(String::from("opcode"));
+    }
+    for (register, (x, y)) in a.v.iter().zip(b.v).enumerate() {
+        if *x != y {
+            fields.push(format!("V{register:X}"));
+        }
+    }
+    if a.i != b.i {
+        fields.push(String::from("I"));
+    }
+    if a.sp != b.sp {
+        fields.push(String::from("SP"));
+    }
+    if timers && a.delay_timer != b.delay_timer {
+        fields.push(String::from("DT"));
+    }
+    if timers && a.sound_timer != b.sound_timer {
+        fields.push(String::from("ST"));
+    }
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE: &str = "\
+0000000000 0200 6012 LD V0, 0x12        V=00000000000000000000000000000000 I=0000 SP=00 DT=00 ST=00
+0000000001 0202 A2FF LD I, 0x2FF        V=12000000000000000000000000000000 I=0000 SP=00 DT=00 ST=00
+0000000002 0204 2208 CALL 0x208         V=12000000000000000000000000000000 I=02FF SP=00 DT=00 ST=00
+0000000003 0208 F015 LD DT, V0          V=12000000000000000000000000000000 I=02FF SP=01 DT=00 ST=00
+0000000004 020A 00EE RET                V=12000000000000000000000000000000 I=02FF SP=01 DT=12 ST=00
+";
+
+    fn compare(left: &str, right: &str, timers: bool) -> Outcome {
+        first_divergence(
+            ("left", left.as_bytes()),
+            ("right", right.as_bytes()),
+            2,
+            timers,
+        )
+        .expect("Valid traces")
+    }
+
+    #[test]
+    fn test_same() {
+        assert!(matches!(compare(TRACE, TRACE, true), Outcome::Same(5)));
+    }
+
+    #[test]
+    fn test_ended() {
+        let shorter = TRACE.lines().take(3).collect::<Vec<_>>().join("\n");
+
+        let Outcome::Ended {
+            compared,
+            side,
+            line,
+        } = compare(TRACE, &shorter, true)
+        else {
+            panic!("The right trace ends first");
+        };
+        assert_eq!(compared, 3);
+        assert_eq!(side, Side::Left);
+        assert_eq!(line.number, 4);
+
+        assert!(matches!(
+            compare(&shorter, TRACE, true),
+            Outcome::Ended {
+                compared: 3,
+                side: Side::Right,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_aligns_cycles() {
+        let later = TRACE.lines().skip(2).collect::<Vec<_>>().join("\n\n");
+
+        assert!(matches!(compare(TRACE, &later, true), Outcome::Same(3)));
+        assert!(matches!(compare(&later, TRACE, true), Outcome::Same(3)));
+    }
+
+    #[test]
+    fn test_divergence() {
+        let changed = TRACE.replacen("I=02FF SP=01 DT=00", "I=0300 SP=01 DT=00", 1);
+
+        let Outcome::Diverged(divergence) = compare(TRACE, &changed, true) else {
+            panic!("The traces diverge");
+        };
+        assert_eq!(divergence.left.entry.cycle, 3);
+        assert_eq!(divergence.right.number, 4);
+        assert_eq!(divergence.fields, ["I"]);
+        assert_eq!(divergence.context.len(), 2);
+        assert!(divergence.context[1].starts_with("0000000002"));
+    }
+
+    #[test]
+    fn test_timers() {
+        let changed = TRACE.replace("DT=12", "DT=11");
+
+        assert!(matches!(compare(TRACE, &changed, false), Outcome::Same(5)));
+        assert!(matches!(
+            compare(TRACE, &changed, true),
+            Outcome::Diverged(Divergence { fields, .. }) if fields == ["DT"]
+        ));
+    }
+
+    #[test]
+    fn test_invalid_line() {
+        let invalid = TRACE.replacen("0000000001", "not a trace\n0000000001", 1);
+
+        let error = first_divergence(
+            ("left", TRACE.as_bytes()),
+            ("b.log", invalid.as_bytes()),
+            2,
+            true,
+        )
+        .err()
+        .expect("Invalid trace");
+        assert!(error.to_string().starts_with("b.log:2: "));
+    }
+}